@@ -1,6 +1,8 @@
+mod cfg;
 mod repl;
 mod rpc;
 
+pub use cfg::CfgExport;
 pub use repl::REPL;
 pub use rpc::Rpc;
 
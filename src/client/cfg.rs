@@ -0,0 +1,42 @@
+use std::{fs, path::Path};
+
+use super::Client;
+use crate::{
+    eth_simulator,
+    utils::{path, solc},
+};
+
+/// Print the control-flow graph of the runtime code of a contract file
+pub struct CfgExport {
+    file: String,
+}
+
+impl Client for CfgExport {
+    fn run(&mut self) {
+        let file = Path::new(&self.file);
+
+        let bytecode = if file.extension().is_some_and(|ext| ext == "sol") {
+            solc::compile(file)
+                .map(|contract| contract.bin_runtime)
+                .ok()
+        } else {
+            fs::read_to_string(file).ok()
+        };
+
+        match bytecode {
+            Some(bytecode) => print!(
+                "{}",
+                eth_simulator::bytecode_cfg(path::get_file_name(file), &bytecode)
+            ),
+            None => println!("failed to read or compile the file, check code or path of contract"),
+        }
+    }
+}
+
+impl CfgExport {
+    pub fn new(file: &str) -> Self {
+        CfgExport {
+            file: file.to_string(),
+        }
+    }
+}
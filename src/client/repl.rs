@@ -1,30 +1,64 @@
 use easy_repl::{command, CommandStatus, Repl};
+use ethereum_types::U256;
 use serde::Deserialize;
 use serde_json;
 
-use std::{fs::File, io::BufReader, rc::Rc, sync::Mutex};
+use std::{
+    cell::Cell,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Mutex,
+};
 
 use super::Client;
 use crate::{
-    eth_api::{EthApi, EthError, EthResult},
+    eth_api::{EthApi, EthError, EthResult, TxOptions},
     eth_simulator::EthSimulator,
+    utils::units::{self, Unit},
 };
 
 pub struct REPL<'a> {
     repl: Repl<'a>,
+    eth_simulator: Rc<Mutex<EthSimulator>>,
+    /// File the state is saved to on exit
+    state_file: Option<PathBuf>,
 }
 
 impl Client for REPL<'_> {
     fn run(&mut self) {
         self.repl.run().expect("Critical REPL error");
+
+        if let Some(state_file) = &self.state_file {
+            let eth_simulator = self.eth_simulator.lock().unwrap();
+            Self::save(&eth_simulator, state_file);
+        }
     }
 }
 
 impl<'a> REPL<'a> {
-    pub fn new() -> Self {
-        let eth_simulator = Rc::new(Mutex::new(EthSimulator::new()));
+    pub fn new(eth_simulator: EthSimulator, state_file: Option<PathBuf>) -> Self {
+        let eth_simulator = Rc::new(Mutex::new(eth_simulator));
+        // unit balances are printed in
+        let unit = Rc::new(Cell::new(Unit::Ether));
         let mut repl = Repl::builder();
 
+        let unit_clone = unit.clone();
+        repl = repl.add(
+            "unit",
+            command! {
+                "set unit of printed balances: wei, gwei or ether",
+                (name: String) => |name: String| {
+                    match name.parse::<Unit>() {
+                        Ok(new_unit) => unit_clone.set(new_unit),
+                        Err(_) => println!("unknown unit, use wei, gwei or ether"),
+                    }
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
         let mut eth_simulator_clone = eth_simulator.clone();
         repl = repl.add(
             "account_add",
@@ -39,26 +73,220 @@ impl<'a> REPL<'a> {
         );
 
         eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_import",
+            command! {
+                "add account of a private key",
+                (name: String, private_key: String) => |name, private_key| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::account_import(&mut eth_simulator, name, private_key);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_export_key",
+            command! {
+                "get private key of account",
+                (address: String) => |address| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::account_export_key(&eth_simulator, address);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        let unit_clone = unit.clone();
         repl = repl.add(
             "account_list",
             command! {
                 "list accounts",
                 () => || {
-                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
-                    Self::account_list(&*eth_simulator);
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::account_list(&eth_simulator, unit_clone.get());
                     Ok(CommandStatus::Done)
                 }
             },
         );
 
         eth_simulator_clone = eth_simulator.clone();
+        let unit_clone = unit.clone();
         repl = repl.add(
             "account_balance",
             command! {
                 "get account balance",
                 (address: String) => |address| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::account_balance(&eth_simulator, address, unit_clone.get());
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_nonce",
+            command! {
+                "get account nonce",
+                (address: String) => |address| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::account_nonce(&eth_simulator, address);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_rename",
+            command! {
+                "give an account a new name",
+                (address: String, name: String) => |address: String, name: String| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.account_rename(&address, &name));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "alias",
+            command! {
+                "name an address which need not have an account or key",
+                (name: String, address: String) => |name: String, address: String| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.alias_add(&name, &address));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "unalias",
+            command! {
+                "remove an alias",
+                (name: String) => |name: String| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.alias_remove(&name));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "alias_list",
+            command! {
+                "list the aliases",
+                () => || {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.alias_list());
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_storage",
+            command! {
+                "list the non-zero storage slots of an account",
+                (address: String) => |address: String| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.account_storage(&address));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_set_balance",
+            command! {
+                "set the balance of an account, creating it if missing",
+                (address: String, balance: String) => |address: String, balance: String| {
                     let mut eth_simulator = eth_simulator_clone.lock().unwrap();
-                    Self::account_balance(&*eth_simulator, address);
+                    match units::parse_amount(&balance) {
+                        Some(balance) => Self::handle_eth_result(
+                            eth_simulator.account_set_balance(&address, balance),
+                        ),
+                        None => println!("wrong balance, use an amount like 100, 0x64, 20gwei or 1.5ether"),
+                    }
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_set_nonce",
+            command! {
+                "set the nonce of an account, creating it if missing",
+                (address: String, nonce: usize) => |address: String, nonce| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.account_set_nonce(&address, nonce));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_set_code",
+            command! {
+                "set the code of an account in hex, creating it if missing",
+                (address: String, code: String) => |address: String, code: String| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.account_set_code(&address, &code));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "account_set_storage",
+            command! {
+                "set a storage slot of an account, creating it if missing",
+                (address: String, slot: String, value: String) => |address: String, slot: String, value: String| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    match (units::parse_amount(&slot), units::parse_amount(&value)) {
+                        (Some(slot), Some(value)) => Self::handle_eth_result(
+                            eth_simulator.account_set_storage(&address, slot, value),
+                        ),
+                        _ => println!("wrong slot or value, use numbers like 1 or 0x01"),
+                    }
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "impersonate",
+            command! {
+                "send transactions from an address without its private key",
+                (address: String) => |address: String| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.account_impersonate(&address));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "stop_impersonating",
+            command! {
+                "stop sending transactions from an address without its private key",
+                (address: String) => |address: String| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.account_stop_impersonating(&address));
                     Ok(CommandStatus::Done)
                 }
             },
@@ -90,6 +318,34 @@ impl<'a> REPL<'a> {
             },
         );
 
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "tx_send_gas",
+            command! {
+                "send transaction with a gas limit and a gas price like 20gwei",
+                (from: String, to: String, value: String, data: String, gas: usize, gas_price: String)
+                    => |from, to, value, data, gas, gas_price| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::tx_send_gas(&mut *eth_simulator, from, to, value, data, gas, gas_price);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "tx_send_dynamic",
+            command! {
+                "send an EIP-1559 transaction with a max fee and a max priority fee per gas like 20gwei",
+                (from: String, to: String, value: String, data: String, max_fee: String, max_priority_fee: String)
+                    => |from, to, value, data, max_fee, max_priority_fee| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::tx_send_dynamic(&mut *eth_simulator, from, to, value, data, max_fee, max_priority_fee);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
         eth_simulator_clone = eth_simulator.clone();
         repl = repl.add(
             "contract_deploy",
@@ -116,8 +372,239 @@ impl<'a> REPL<'a> {
             },
         );
 
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "contract_cfg",
+            command! {
+                "print control-flow graph of contract in DOT format",
+                (contract: String) => |contract| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::contract_cfg(&eth_simulator, contract);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "contract_storage",
+            command! {
+                "print the state variables of a deployed contract decoded with its storage layout",
+                (contract: String) => |contract: String| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.contract_storage(&contract));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "coverage_start",
+            command! {
+                "start recording coverage of contracts",
+                () => || {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::coverage_start(&mut eth_simulator);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "coverage_report",
+            command! {
+                "write coverage of contracts since coverage_start to file in LCOV format",
+                (file: String) => |file| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::coverage_report(&eth_simulator, file);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "state_root",
+            command! {
+                "print the state root after a block, or of the current state with latest",
+                (block: String) => |block| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::state_root(&eth_simulator, block);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "tx_list",
+            command! {
+                "list the mined transactions with their hashes",
+                () => || {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.tx_list());
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "tx_receipt",
+            command! {
+                "print the status, gas used and fee of a mined transaction",
+                (tx_hash: String) => |tx_hash: String| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.tx_receipt(&tx_hash));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "tx_logs",
+            command! {
+                "print the logs of a mined transaction",
+                (tx_hash: String) => |tx_hash: String| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.tx_logs(&tx_hash));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "tx_diff",
+            command! {
+                "print the accounts a mined transaction changed, before and after",
+                (tx_hash: String) => |tx_hash: String| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::handle_eth_result(eth_simulator.tx_state_diff(&tx_hash));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "proof",
+            command! {
+                "print the Merkle proof of an account and storage slots (comma separated, - for none) after a block, or of the current state with latest",
+                (address: String, block: String, slots: String) => |address, block, slots| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::account_proof(&eth_simulator, address, block, slots);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "snapshot",
+            command! {
+                "record the current state and print the id to revert to",
+                () => || {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    println!("snapshot: {}", eth_simulator.snapshot());
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "revert",
+            command! {
+                "revert to a snapshot, dropping it and all later snapshots",
+                (id: usize) => |id| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    if !eth_simulator.revert(id) {
+                        println!("snapshot does not exist");
+                    }
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "save",
+            command! {
+                "save the whole state to a file",
+                (file: PathBuf) => |file: PathBuf| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::save(&eth_simulator, &file);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "load",
+            command! {
+                "replace the whole state with one saved to a file",
+                (file: PathBuf) => |file: PathBuf| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    if let Err(err) = eth_simulator.load(&file) {
+                        println!("loading state from {} failed: {}", file.display(), err);
+                    }
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "dump",
+            command! {
+                "write all accounts after a block, or the current ones with latest, to a file (- to print) in the dump format of geth",
+                (block: String, file: String) => |block, file| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::state_dump(&eth_simulator, block, file);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "fee_history",
+            command! {
+                "print base fees, gas used ratios and priority fees at percentiles like 25,50,75 (- for none) of blocks up to a block or latest",
+                (block_count: usize, newest_block: String, percentiles: String)
+                    => |block_count, newest_block, percentiles| {
+                    let eth_simulator = eth_simulator_clone.lock().unwrap();
+                    Self::fee_history(&eth_simulator, block_count, newest_block, percentiles);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
+        eth_simulator_clone = eth_simulator.clone();
+        repl = repl.add(
+            "import",
+            command! {
+                "merge the accounts of a state dump in the format of geth into the state",
+                (file: PathBuf) => |file: PathBuf| {
+                    let mut eth_simulator = eth_simulator_clone.lock().unwrap();
+                    match eth_simulator.import_dump(&file) {
+                        Ok(count) => println!("{} accounts imported", count),
+                        Err(err) => println!("importing {} failed: {}", file.display(), err),
+                    }
+                    Ok(CommandStatus::Done)
+                }
+            },
+        );
+
         REPL {
             repl: repl.build().expect("Failed to create repl"),
+            eth_simulator,
+            state_file,
         }
     }
 
@@ -125,23 +612,78 @@ impl<'a> REPL<'a> {
         Self::handle_eth_result(eth_simulator.account_add(&name));
     }
 
-    fn account_list(eth_simulator: &EthSimulator) {
-        Self::handle_eth_result(eth_simulator.account_list());
+    fn account_import(eth_simulator: &mut EthSimulator, name: String, private_key: String) {
+        Self::handle_eth_result(eth_simulator.account_import(&name, &private_key));
+    }
+
+    fn account_export_key(eth_simulator: &EthSimulator, address: String) {
+        Self::handle_eth_result(eth_simulator.account_export_key(&address));
+    }
+
+    fn account_list(eth_simulator: &EthSimulator, unit: Unit) {
+        match eth_simulator.account_list() {
+            Ok(EthResult::AccountList(accounts)) => {
+                for account in accounts {
+                    println!(
+                        "name: {}, address: {}, balance: {}",
+                        account.name,
+                        account.address,
+                        units::format_amount(account.balance, unit)
+                    );
+                }
+            }
+            result => Self::handle_eth_result(result),
+        }
+    }
+
+    fn account_balance(eth_simulator: &EthSimulator, address: String, unit: Unit) {
+        match eth_simulator.account_balance(&address) {
+            Ok(EthResult::Balance(balance)) => {
+                println!("balance: {}", units::format_amount(balance, unit))
+            }
+            result => Self::handle_eth_result(result),
+        }
     }
 
-    fn account_balance(eth_simulator: &EthSimulator, address: String) {
-        Self::handle_eth_result(eth_simulator.account_balance(&address));
+    fn account_nonce(eth_simulator: &EthSimulator, address: String) {
+        Self::handle_eth_result(eth_simulator.account_nonce(&address));
     }
 
     fn tx_send_file(eth_simulator: &mut EthSimulator, params_file: String) {
         if let Ok(file) = File::open(params_file) {
             if let Ok(tx) = serde_json::from_reader::<BufReader<File>, Tx>(BufReader::new(file)) {
-                Self::handle_eth_result(eth_simulator.tx_send(
-                    &tx.from,
-                    &tx.to,
-                    tx.value.parse::<usize>().unwrap(),
-                    &tx.data,
-                ));
+                let fees = (
+                    Self::parse_fee(&tx.gas_price),
+                    Self::parse_fee(&tx.max_fee_per_gas),
+                    Self::parse_fee(&tx.max_priority_fee_per_gas),
+                );
+                let (gas_price, max_fee_per_gas, max_priority_fee_per_gas) = match fees {
+                    (Some(gas_price), Some(max_fee), Some(max_priority_fee)) => {
+                        (gas_price, max_fee, max_priority_fee)
+                    }
+                    _ => {
+                        println!("wrong fee, use an amount like 100, 0x64 or 20gwei");
+                        return;
+                    }
+                };
+                match units::parse_amount(&tx.value) {
+                    Some(value) => Self::handle_eth_result(eth_simulator.tx_send_with(
+                        &tx.from,
+                        &tx.to,
+                        value,
+                        &tx.data,
+                        TxOptions {
+                            nonce: tx.nonce.map(|nonce| nonce.parse::<usize>().unwrap()),
+                            gas: tx.gas,
+                            gas_price,
+                            max_fee_per_gas,
+                            max_priority_fee_per_gas,
+                        },
+                    )),
+                    None => {
+                        println!("wrong value, use an amount like 100, 0x64, 20gwei or 1.5ether")
+                    }
+                }
             } else {
                 println!("wrong file format, failed to deserialize file")
             }
@@ -157,12 +699,77 @@ impl<'a> REPL<'a> {
         value: String,
         data: String,
     ) {
-        Self::handle_eth_result(eth_simulator.tx_send(
-            &from,
-            &to,
-            value.parse::<usize>().unwrap(),
-            &data,
-        ));
+        match units::parse_amount(&value) {
+            Some(value) => {
+                Self::handle_eth_result(eth_simulator.tx_send(&from, &to, value, &data, None))
+            }
+            None => println!("wrong value, use an amount like 100, 0x64, 20gwei or 1.5ether"),
+        }
+    }
+
+    fn tx_send_gas(
+        eth_simulator: &mut EthSimulator,
+        from: String,
+        to: String,
+        value: String,
+        data: String,
+        gas: usize,
+        gas_price: String,
+    ) {
+        match (units::parse_amount(&value), units::parse_amount(&gas_price)) {
+            (Some(value), Some(gas_price)) => Self::handle_eth_result(eth_simulator.tx_send_with(
+                &from,
+                &to,
+                value,
+                &data,
+                TxOptions {
+                    gas: Some(gas),
+                    gas_price: Some(gas_price),
+                    ..TxOptions::default()
+                },
+            )),
+            _ => println!("wrong amount, use an amount like 100, 0x64, 20gwei or 1.5ether"),
+        }
+    }
+
+    /// Fee of a params file, `None` if it is given but not an amount
+    fn parse_fee(fee: &Option<String>) -> Option<Option<U256>> {
+        match fee {
+            Some(fee) => units::parse_amount(fee).map(Some),
+            None => Some(None),
+        }
+    }
+
+    fn tx_send_dynamic(
+        eth_simulator: &mut EthSimulator,
+        from: String,
+        to: String,
+        value: String,
+        data: String,
+        max_fee: String,
+        max_priority_fee: String,
+    ) {
+        let amounts = (
+            units::parse_amount(&value),
+            units::parse_amount(&max_fee),
+            units::parse_amount(&max_priority_fee),
+        );
+        match amounts {
+            (Some(value), Some(max_fee), Some(max_priority_fee)) => {
+                Self::handle_eth_result(eth_simulator.tx_send_with(
+                    &from,
+                    &to,
+                    value,
+                    &data,
+                    TxOptions {
+                        max_fee_per_gas: Some(max_fee),
+                        max_priority_fee_per_gas: Some(max_priority_fee),
+                        ..TxOptions::default()
+                    },
+                ))
+            }
+            _ => println!("wrong amount, use an amount like 100, 0x64, 20gwei or 1.5ether"),
+        }
     }
 
     fn contract_deploy(eth_simulator: &mut EthSimulator, from: String, contract_file: String) {
@@ -178,6 +785,124 @@ impl<'a> REPL<'a> {
         Self::handle_eth_result(eth_simulator.contract_call(&from, &contract, &input));
     }
 
+    fn contract_cfg(eth_simulator: &EthSimulator, contract: String) {
+        Self::handle_eth_result(eth_simulator.contract_cfg(&contract));
+    }
+
+    fn coverage_start(eth_simulator: &mut EthSimulator) {
+        Self::handle_eth_result(eth_simulator.coverage_start());
+    }
+
+    fn coverage_report(eth_simulator: &EthSimulator, file: String) {
+        match eth_simulator.coverage_report() {
+            Ok(EthResult::Lcov(lcov)) => {
+                if fs::write(&file, lcov).is_err() {
+                    println!("failed to write the file, check the path of file")
+                }
+            }
+            result => Self::handle_eth_result(result),
+        }
+    }
+
+    fn state_root(eth_simulator: &EthSimulator, block: String) {
+        match Self::parse_block(&block) {
+            Some(block) => Self::handle_eth_result(eth_simulator.state_root(block)),
+            None => println!("wrong block, use a block number or latest"),
+        }
+    }
+
+    fn account_proof(eth_simulator: &EthSimulator, address: String, block: String, slots: String) {
+        let block = match Self::parse_block(&block) {
+            Some(block) => block,
+            None => {
+                println!("wrong block, use a block number or latest");
+                return;
+            }
+        };
+        let slots: Option<Vec<U256>> = if slots == "-" {
+            Some(Vec::new())
+        } else {
+            slots.split(',').map(units::parse_amount).collect()
+        };
+        match slots {
+            Some(slots) => {
+                Self::handle_eth_result(eth_simulator.account_proof(&address, &slots, block))
+            }
+            None => println!("wrong slots, use numbers like 0,0x1 or - for none"),
+        }
+    }
+
+    fn state_dump(eth_simulator: &EthSimulator, block: String, file: String) {
+        let block = match Self::parse_block(&block) {
+            Some(block) => block,
+            None => {
+                println!("wrong block, use a block number or latest");
+                return;
+            }
+        };
+        match eth_simulator.state_dump(block) {
+            Ok(EthResult::Dump(dump)) if file != "-" => {
+                if fs::write(&file, dump).is_err() {
+                    println!("failed to write the file, check the path of file")
+                }
+            }
+            result => Self::handle_eth_result(result),
+        }
+    }
+
+    fn fee_history(
+        eth_simulator: &EthSimulator,
+        block_count: usize,
+        newest_block: String,
+        percentiles: String,
+    ) {
+        let newest_block = match Self::parse_block(&newest_block) {
+            Some(block) => block,
+            None => {
+                println!("wrong block, use a block number or latest");
+                return;
+            }
+        };
+        let percentiles: Result<Vec<f64>, _> = if percentiles == "-" {
+            Ok(Vec::new())
+        } else {
+            percentiles.split(',').map(|p| p.trim().parse()).collect()
+        };
+        match percentiles {
+            Ok(percentiles) => Self::handle_eth_result(eth_simulator.fee_history(
+                block_count,
+                newest_block,
+                &percentiles,
+            )),
+            Err(_) => println!("wrong percentiles, use a list like 25,50,75 or -"),
+        }
+    }
+
+    fn save(eth_simulator: &EthSimulator, file: &Path) {
+        match eth_simulator.save(file) {
+            Ok(()) => println!("state saved to {}", file.display()),
+            Err(err) => println!("saving state to {} failed: {}", file.display(), err),
+        }
+    }
+
+    /// Address with its name like `alice (0x..)`, or the address alone
+    fn label(address: &str, name: &str) -> String {
+        if name.is_empty() {
+            address.to_string()
+        } else {
+            format!("{} ({})", name, address)
+        }
+    }
+
+    /// Block number, `None` inside for latest
+    fn parse_block(block: &str) -> Option<Option<usize>> {
+        if block == "latest" {
+            Some(None)
+        } else {
+            block.parse().ok().map(Some)
+        }
+    }
+
     fn handle_eth_result(result: Result<EthResult, EthError>) {
         match result {
             Ok(value) => match value {
@@ -185,20 +910,137 @@ impl<'a> REPL<'a> {
                     for account in accounts {
                         println!(
                             "name: {}, address: {}, balance: {}",
-                            account.name, account.address, account.balance
+                            account.name,
+                            account.address,
+                            units::format_amount(account.balance, Unit::Wei)
                         );
                     }
                 }
+                EthResult::Balance(balance) => {
+                    println!("balance: {}", units::format_amount(balance, Unit::Wei))
+                }
                 EthResult::Address(address) => println!("address: {}", address),
+                EthResult::PrivateKey(private_key) => println!("private key: {}", private_key),
                 EthResult::Value(value) => println!("value: {}", value),
+                EthResult::Dot(dot) => print!("{}", dot),
+                EthResult::Lcov(lcov) => print!("{}", lcov),
+                EthResult::Dump(dump) => println!("{}", dump),
+                EthResult::Hash(hash) => println!("hash: {}", hash),
+                EthResult::Proof(proof) => {
+                    println!("{}", serde_json::to_string_pretty(&proof).unwrap())
+                }
+                EthResult::AliasList(aliases) => {
+                    for alias in aliases {
+                        println!("name: {}, address: {}", alias.name, alias.address);
+                    }
+                }
+                EthResult::TxList(txs) => {
+                    for tx in txs {
+                        let to = if tx.to.is_empty() {
+                            "create".to_string()
+                        } else {
+                            Self::label(&tx.to, &tx.to_name)
+                        };
+                        println!(
+                            "block: {}, hash: {}, from: {}, to: {}, {}",
+                            tx.block,
+                            tx.hash,
+                            Self::label(&tx.from, &tx.from_name),
+                            to,
+                            if tx.success { "success" } else { "failed" }
+                        );
+                    }
+                }
+                EthResult::Receipt(receipt) => {
+                    println!("{}", serde_json::to_string_pretty(&receipt).unwrap())
+                }
+                EthResult::FeeHistory(history) => {
+                    println!("{}", serde_json::to_string_pretty(&history).unwrap())
+                }
+                EthResult::Logs(logs) => {
+                    for log in logs {
+                        println!(
+                            "address: {}, topics: [{}], data: {}",
+                            Self::label(&log.address, &log.name),
+                            log.topics.join(", "),
+                            log.data
+                        );
+                    }
+                }
+                EthResult::StateDiff(diff) => {
+                    println!("{}", serde_json::to_string_pretty(&diff).unwrap())
+                }
+                EthResult::Storage(slots) => {
+                    for slot in slots {
+                        println!("slot: {}, value: {}", slot.slot, slot.value);
+                    }
+                }
+                EthResult::StorageLayout(variables) => {
+                    for variable in variables {
+                        println!(
+                            "{} ({}): {}, slot: {}, offset: {}",
+                            variable.name,
+                            variable.label,
+                            variable.value,
+                            variable.slot,
+                            variable.offset
+                        );
+                    }
+                }
                 EthResult::None => {}
             },
             Err(err) => match err {
                 EthError::NotExistedAddress => println!("some address does not exist"),
-                EthError::NotEnoughBalance => println!("balance is not enough"),
-                EthError::VMError => println!("there is a vm error"),
+                EthError::ExistedAddress => println!("account already exists"),
+                EthError::ExistedName => println!("name is already taken"),
+                EthError::InvalidName => println!("names cannot be empty or start with 0x"),
+                EthError::NotExistedName => println!("alias does not exist"),
+                EthError::InvalidPrivateKey => println!("private key is not valid"),
+                EthError::NoPrivateKey => {
+                    println!("account has no private key, impersonate it to send from it")
+                }
+                EthError::NotImpersonated => println!("address is not impersonated"),
+                EthError::NotEnoughBalance => {
+                    println!("balance is not enough for the value and the gas limit")
+                }
+                EthError::IntrinsicGasTooLow { intrinsic, limit } => println!(
+                    "gas limit {} is below the intrinsic gas {}",
+                    limit, intrinsic
+                ),
+                EthError::GasLimitExceeded => println!("gas limit is above the block gas limit"),
+                EthError::FeeCapTooLow { fee_cap, base_fee } => println!(
+                    "max fee per gas {} is below the base fee {}",
+                    units::format_amount(fee_cap, Unit::Gwei),
+                    units::format_amount(base_fee, Unit::Gwei)
+                ),
+                EthError::TipAboveFeeCap => {
+                    println!("max priority fee per gas is above the max fee per gas")
+                }
+                EthError::TxTypeNotSupported => {
+                    println!("dynamic fee transactions need the London fork or later")
+                }
+                EthError::InvalidPercentiles => {
+                    println!("percentiles must be between 0 and 100 in ascending order")
+                }
+                EthError::NonceTooLow { expected, actual } => {
+                    println!("nonce {} is too low, expected {}", actual, expected)
+                }
+                EthError::NonceTooHigh { expected, actual } => {
+                    println!("nonce {} is too high, expected {}", actual, expected)
+                }
+                EthError::VMError(message) => println!("there is a vm error: {}", message),
+                EthError::StepLimitExceeded => println!("execution exceeded the step limit"),
+                EthError::MemoryLimitExceeded => println!("execution exceeded the memory limit"),
+                EthError::Timeout => println!("execution timed out"),
                 EthError::CallEoAAccount => println!("called account is not Contract"),
                 EthError::NotExistedContract => println!("called contract does not exist"),
+                EthError::NoStorageLayout => {
+                    println!("contract has no storage layout, deploy it from source with a solc giving one")
+                }
+                EthError::CoverageNotStarted => println!("coverage is not started"),
+                EthError::NotExistedBlock => println!("block does not exist"),
+                EthError::NotExistedTx => println!("transaction does not exist"),
+                EthError::InvalidCode => println!("code is not valid hex"),
                 EthError::CompileError => {
                     println!("compiling contract failed, check code or path of contract")
                 }
@@ -214,4 +1056,15 @@ struct Tx {
     to: String,
     value: String,
     data: String,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    gas: Option<usize>,
+    /// Amount like 20gwei
+    #[serde(default)]
+    gas_price: Option<String>,
+    #[serde(default)]
+    max_fee_per_gas: Option<String>,
+    #[serde(default)]
+    max_priority_fee_per_gas: Option<String>,
 }
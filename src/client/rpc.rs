@@ -1,3 +1,4 @@
+use ethereum_types::U256;
 use serde::Deserialize;
 use serde_json::{self, json, Value};
 
@@ -5,14 +6,16 @@ use std::{
     collections::HashMap,
     io::Write,
     net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     thread,
 };
 
 use super::Client;
 use crate::{
-    eth_api::{EthApi, EthResult},
+    eth_api::{EthApi, EthResult, TxOptions},
     eth_simulator::EthSimulator,
+    utils::units,
 };
 
 #[derive(Deserialize, Debug)]
@@ -29,37 +32,62 @@ enum RpcError {
 pub struct Rpc {
     server: TcpListener,
     socket: String,
+    eth_simulator: Arc<Mutex<EthSimulator>>,
+    /// File the state is saved to by `state_save` and when the server is stopped
+    state_file: Option<PathBuf>,
 }
 
 impl Client for Rpc {
     fn run(&mut self) {
         println!("rpc server listens at {}", self.socket);
-
-        let eth_simulator = Arc::new(Mutex::new(EthSimulator::new()));
+        if let Some(state_file) = self.state_file.clone() {
+            let eth_simulator = self.eth_simulator.clone();
+            // waits for the request being handled, then saves and stops on SIGINT or SIGTERM
+            ctrlc::set_handler(move || {
+                let eth_simulator = eth_simulator
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                match eth_simulator.save(&state_file) {
+                    Ok(()) => println!("state saved to {}", state_file.display()),
+                    Err(err) => {
+                        println!("saving state to {} failed: {}", state_file.display(), err)
+                    }
+                }
+                std::process::exit(0);
+            })
+            .expect("Failed to set the signal handler");
+        }
 
         for stream in self.server.incoming() {
             let stream = stream.unwrap();
-            let eth_simulator_clone = eth_simulator.clone();
+            let eth_simulator_clone = self.eth_simulator.clone();
+            let state_file = self.state_file.clone();
 
             println!("New connection: {}", stream.peer_addr().unwrap());
 
             thread::spawn(move || {
                 let mut eth_simulator = eth_simulator_clone.lock().unwrap();
-                Self::handle_connection(&mut eth_simulator, stream);
+                Self::handle_connection(&mut eth_simulator, state_file.as_deref(), stream);
             });
         }
     }
 }
 
 impl Rpc {
-    pub fn new(socket: &str) -> Self {
+    pub fn new(socket: &str, eth_simulator: EthSimulator, state_file: Option<PathBuf>) -> Self {
         Rpc {
             server: TcpListener::bind(socket).unwrap(),
             socket: socket.to_string(),
+            eth_simulator: Arc::new(Mutex::new(eth_simulator)),
+            state_file,
         }
     }
 
-    fn handle_connection(eth_simulator: &mut EthSimulator, mut stream: TcpStream) {
+    fn handle_connection(
+        eth_simulator: &mut EthSimulator,
+        state_file: Option<&Path>,
+        mut stream: TcpStream,
+    ) {
         let mut status = "ok";
         let mut result = Value::Null;
 
@@ -67,7 +95,7 @@ impl Rpc {
         if let Ok(request) = Request::deserialize(&mut de) {
             println!("receive request {:?}", request);
 
-            match Self::handle_request(eth_simulator, request) {
+            match Self::handle_request(eth_simulator, state_file, request) {
                 Ok(rst) => {
                     result = rst;
                 }
@@ -90,15 +118,51 @@ impl Rpc {
 
     fn handle_request(
         eth_simulator: &mut EthSimulator,
+        state_file: Option<&Path>,
         request: Request,
     ) -> Result<Value, RpcError> {
         match request.method.as_str() {
             "account_add" => Self::account_add(eth_simulator, &request.params),
+            "account_import" => Self::account_import(eth_simulator, &request.params),
+            "account_rename" => Self::account_rename(eth_simulator, &request.params),
+            "alias_add" => Self::alias_add(eth_simulator, &request.params),
+            "alias_remove" => Self::alias_remove(eth_simulator, &request.params),
+            "alias_list" => Self::alias_list(eth_simulator, &request.params),
+            "account_export_key" => Self::account_export_key(eth_simulator, &request.params),
             "account_list" => Self::account_list(eth_simulator, &request.params),
             "account_balance" => Self::account_balance(eth_simulator, &request.params),
+            "account_nonce" | "eth_getTransactionCount" => {
+                Self::account_nonce(eth_simulator, &request.params)
+            }
+            "anvil_setBalance" => Self::account_set_balance(eth_simulator, &request.params),
+            "anvil_setNonce" => Self::account_set_nonce(eth_simulator, &request.params),
+            "anvil_setCode" => Self::account_set_code(eth_simulator, &request.params),
+            "anvil_setStorageAt" => Self::account_set_storage(eth_simulator, &request.params),
+            "anvil_impersonateAccount" => Self::account_impersonate(eth_simulator, &request.params),
+            "anvil_stopImpersonatingAccount" => {
+                Self::account_stop_impersonating(eth_simulator, &request.params)
+            }
             "tx_send" => Self::tx_send(eth_simulator, &request.params),
             "contract_deploy" => Self::contract_deploy(eth_simulator, &request.params),
             "contract_call" => Self::contract_call(eth_simulator, &request.params),
+            "contract_cfg" => Self::contract_cfg(eth_simulator, &request.params),
+            "coverage_start" => Self::coverage_start(eth_simulator, &request.params),
+            "coverage_report" => Self::coverage_report(eth_simulator, &request.params),
+            "state_root" => Self::state_root(eth_simulator, &request.params),
+            "tx_list" => Self::tx_list(eth_simulator, &request.params),
+            "tx_logs" => Self::tx_logs(eth_simulator, &request.params),
+            "tx_receipt" | "eth_getTransactionReceipt" => {
+                Self::tx_receipt(eth_simulator, &request.params)
+            }
+            "tx_state_diff" => Self::tx_state_diff(eth_simulator, &request.params),
+            "eth_getProof" => Self::account_proof(eth_simulator, &request.params),
+            "storage_list" => Self::account_storage(eth_simulator, &request.params),
+            "storage_decode" => Self::contract_storage(eth_simulator, &request.params),
+            "debug_dumpBlock" => Self::state_dump(eth_simulator, &request.params),
+            "eth_feeHistory" => Self::fee_history(eth_simulator, &request.params),
+            "state_save" => Self::save(eth_simulator, state_file),
+            "evm_snapshot" => Self::snapshot(eth_simulator, &request.params),
+            "evm_revert" => Self::revert(eth_simulator, &request.params),
             _ => Err(RpcError::WrongMethod),
         }
     }
@@ -118,6 +182,88 @@ impl Rpc {
         }
     }
 
+    fn account_import(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let (Some(name), Some(private_key)) = (params.get("name"), params.get("private_key")) {
+            if let Ok(EthResult::Address(address)) = eth_simulator.account_import(name, private_key)
+            {
+                Ok(json!({ "address": address }))
+            } else {
+                Err(RpcError::WrongRequest)
+            }
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn account_rename(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+        let name = params.get("name").ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.account_rename(address, name) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongRequest),
+        }
+    }
+
+    fn alias_add(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let name = params.get("name").ok_or(RpcError::WrongParams)?;
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.alias_add(name, address) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongRequest),
+        }
+    }
+
+    fn alias_remove(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let name = params.get("name").ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.alias_remove(name) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongParams),
+        }
+    }
+
+    fn alias_list(
+        eth_simulator: &EthSimulator,
+        _params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let Ok(EthResult::AliasList(aliases)) = eth_simulator.alias_list() {
+            Ok(json!({ "aliases": aliases }))
+        } else {
+            Err(RpcError::WrongRequest)
+        }
+    }
+
+    fn account_export_key(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let Some(address) = params.get("address") {
+            if let Ok(EthResult::PrivateKey(private_key)) =
+                eth_simulator.account_export_key(address)
+            {
+                Ok(json!({ "private_key": private_key }))
+            } else {
+                Err(RpcError::WrongRequest)
+            }
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
     fn account_list(
         eth_simulator: &EthSimulator,
         _params: &HashMap<String, String>,
@@ -134,7 +280,7 @@ impl Rpc {
         params: &HashMap<String, String>,
     ) -> Result<Value, RpcError> {
         if let Some(address) = params.get("address") {
-            if let Ok(EthResult::Value(balance)) = eth_simulator.account_balance(address) {
+            if let Ok(EthResult::Balance(balance)) = eth_simulator.account_balance(address) {
                 Ok(json!({ "balance": balance }))
             } else {
                 Err(RpcError::WrongParams)
@@ -144,6 +290,113 @@ impl Rpc {
         }
     }
 
+    fn account_nonce(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let Some(address) = params.get("address") {
+            if let Ok(EthResult::Value(nonce)) = eth_simulator.account_nonce(address) {
+                Ok(json!({ "nonce": nonce }))
+            } else {
+                Err(RpcError::WrongParams)
+            }
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn account_set_balance(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+        let balance = params
+            .get("balance")
+            .and_then(|balance| units::parse_amount(balance))
+            .ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.account_set_balance(address, balance) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongParams),
+        }
+    }
+
+    fn account_set_nonce(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+        let nonce = params
+            .get("nonce")
+            .and_then(|nonce| units::parse_amount(nonce))
+            .filter(|nonce| nonce.bits() <= 64)
+            .ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.account_set_nonce(address, nonce.as_usize()) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongParams),
+        }
+    }
+
+    fn account_set_code(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let (Some(address), Some(code)) = (params.get("address"), params.get("code")) {
+            match eth_simulator.account_set_code(address, code) {
+                Ok(_) => Ok(Value::Null),
+                Err(_) => Err(RpcError::WrongParams),
+            }
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn account_set_storage(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+        let (slot, value) = params
+            .get("slot")
+            .and_then(|slot| units::parse_amount(slot))
+            .zip(
+                params
+                    .get("value")
+                    .and_then(|value| units::parse_amount(value)),
+            )
+            .ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.account_set_storage(address, slot, value) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongParams),
+        }
+    }
+
+    fn account_impersonate(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.account_impersonate(address) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongParams),
+        }
+    }
+
+    fn account_stop_impersonating(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+
+        match eth_simulator.account_stop_impersonating(address) {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongParams),
+        }
+    }
+
     fn tx_send(
         eth_simulator: &mut EthSimulator,
         params: &HashMap<String, String>,
@@ -154,7 +407,26 @@ impl Rpc {
             params.get("value"),
             params.get("data"),
         ) {
-            match eth_simulator.tx_send(from, to, value.parse::<usize>().unwrap(), data) {
+            let nonce = match params.get("nonce") {
+                Some(nonce) => Some(nonce.parse::<usize>().map_err(|_| RpcError::WrongParams)?),
+                None => None,
+            };
+            let gas = match params.get("gas") {
+                Some(gas) => Some(gas.parse::<usize>().map_err(|_| RpcError::WrongParams)?),
+                None => None,
+            };
+            let value = units::parse_amount(value).ok_or(RpcError::WrongParams)?;
+            let options = TxOptions {
+                nonce,
+                gas,
+                gas_price: Self::optional_amount(params, "gas_price")?,
+                max_fee_per_gas: Self::optional_amount(params, "max_fee_per_gas")?,
+                max_priority_fee_per_gas: Self::optional_amount(
+                    params,
+                    "max_priority_fee_per_gas",
+                )?,
+            };
+            match eth_simulator.tx_send_with(from, to, value, data, options) {
                 Ok(result) => match result {
                     EthResult::Value(value) => Ok(Value::String(value.to_string())),
                     _ => Ok(Value::Null),
@@ -166,6 +438,19 @@ impl Rpc {
         }
     }
 
+    /// Amount of an optional param like `20gwei`
+    fn optional_amount(
+        params: &HashMap<String, String>,
+        key: &str,
+    ) -> Result<Option<U256>, RpcError> {
+        match params.get(key) {
+            Some(amount) => Ok(Some(
+                units::parse_amount(amount).ok_or(RpcError::WrongParams)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
     fn contract_deploy(
         eth_simulator: &mut EthSimulator,
         params: &HashMap<String, String>,
@@ -201,4 +486,239 @@ impl Rpc {
             Err(RpcError::WrongParams)
         }
     }
+
+    fn contract_cfg(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let Some(contract) = params.get("contract") {
+            if let Ok(EthResult::Dot(dot)) = eth_simulator.contract_cfg(contract) {
+                Ok(json!({ "dot": dot }))
+            } else {
+                Err(RpcError::WrongRequest)
+            }
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn coverage_start(
+        eth_simulator: &mut EthSimulator,
+        _params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        match eth_simulator.coverage_start() {
+            Ok(_) => Ok(Value::Null),
+            Err(_) => Err(RpcError::WrongRequest),
+        }
+    }
+
+    fn coverage_report(
+        eth_simulator: &EthSimulator,
+        _params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let Ok(EthResult::Lcov(lcov)) = eth_simulator.coverage_report() {
+            Ok(json!({ "lcov": lcov }))
+        } else {
+            Err(RpcError::WrongRequest)
+        }
+    }
+
+    /// State root after `block`, of the current state without it
+    fn state_root(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let block = match params.get("block") {
+            Some(block) => Some(block.parse::<usize>().map_err(|_| RpcError::WrongParams)?),
+            None => None,
+        };
+
+        if let Ok(EthResult::Hash(state_root)) = eth_simulator.state_root(block) {
+            Ok(json!({ "stateRoot": state_root }))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn tx_list(
+        eth_simulator: &EthSimulator,
+        _params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        if let Ok(EthResult::TxList(txs)) = eth_simulator.tx_list() {
+            Ok(json!(txs))
+        } else {
+            Err(RpcError::WrongRequest)
+        }
+    }
+
+    fn tx_logs(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let hash = params.get("hash").ok_or(RpcError::WrongParams)?;
+
+        if let Ok(EthResult::Logs(logs)) = eth_simulator.tx_logs(hash) {
+            Ok(json!(logs))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn tx_receipt(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let hash = params.get("hash").ok_or(RpcError::WrongParams)?;
+
+        if let Ok(EthResult::Receipt(receipt)) = eth_simulator.tx_receipt(hash) {
+            Ok(json!(receipt))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    /// Result of the `prestateTracer` in diff mode for the transaction `hash`
+    fn tx_state_diff(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let hash = params.get("hash").ok_or(RpcError::WrongParams)?;
+
+        if let Ok(EthResult::StateDiff(diff)) = eth_simulator.tx_state_diff(hash) {
+            Ok(json!(diff))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    /// `storageKeys` are comma separated, without `block` the proof is of the current state
+    fn account_proof(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+        let storage_keys = match params.get("storageKeys") {
+            Some(keys) if !keys.trim().is_empty() => keys
+                .split(',')
+                .map(units::parse_amount)
+                .collect::<Option<Vec<_>>>()
+                .ok_or(RpcError::WrongParams)?,
+            _ => Vec::new(),
+        };
+        let block = match params.get("block").map(String::as_str) {
+            Some("latest") | None => None,
+            Some(block) => Some(block.parse::<usize>().map_err(|_| RpcError::WrongParams)?),
+        };
+
+        if let Ok(EthResult::Proof(proof)) =
+            eth_simulator.account_proof(address, &storage_keys, block)
+        {
+            Ok(json!(proof))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn account_storage(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let address = params.get("address").ok_or(RpcError::WrongParams)?;
+        if let Ok(EthResult::Storage(slots)) = eth_simulator.account_storage(address) {
+            Ok(json!(slots))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn contract_storage(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let contract = params.get("contract").ok_or(RpcError::WrongParams)?;
+        if let Ok(EthResult::StorageLayout(variables)) = eth_simulator.contract_storage(contract) {
+            Ok(json!(variables))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    fn state_dump(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let block = match params.get("block").map(String::as_str) {
+            Some("latest") | None => None,
+            Some(block) => Some(block.parse::<usize>().map_err(|_| RpcError::WrongParams)?),
+        };
+
+        if let Ok(EthResult::Dump(dump)) = eth_simulator.state_dump(block) {
+            serde_json::from_str(&dump).map_err(|_| RpcError::WrongRequest)
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    /// Percentiles are given as a comma separated list like `25,50,75`
+    fn fee_history(
+        eth_simulator: &EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let block_count = params
+            .get("blockCount")
+            .ok_or(RpcError::WrongParams)?
+            .parse::<usize>()
+            .map_err(|_| RpcError::WrongParams)?;
+        let newest_block = match params.get("newestBlock").map(String::as_str) {
+            Some("latest") | None => None,
+            Some(block) => Some(block.parse::<usize>().map_err(|_| RpcError::WrongParams)?),
+        };
+        let percentiles = match params.get("rewardPercentiles") {
+            Some(percentiles) if !percentiles.trim().is_empty() => percentiles
+                .split(',')
+                .map(|percentile| percentile.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| RpcError::WrongParams)?,
+            _ => Vec::new(),
+        };
+
+        if let Ok(EthResult::FeeHistory(history)) =
+            eth_simulator.fee_history(block_count, newest_block, &percentiles)
+        {
+            Ok(json!(history))
+        } else {
+            Err(RpcError::WrongParams)
+        }
+    }
+
+    /// Save to the file given with `--state`, files cannot be chosen over the network
+    fn save(eth_simulator: &EthSimulator, state_file: Option<&Path>) -> Result<Value, RpcError> {
+        let state_file = state_file.ok_or(RpcError::WrongRequest)?;
+        eth_simulator
+            .save(state_file)
+            .map_err(|_| RpcError::WrongRequest)?;
+
+        Ok(Value::Bool(true))
+    }
+
+    fn snapshot(
+        eth_simulator: &mut EthSimulator,
+        _params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        Ok(Value::String(format!("{:#x}", eth_simulator.snapshot())))
+    }
+
+    /// `id` as returned by `evm_snapshot`, the result tells whether the snapshot existed
+    fn revert(
+        eth_simulator: &mut EthSimulator,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, RpcError> {
+        let id = params
+            .get("id")
+            .and_then(|id| units::parse_amount(id))
+            .filter(|id| id.bits() <= 64)
+            .ok_or(RpcError::WrongParams)?;
+
+        Ok(Value::Bool(eth_simulator.revert(id.as_usize())))
+    }
 }
@@ -1,41 +1,307 @@
+use ethereum_types::U256;
 use serde::Serialize;
 
+use std::collections::BTreeMap;
+
 #[derive(Serialize)]
 pub struct AccountInfo {
     pub name: String,
     pub address: String,
-    pub balance: usize,
+    /// In wei
+    pub balance: U256,
+}
+
+/// Account and storage proof in the format of `eth_getProof`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProofInfo {
+    pub address: String,
+    pub account_proof: Vec<String>,
+    pub balance: U256,
+    pub code_hash: String,
+    pub nonce: U256,
+    pub storage_hash: String,
+    pub storage_proof: Vec<StorageProofInfo>,
+}
+
+#[derive(Serialize)]
+pub struct StorageProofInfo {
+    pub key: String,
+    pub value: U256,
+    pub proof: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct StorageSlotInfo {
+    pub slot: String,
+    pub value: String,
+}
+
+/// State variable decoded with the storage layout of its contract
+#[derive(Serialize)]
+pub struct StorageVariableInfo {
+    pub name: String,
+    /// Solidity type
+    #[serde(rename = "type")]
+    pub label: String,
+    pub slot: String,
+    pub offset: usize,
+    pub value: String,
+}
+
+/// Name of an address which need not have an account
+#[derive(Serialize)]
+pub struct AliasInfo {
+    pub name: String,
+    pub address: String,
+}
+
+/// Addresses come with their account name or alias, empty if they have none
+#[derive(Serialize)]
+pub struct TxInfo {
+    pub hash: String,
+    pub block: usize,
+    pub from: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub from_name: String,
+    /// Empty for contract creations
+    pub to: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub to_name: String,
+    pub success: bool,
+}
+
+#[derive(Serialize)]
+pub struct LogInfo {
+    pub address: String,
+    /// Account name or alias of the address, empty if it has none
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+/// Receipt of a mined transaction in the format of `eth_getTransactionReceipt`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptInfo {
+    pub transaction_hash: String,
+    pub block_number: usize,
+    pub from: String,
+    /// None for contract creations
+    pub to: Option<String>,
+    /// Address of the contract created, if any
+    pub contract_address: Option<String>,
+    /// 2 for dynamic fee transactions, 0 for legacy ones
+    #[serde(rename = "type")]
+    pub tx_type: u8,
+    pub status: bool,
+    /// After refunds
+    pub gas_used: usize,
+    /// Wei paid per unit of gas
+    pub effective_gas_price: U256,
+    /// Wei paid by the sender for the gas used
+    pub fee: U256,
+    /// Part of the fee burnt as base fee, the rest is paid to the coinbase
+    pub burnt_fee: U256,
+}
+
+/// Fees of a range of blocks in the format of `eth_feeHistory`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeHistoryInfo {
+    pub oldest_block: usize,
+    /// One per block and the one of the block after the newest
+    pub base_fee_per_gas: Vec<U256>,
+    /// Gas used divided by the gas limit of each block
+    pub gas_used_ratio: Vec<f64>,
+    /// Priority fee per gas at each of the percentiles asked for, per block
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reward: Vec<Vec<U256>>,
+}
+
+/// Optional settings of a transaction, defaults are used for those not set
+#[derive(Default)]
+pub struct TxOptions {
+    /// The current nonce of the sender by default
+    pub nonce: Option<usize>,
+    /// Gas limit
+    pub gas: Option<usize>,
+    /// Wei per unit of gas of a legacy transaction, 10 wei above the base fee by default
+    pub gas_price: Option<U256>,
+    /// EIP-1559: setting one of the max fees makes a dynamic fee transaction.
+    /// The max fee is twice the base fee plus the max priority fee by default.
+    pub max_fee_per_gas: Option<U256>,
+    /// Zero by default
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+/// Accounts changed by a transaction in the diff mode format of `prestateTracer`
+#[derive(Serialize)]
+pub struct StateDiffInfo {
+    pub pre: BTreeMap<String, AccountDiffInfo>,
+    pub post: BTreeMap<String, AccountDiffInfo>,
+}
+
+#[derive(Serialize)]
+pub struct AccountDiffInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<String, String>,
 }
 
 pub enum EthError {
     NotExistedAddress,
+    ExistedAddress,
+    /// An account or alias already has the name
+    ExistedName,
+    /// Names cannot be empty or start with `0x`
+    InvalidName,
+    NotExistedName,
+    InvalidPrivateKey,
+    /// The account was not created from a keypair, like a contract
+    NoPrivateKey,
+    /// The address is not impersonated, so impersonating cannot be stopped
+    NotImpersonated,
+    /// The balance does not cover the value and the whole gas limit
     NotEnoughBalance,
-    VMError,
+    /// The gas limit does not cover the intrinsic gas
+    IntrinsicGasTooLow {
+        intrinsic: usize,
+        limit: usize,
+    },
+    /// The gas limit is above the gas limit of the block
+    GasLimitExceeded,
+    /// The max fee per gas, or the gas price, is below the base fee of the block
+    FeeCapTooLow {
+        fee_cap: U256,
+        base_fee: U256,
+    },
+    /// The max priority fee per gas is above the max fee per gas
+    TipAboveFeeCap,
+    /// Dynamic fee transactions are only accepted from London on
+    TxTypeNotSupported,
+    /// Percentiles have to be between 0 and 100 in ascending order
+    InvalidPercentiles,
+    NonceTooLow {
+        expected: usize,
+        actual: usize,
+    },
+    NonceTooHigh {
+        expected: usize,
+        actual: usize,
+    },
+    /// Description of the error with its Solidity location when known
+    VMError(String),
+    StepLimitExceeded,
+    MemoryLimitExceeded,
+    Timeout,
     CallEoAAccount,
     CompileError,
     NotExistedContract,
+    CoverageNotStarted,
+    NotExistedBlock,
+    NotExistedTx,
+    /// Code is not given as hex
+    InvalidCode,
+    /// The contract was not compiled here, or solc gave no storage layout
+    NoStorageLayout,
 }
 
 pub enum EthResult {
     Address(String),
+    PrivateKey(String),
+    /// Amount of wei
+    Balance(U256),
     AccountList(Vec<AccountInfo>),
-    Value(usize),
+    AliasList(Vec<AliasInfo>),
+    Value(U256),
+    Dot(String),
+    Lcov(String),
+    /// State dump in the JSON format of geth
+    Dump(String),
+    Hash(String),
+    Proof(AccountProofInfo),
+    Storage(Vec<StorageSlotInfo>),
+    StorageLayout(Vec<StorageVariableInfo>),
+    TxList(Vec<TxInfo>),
+    Receipt(ReceiptInfo),
+    FeeHistory(FeeHistoryInfo),
+    Logs(Vec<LogInfo>),
+    StateDiff(StateDiffInfo),
     None,
 }
 
 pub trait EthApi {
     fn account_add(&mut self, name: &str) -> Result<EthResult, EthError>;
+    /// Add the account of a known private key given in hex
+    fn account_import(&mut self, name: &str, private_key: &str) -> Result<EthResult, EthError>;
+    /// Name an existing account, names of accounts and aliases are unique
+    fn account_rename(&mut self, address: &str, name: &str) -> Result<EthResult, EthError>;
+    /// Name an address whether or not it has an account, like a contract of a real chain
+    fn alias_add(&mut self, name: &str, address: &str) -> Result<EthResult, EthError>;
+    fn alias_remove(&mut self, name: &str) -> Result<EthResult, EthError>;
+    fn alias_list(&self) -> Result<EthResult, EthError>;
+    fn account_export_key(&self, address: &str) -> Result<EthResult, EthError>;
     fn account_list(&self) -> Result<EthResult, EthError>;
     fn account_balance(&self, address: &str) -> Result<EthResult, EthError>;
+    fn account_nonce(&self, address: &str) -> Result<EthResult, EthError>;
+    /// Non-zero storage slots ordered by slot
+    fn account_storage(&self, address: &str) -> Result<EthResult, EthError>;
+
+    /// Cheat codes setting state without a transaction, accounts given by
+    /// address are created if missing
+    fn account_set_balance(&mut self, address: &str, balance: U256) -> Result<EthResult, EthError>;
+    fn account_set_nonce(&mut self, address: &str, nonce: usize) -> Result<EthResult, EthError>;
+    /// Code given in hex, it replaces any compiled contract at the address
+    fn account_set_code(&mut self, address: &str, code: &str) -> Result<EthResult, EthError>;
+    fn account_set_storage(
+        &mut self,
+        address: &str,
+        slot: U256,
+        value: U256,
+    ) -> Result<EthResult, EthError>;
 
+    /// Send transactions from an address without its private key,
+    /// the account is created by its first transaction if missing
+    fn account_impersonate(&mut self, address: &str) -> Result<EthResult, EthError>;
+    fn account_stop_impersonating(&mut self, address: &str) -> Result<EthResult, EthError>;
+
+    /// Without a nonce the current nonce of the sender is used.
+    /// Only accounts with a private key or impersonated ones can send.
     fn tx_send(
         &mut self,
         from: &str,
         to: &str,
-        value: usize,
+        value: U256,
+        data: &str,
+        nonce: Option<usize>,
+    ) -> Result<EthResult, EthError>;
+    /// Send with a gas limit and price. The sender pays the whole gas limit upfront
+    /// and gets back what is not used, the fee of the gas used goes to the coinbase.
+    fn tx_send_with(
+        &mut self,
+        from: &str,
+        to: &str,
+        value: U256,
         data: &str,
+        options: TxOptions,
     ) -> Result<EthResult, EthError>;
 
+    /// Mined transactions, oldest first
+    fn tx_list(&self) -> Result<EthResult, EthError>;
+    /// Balance, nonce, code and storage slots of the accounts changed by a mined transaction
+    fn tx_state_diff(&self, tx_hash: &str) -> Result<EthResult, EthError>;
+    /// Logs of a mined transaction with the names of their addresses
+    fn tx_logs(&self, tx_hash: &str) -> Result<EthResult, EthError>;
+    /// Status, gas used and fee of a mined transaction
+    fn tx_receipt(&self, tx_hash: &str) -> Result<EthResult, EthError>;
+
     fn contract_deploy(&mut self, from: &str, contract_file: &str) -> Result<EthResult, EthError>;
 
     fn contract_call(
@@ -44,4 +310,35 @@ pub trait EthApi {
         contract: &str,
         input: &str,
     ) -> Result<EthResult, EthError>;
+
+    fn contract_cfg(&self, contract: &str) -> Result<EthResult, EthError>;
+    /// State variables of a contract compiled here, decoded with its storage layout.
+    /// Mapping entries are found through the keys hashed by transactions.
+    fn contract_storage(&self, contract: &str) -> Result<EthResult, EthError>;
+
+    /// Start recording the code executed by transactions
+    fn coverage_start(&mut self) -> Result<EthResult, EthError>;
+    /// Coverage of the deployed contracts since the last start in LCOV format
+    fn coverage_report(&self) -> Result<EthResult, EthError>;
+
+    /// State root after block `block`, of the current state without a block
+    fn state_root(&self, block: Option<usize>) -> Result<EthResult, EthError>;
+    /// All accounts after `block` in the format of geth's `dump`, the current ones without a block
+    fn state_dump(&self, block: Option<usize>) -> Result<EthResult, EthError>;
+    /// Base fees, gas used ratios and priority fees at `reward_percentiles` of up to
+    /// `block_count` blocks ending with `newest_block`, the latest one without a block
+    fn fee_history(
+        &self,
+        block_count: usize,
+        newest_block: Option<usize>,
+        reward_percentiles: &[f64],
+    ) -> Result<EthResult, EthError>;
+    /// Merkle proof of an account and its storage slots against the state root
+    /// after `block`, the address does not have to exist
+    fn account_proof(
+        &self,
+        address: &str,
+        storage_keys: &[U256],
+        block: Option<usize>,
+    ) -> Result<EthResult, EthError>;
 }
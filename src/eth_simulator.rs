@@ -1,89 +1,265 @@
 mod account;
 mod block;
+mod diff;
+mod dump;
 mod eth_types;
 mod evm;
+mod genesis;
 mod hash;
+mod layout;
+mod lcov;
+mod proof;
 mod state;
+mod trie;
 mod tx;
 
-use std::path::Path;
+pub use evm::ExecutionLimits;
+pub use genesis::GenesisError;
+pub use proof::{
+    verify_account_proof, verify_storage_proof, AccountProof, ProvenAccount, StorageProof,
+};
+pub use state::SnapshotId;
+pub use trie::ProofError;
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
 
 use self::{
-    eth_types::{Address, Bytes, EthFrom, U256},
+    account::Account,
+    diff::AccountDiff,
+    dump::Dump,
+    eth_types::{Address, Bytes, Code, EthFrom, Secret, H256, U256},
+    evm::{Cfg, Host, VMError, VMErrorKind},
+    genesis::Genesis,
+    layout::Layout,
     state::{State, StateError, TxError},
-    tx::{Tx, TxType},
+    tx::{Tx, TxType, DEFAULT_GAS, DEFAULT_GAS_PRICE},
 };
 use crate::{
-    eth_api::{AccountInfo, EthApi, EthError, EthResult},
+    eth_api::{
+        AccountDiffInfo, AccountInfo, AccountProofInfo, AliasInfo, EthApi, EthError, EthResult,
+        FeeHistoryInfo, LogInfo, ReceiptInfo, StateDiffInfo, StorageProofInfo, StorageSlotInfo,
+        StorageVariableInfo, TxInfo, TxOptions,
+    },
     utils::{path, solc},
 };
 
+/// Control-flow graph in DOT format of bytecode given as hex
+pub fn bytecode_cfg(name: &str, bytecode: &str) -> String {
+    let bytecode = bytecode.trim();
+    let bytecode = bytecode.strip_prefix("0x").unwrap_or(bytecode);
+
+    Cfg::new(&Code::ethfrom(bytecode)).to_dot(name)
+}
+
+#[derive(Debug)]
+pub enum StateFileError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for StateFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateFileError::Io(err) => write!(f, "{}", err),
+            StateFileError::Json(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct EthSimulator {
     state: State,
+    contracts: HashMap<Address, solc::Contract>,
 }
 
 impl EthSimulator {
-    pub fn new() -> Self {
+    pub fn new(limits: ExecutionLimits) -> Self {
         EthSimulator {
-            state: State::new(),
+            state: State::new(limits),
+            contracts: HashMap::new(),
         }
     }
 
-    fn get_address(&self, addr: &str) -> Option<Address> {
-        if addr.starts_with("0x") {
-            let address = Address::ethfrom(addr);
-            if self.state.address_exist(&address) {
-                Some(address)
-            } else {
-                None
+    /// Simulator starting from the accounts and environment of a genesis file in geth format
+    pub fn from_genesis(limits: ExecutionLimits, genesis: &Path) -> Result<Self, GenesisError> {
+        Ok(EthSimulator {
+            state: State::from_genesis(limits, Genesis::from_file(genesis)?),
+            contracts: HashMap::new(),
+        })
+    }
+
+    /// Merge the accounts of a state dump in geth format into the state,
+    /// returning how many there were. Accounts of the dump replace the balance,
+    /// nonce and code of existing ones and set the slots given, keeping their names.
+    pub fn import_dump(&mut self, path: &Path) -> Result<usize, GenesisError> {
+        let accounts = Dump::from_file(path)?.into_accounts()?;
+        for account in &accounts {
+            let name = account.get_name();
+            let is_new = !self.state.address_exist(account.get_address());
+            if is_new && self.state.name_exists(name) {
+                return Err(GenesisError::DuplicateName(name.to_string()));
             }
-        } else {
-            self.state.account_query_address_by_name(addr)
         }
+        let count = accounts.len();
+        for account in accounts {
+            self.contracts.remove(account.get_address());
+            self.state.account_merge(account);
+        }
+
+        Ok(count)
     }
-}
 
-impl EthApi for EthSimulator {
-    fn account_add(&mut self, name: &str) -> Result<EthResult, EthError> {
-        let address = self.state.account_add(name);
-        Ok(EthResult::Address(String::ethfrom(&address)))
+    /// Simulator continuing from a state saved with `save`
+    pub fn from_state_file(limits: ExecutionLimits, path: &Path) -> Result<Self, StateFileError> {
+        let file = File::open(path).map_err(StateFileError::Io)?;
+        let mut eth_simulator: EthSimulator =
+            serde_json::from_reader(BufReader::new(file)).map_err(StateFileError::Json)?;
+        eth_simulator.state.set_limits(limits);
+        eth_simulator.state.index_names();
+
+        Ok(eth_simulator)
     }
 
-    fn account_list(&self) -> Result<EthResult, EthError> {
-        Ok(EthResult::AccountList(
-            self.state
-                .account_list()
-                .iter()
-                .map(|(name, address, balance)| AccountInfo {
-                    name: name.to_string(),
-                    address: String::ethfrom(*address),
-                    balance: *balance,
-                })
-                .collect(),
-        ))
+    /// Write accounts with their keys, code and storage, blocks with their receipts,
+    /// transactions and compiled contracts to `path`. Snapshots and coverage are not saved.
+    /// The file is replaced only once the whole state is written.
+    pub fn save(&self, path: &Path) -> Result<(), StateFileError> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let file = File::create(&partial).map_err(StateFileError::Io)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self).map_err(StateFileError::Json)?;
+        writer.flush().map_err(StateFileError::Io)?;
+
+        fs::rename(&partial, path).map_err(StateFileError::Io)
     }
 
-    fn account_balance(&self, address: &str) -> Result<EthResult, EthError> {
-        Ok(EthResult::Value(
-            self.state
-                .account_get_balance(
-                    &self
-                        .get_address(address)
-                        .ok_or(EthError::NotExistedAddress)?,
-                )
-                .unwrap(),
-        ))
+    /// Replace the whole state with the one saved at `path`, keeping the execution limits
+    pub fn load(&mut self, path: &Path) -> Result<(), StateFileError> {
+        *self = Self::from_state_file(self.state.limits().clone(), path)?;
+        Ok(())
     }
 
-    fn tx_send(
+    /// Record the current accounts, blocks and transactions to revert to later
+    pub fn snapshot(&mut self) -> SnapshotId {
+        self.state.snapshot()
+    }
+
+    /// Go back to snapshot `id`, invalidating it and all later snapshots.
+    /// `false` if there is no such snapshot.
+    pub fn revert(&mut self, id: SnapshotId) -> bool {
+        if !self.state.revert(id) {
+            return false;
+        }
+        // forget the sources of contracts deployed after the snapshot
+        let state = &self.state;
+        self.contracts
+            .retain(|address, _| state.address_exist(address));
+
+        true
+    }
+
+    /// Proof of `address` and its storage `keys` against the state root after
+    /// block `number`, or against the current state root without a block.
+    /// `None` if there is no such block.
+    pub fn proof(
+        &self,
+        address: &Address,
+        keys: &[H256],
+        number: Option<usize>,
+    ) -> Option<AccountProof> {
+        self.state.account_proof(address, keys, number)
+    }
+
+    fn get_address(&self, addr: &str) -> Option<Address> {
+        // aliases may name addresses without an account
+        self.parse_address(addr)
+            .filter(|address| self.state.address_exist(address))
+    }
+
+    /// Error if `name` cannot be given to an account or alias. Names have to be
+    /// unique and cannot look like addresses.
+    fn check_name(&self, name: &str) -> Result<(), EthError> {
+        if name.is_empty() || name.starts_with("0x") {
+            return Err(EthError::InvalidName);
+        }
+        if self.state.name_exists(name) {
+            return Err(EthError::ExistedName);
+        }
+
+        Ok(())
+    }
+
+    /// `name`, or with the first free number appended like `Token_1` if it is taken
+    fn unused_name(&self, name: &str) -> String {
+        (0..)
+            .map(|i| match i {
+                0 => name.to_string(),
+                i => format!("{}_{}", name, i),
+            })
+            .find(|name| !self.state.name_exists(name))
+            .unwrap()
+    }
+
+    /// Name or alias of `address` if it has one
+    fn label(&self, address: &Address) -> String {
+        self.state
+            .address_label(address)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn parse_tx_hash(tx_hash: &str) -> Option<H256> {
+        let hex = tx_hash.trim().strip_prefix("0x").unwrap_or(tx_hash.trim());
+        if hex.len() != 64 {
+            return None;
+        }
+        H256::from_str(hex).ok()
+    }
+
+    /// Address given in hex whether or not it exists, or the address of a named account
+    fn parse_address(&self, addr: &str) -> Option<Address> {
+        match addr.strip_prefix("0x") {
+            Some(hex) if hex.len() == 40 => Address::from_str(hex).ok(),
+            Some(_) => None,
+            None => self.state.account_query_address_by_name(addr),
+        }
+    }
+
+    fn tx_send_inner(
         &mut self,
         from: &str,
         to: &str,
-        value: usize,
+        value: U256,
         data: &str,
+        options: TxOptions,
+        is_static: bool,
     ) -> Result<EthResult, EthError> {
-        let from_addr = self.get_address(from).ok_or(EthError::NotExistedAddress)?;
-        let tx = if let Some(to_addr) = self.get_address(to) {
+        // impersonated senders may not exist yet
+        let from_addr = self
+            .parse_address(from)
+            .ok_or(EthError::NotExistedAddress)?;
+        let nonce = options
+            .nonce
+            .unwrap_or(self.state.account_get_nonce(&from_addr).unwrap_or(0));
+        let to_addr = self.get_address(to);
+        // unknown names deploy contracts, aliases without an account and addresses do not
+        if to_addr.is_none() {
+            if to.starts_with("0x") || self.state.name_exists(to) {
+                return Err(EthError::NotExistedAddress);
+            }
+            self.check_name(to)?;
+        }
+        let tx = if let Some(to_addr) = to_addr {
             let tx_type = if self.state.address_is_contract(&to_addr) {
                 TxType::CallContract
             } else {
@@ -93,6 +269,7 @@ impl EthApi for EthSimulator {
             Tx::new(
                 from_addr,
                 to_addr,
+                nonce,
                 value,
                 Bytes::ethfrom(data),
                 tx_type,
@@ -102,19 +279,39 @@ impl EthApi for EthSimulator {
             Tx::new(
                 from_addr,
                 Address::zero(),
+                nonce,
                 value,
                 Bytes::ethfrom(data),
                 TxType::DeployContract,
                 to.to_string(),
             )
         };
+        let base_fee = self.state.base_fee();
+        let tx = tx.with_gas(
+            options.gas.unwrap_or(DEFAULT_GAS),
+            options
+                .gas_price
+                .unwrap_or_else(|| base_fee + DEFAULT_GAS_PRICE),
+        );
+        let tx = match (options.max_fee_per_gas, options.max_priority_fee_per_gas) {
+            (None, None) => tx,
+            (max_fee, max_priority_fee) => {
+                let max_priority_fee = max_priority_fee.unwrap_or_default();
+                let max_fee = max_fee.unwrap_or_else(|| base_fee * 2 + max_priority_fee);
+                tx.with_dynamic_fee(max_fee, max_priority_fee)
+            }
+        };
 
-        match self.state.tx_send(tx) {
+        let result = if is_static {
+            self.state.tx_send_static(tx)
+        } else {
+            self.state.tx_send(tx)
+        };
+
+        match result {
             Ok(result) => {
                 if result.len() > 0 {
-                    Ok(EthResult::Value(
-                        U256::ethfrom(result.as_slice()).as_usize(),
-                    ))
+                    Ok(EthResult::Value(U256::ethfrom(result.as_slice())))
                 } else {
                     Ok(EthResult::None)
                 }
@@ -125,23 +322,408 @@ impl EthApi for EthSimulator {
                         Err(EthError::NotExistedAddress)
                     }
                     TxError::NotEnoughBalance => Err(EthError::NotEnoughBalance),
+                    TxError::IntrinsicGasTooLow { intrinsic, limit } => {
+                        Err(EthError::IntrinsicGasTooLow { intrinsic, limit })
+                    }
+                    TxError::GasLimitExceeded => Err(EthError::GasLimitExceeded),
+                    TxError::FeeCapTooLow { fee_cap, base_fee } => {
+                        Err(EthError::FeeCapTooLow { fee_cap, base_fee })
+                    }
+                    TxError::TipAboveFeeCap => Err(EthError::TipAboveFeeCap),
+                    TxError::TxTypeNotSupported => Err(EthError::TxTypeNotSupported),
+                    TxError::NoPrivateKey => Err(EthError::NoPrivateKey),
                     TxError::CallEoAAccount => Err(EthError::CallEoAAccount),
+                    TxError::NonceTooLow { expected, actual } => {
+                        Err(EthError::NonceTooLow { expected, actual })
+                    }
+                    TxError::NonceTooHigh { expected, actual } => {
+                        Err(EthError::NonceTooHigh { expected, actual })
+                    }
                 },
-                StateError::VMError(_vm_error) => {
+                StateError::VMError(vm_error) => {
                     #[cfg(feature = "debug_print")]
-                    println!("{:#?}", _vm_error);
+                    println!("{:#?}", vm_error);
 
-                    Err(EthError::VMError)
+                    match vm_error.error_kind {
+                        VMErrorKind::StepLimitExceeded => Err(EthError::StepLimitExceeded),
+                        VMErrorKind::MemoryLimitExceeded => Err(EthError::MemoryLimitExceeded),
+                        VMErrorKind::Timeout => Err(EthError::Timeout),
+                        _ => Err(EthError::VMError(
+                            self.describe_vm_error(&vm_error, to_addr),
+                        )),
+                    }
                 }
             },
         }
     }
 
+    /// Error kind and position, with the Solidity location if the failing
+    /// contract was deployed from source
+    fn describe_vm_error(&self, vm_error: &VMError, address: Option<Address>) -> String {
+        let kind = match &vm_error.error_kind {
+            VMErrorKind::Revert(output) => format!(
+                "Revert(0x{})",
+                output
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>()
+            ),
+            kind => format!("{:?}", kind),
+        };
+        let mut message = format!(
+            "{} at {} (pc 0x{:04x})",
+            kind, vm_error.instruction, vm_error.pc
+        );
+
+        let location = address.and_then(|address| {
+            let code = self.state.account_get_code(&address)?;
+            self.contracts
+                .get(&address)?
+                .source_location(code, vm_error.pc)
+        });
+        if let Some(location) = location {
+            message.push_str(&format!("\n  --> {}", location));
+        }
+
+        message
+    }
+}
+
+impl EthApi for EthSimulator {
+    fn account_add(&mut self, name: &str) -> Result<EthResult, EthError> {
+        self.check_name(name)?;
+        let address = self.state.account_add(name);
+        Ok(EthResult::Address(String::ethfrom(&address)))
+    }
+
+    fn account_import(&mut self, name: &str, private_key: &str) -> Result<EthResult, EthError> {
+        self.check_name(name)?;
+        let private_key = private_key.trim();
+        let private_key = Secret::from_str(private_key.strip_prefix("0x").unwrap_or(private_key))
+            .map_err(|_| EthError::InvalidPrivateKey)?;
+        let account = Account::from_private_key(name.to_string(), private_key)
+            .ok_or(EthError::InvalidPrivateKey)?;
+
+        let address = self
+            .state
+            .account_import(account)
+            .ok_or(EthError::ExistedAddress)?;
+        Ok(EthResult::Address(String::ethfrom(&address)))
+    }
+
+    fn account_rename(&mut self, address: &str, name: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .get_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        // keeping its name is fine
+        let is_alias = self.state.aliases().contains_key(name);
+        if !is_alias && self.state.account_query_address_by_name(name) == Some(address) {
+            return Ok(EthResult::None);
+        }
+        self.check_name(name)?;
+
+        self.state.account_rename(&address, name);
+        Ok(EthResult::None)
+    }
+
+    fn alias_add(&mut self, name: &str, address: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        self.check_name(name)?;
+
+        self.state.alias_add(name, address);
+        Ok(EthResult::None)
+    }
+
+    fn alias_remove(&mut self, name: &str) -> Result<EthResult, EthError> {
+        if !self.state.alias_remove(name) {
+            return Err(EthError::NotExistedName);
+        }
+        Ok(EthResult::None)
+    }
+
+    fn alias_list(&self) -> Result<EthResult, EthError> {
+        Ok(EthResult::AliasList(
+            self.state
+                .aliases()
+                .iter()
+                .map(|(name, address)| AliasInfo {
+                    name: name.clone(),
+                    address: String::ethfrom(address),
+                })
+                .collect(),
+        ))
+    }
+
+    fn account_export_key(&self, address: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .get_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        let private_key = self
+            .state
+            .account_get_private_key(&address)
+            .ok_or(EthError::NoPrivateKey)?;
+
+        Ok(EthResult::PrivateKey(String::ethfrom(private_key)))
+    }
+
+    fn account_list(&self) -> Result<EthResult, EthError> {
+        Ok(EthResult::AccountList(
+            self.state
+                .account_list()
+                .iter()
+                .map(|(name, address, balance)| AccountInfo {
+                    name: name.to_string(),
+                    address: String::ethfrom(*address),
+                    balance: *balance,
+                })
+                .collect(),
+        ))
+    }
+
+    fn account_balance(&self, address: &str) -> Result<EthResult, EthError> {
+        Ok(EthResult::Balance(
+            self.state
+                .account_get_balance(
+                    &self
+                        .get_address(address)
+                        .ok_or(EthError::NotExistedAddress)?,
+                )
+                .unwrap(),
+        ))
+    }
+
+    fn account_nonce(&self, address: &str) -> Result<EthResult, EthError> {
+        Ok(EthResult::Value(U256::from(
+            self.state
+                .account_get_nonce(
+                    &self
+                        .get_address(address)
+                        .ok_or(EthError::NotExistedAddress)?,
+                )
+                .unwrap(),
+        )))
+    }
+
+    fn account_storage(&self, address: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .get_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+
+        Ok(EthResult::Storage(
+            self.state
+                .account_get_storage(&address)
+                .unwrap()
+                .iter()
+                .map(|(slot, value)| StorageSlotInfo {
+                    slot: String::ethfrom(slot),
+                    value: String::ethfrom(value),
+                })
+                .collect(),
+        ))
+    }
+
+    fn account_set_balance(&mut self, address: &str, balance: U256) -> Result<EthResult, EthError> {
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        self.state.account_set_balance(&address, balance);
+        Ok(EthResult::None)
+    }
+
+    fn account_set_nonce(&mut self, address: &str, nonce: usize) -> Result<EthResult, EthError> {
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        self.state.account_set_nonce(&address, nonce);
+        Ok(EthResult::None)
+    }
+
+    fn account_set_code(&mut self, address: &str, code: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        let code = code.trim();
+        let code = code.strip_prefix("0x").unwrap_or(code).to_lowercase();
+        if !code.len().is_multiple_of(2) || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(EthError::InvalidCode);
+        }
+
+        self.state
+            .account_set_code(&address, Code::ethfrom(code.as_str()));
+        // the source of a compiled contract no longer matches the code
+        self.contracts.remove(&address);
+        Ok(EthResult::None)
+    }
+
+    fn account_set_storage(
+        &mut self,
+        address: &str,
+        slot: U256,
+        value: U256,
+    ) -> Result<EthResult, EthError> {
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        self.state
+            .account_set_storage(&address, H256::ethfrom(slot), H256::ethfrom(value));
+        Ok(EthResult::None)
+    }
+
+    fn account_impersonate(&mut self, address: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        self.state.impersonate(address);
+        Ok(EthResult::None)
+    }
+
+    fn account_stop_impersonating(&mut self, address: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        if !self.state.stop_impersonating(&address) {
+            return Err(EthError::NotImpersonated);
+        }
+        Ok(EthResult::None)
+    }
+
+    fn tx_send(
+        &mut self,
+        from: &str,
+        to: &str,
+        value: U256,
+        data: &str,
+        nonce: Option<usize>,
+    ) -> Result<EthResult, EthError> {
+        let options = TxOptions {
+            nonce,
+            ..TxOptions::default()
+        };
+        self.tx_send_inner(from, to, value, data, options, false)
+    }
+
+    fn tx_send_with(
+        &mut self,
+        from: &str,
+        to: &str,
+        value: U256,
+        data: &str,
+        options: TxOptions,
+    ) -> Result<EthResult, EthError> {
+        self.tx_send_inner(from, to, value, data, options, false)
+    }
+
+    fn tx_list(&self) -> Result<EthResult, EthError> {
+        Ok(EthResult::TxList(
+            self.state
+                .tx_list()
+                .into_iter()
+                .map(|(block, tx, success)| {
+                    let to = match tx.tx_type() {
+                        TxType::DeployContract => None,
+                        _ => Some(tx.to()),
+                    };
+                    TxInfo {
+                        hash: String::ethfrom(&tx.hash()),
+                        block,
+                        from: String::ethfrom(tx.from()),
+                        from_name: self.label(tx.from()),
+                        to: to.map(String::ethfrom).unwrap_or_default(),
+                        to_name: to.map(|to| self.label(to)).unwrap_or_default(),
+                        success,
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    fn tx_logs(&self, tx_hash: &str) -> Result<EthResult, EthError> {
+        let tx_hash = Self::parse_tx_hash(tx_hash).ok_or(EthError::NotExistedTx)?;
+        let logs = self.state.tx_logs(&tx_hash).ok_or(EthError::NotExistedTx)?;
+
+        Ok(EthResult::Logs(
+            logs.iter()
+                .map(|log| LogInfo {
+                    address: String::ethfrom(&log.address),
+                    name: self.label(&log.address),
+                    topics: log.topics.iter().map(String::ethfrom).collect(),
+                    data: String::ethfrom(log.data.as_slice()),
+                })
+                .collect(),
+        ))
+    }
+
+    fn tx_receipt(&self, tx_hash: &str) -> Result<EthResult, EthError> {
+        let tx_hash = Self::parse_tx_hash(tx_hash).ok_or(EthError::NotExistedTx)?;
+        let (number, block) = self
+            .state
+            .tx_block(&tx_hash)
+            .ok_or(EthError::NotExistedTx)?;
+        let tx = block.get_tx();
+        let is_create = tx.tx_type() == TxType::DeployContract;
+        let gas_price = tx.effective_gas_price(block.get_base_fee());
+
+        Ok(EthResult::Receipt(ReceiptInfo {
+            transaction_hash: String::ethfrom(&tx_hash),
+            block_number: number,
+            from: String::ethfrom(tx.from()),
+            to: (!is_create).then(|| String::ethfrom(tx.to())),
+            contract_address: is_create
+                .then(|| String::ethfrom(&hash::create_address(tx.from(), tx.nonce()))),
+            tx_type: tx.envelope_type(),
+            status: block.is_success(),
+            gas_used: block.get_gas_used(),
+            effective_gas_price: gas_price,
+            fee: gas_price * block.get_gas_used(),
+            burnt_fee: block.get_base_fee() * block.get_gas_used(),
+        }))
+    }
+
+    fn tx_state_diff(&self, tx_hash: &str) -> Result<EthResult, EthError> {
+        let tx_hash = Self::parse_tx_hash(tx_hash).ok_or(EthError::NotExistedTx)?;
+        let diff = self
+            .state
+            .tx_state_diff(&tx_hash)
+            .ok_or(EthError::NotExistedTx)?;
+
+        let accounts = |accounts: BTreeMap<Address, AccountDiff>| {
+            accounts
+                .into_iter()
+                .map(|(address, account)| {
+                    let info = AccountDiffInfo {
+                        balance: account.balance,
+                        nonce: account.nonce,
+                        code: account.code.map(|code| String::ethfrom(code.as_slice())),
+                        storage: account
+                            .storage
+                            .iter()
+                            .map(|(key, value)| (String::ethfrom(key), String::ethfrom(value)))
+                            .collect(),
+                    };
+                    (String::ethfrom(&address), info)
+                })
+                .collect()
+        };
+
+        Ok(EthResult::StateDiff(StateDiffInfo {
+            pre: accounts(diff.pre),
+            post: accounts(diff.post),
+        }))
+    }
+
     fn contract_deploy(&mut self, from: &str, contract_file: &str) -> Result<EthResult, EthError> {
         let contract = Path::new(contract_file);
 
-        if let Ok(result) = solc::compile(contract) {
-            self.tx_send(from, path::get_file_name(contract), 0, &result)
+        if let Ok(compiled) = solc::compile(contract) {
+            // deploying a contract again names it like `Token_1`
+            let name = self.unused_name(path::get_file_name(contract));
+            let result = self.tx_send(from, &name, U256::zero(), &compiled.bin, None)?;
+            if let Some(address) = self.get_address(&name) {
+                self.contracts.insert(address, compiled);
+            }
+            Ok(result)
         } else {
             Err(EthError::CompileError)
         }
@@ -153,9 +735,542 @@ impl EthApi for EthSimulator {
         contract: &str,
         input: &str,
     ) -> Result<EthResult, EthError> {
-        if self.get_address(contract) == None {
-            return Err(EthError::NotExistedContract);
+        let address = self
+            .get_address(contract)
+            .ok_or(EthError::NotExistedContract)?;
+        // view functions run in static mode so that accidental writes are caught
+        let is_view = self
+            .contracts
+            .get(&address)
+            .is_some_and(|compiled| compiled.is_view_call(&Bytes::ethfrom(input)));
+
+        self.tx_send_inner(
+            from,
+            contract,
+            U256::zero(),
+            input,
+            TxOptions::default(),
+            is_view,
+        )
+    }
+
+    fn contract_cfg(&self, contract: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .get_address(contract)
+            .ok_or(EthError::NotExistedContract)?;
+        if !self.state.address_is_contract(&address) {
+            return Err(EthError::CallEoAAccount);
+        }
+
+        let code = self.state.account_get_code(&address).unwrap();
+        Ok(EthResult::Dot(Cfg::new(code).to_dot(contract)))
+    }
+
+    fn contract_storage(&self, contract: &str) -> Result<EthResult, EthError> {
+        let address = self
+            .get_address(contract)
+            .ok_or(EthError::NotExistedContract)?;
+        let layout = self
+            .contracts
+            .get(&address)
+            .map(|compiled| &compiled.storage_layout)
+            .filter(|layout| !layout.is_null())
+            .ok_or(EthError::NoStorageLayout)?;
+
+        let variables = Layout::new(
+            layout,
+            |key: &H256| self.state.storage(&address, key),
+            self.state.keccak_preimages(),
+        )
+        .decode()
+        .ok_or(EthError::NoStorageLayout)?;
+
+        Ok(EthResult::StorageLayout(
+            variables
+                .into_iter()
+                .map(|variable| StorageVariableInfo {
+                    name: variable.name,
+                    label: variable.label,
+                    slot: String::ethfrom(&variable.slot),
+                    offset: variable.offset,
+                    value: variable.value,
+                })
+                .collect(),
+        ))
+    }
+
+    fn state_dump(&self, block: Option<usize>) -> Result<EthResult, EthError> {
+        let dump = self.state.dump(block).ok_or(EthError::NotExistedBlock)?;
+        Ok(EthResult::Dump(
+            serde_json::to_string_pretty(&dump).unwrap(),
+        ))
+    }
+
+    fn fee_history(
+        &self,
+        block_count: usize,
+        newest_block: Option<usize>,
+        reward_percentiles: &[f64],
+    ) -> Result<EthResult, EthError> {
+        let in_range = reward_percentiles
+            .iter()
+            .all(|percentile| (0.0..=100.0).contains(percentile));
+        let ascending = reward_percentiles.windows(2).all(|pair| pair[0] <= pair[1]);
+        if !in_range || !ascending {
+            return Err(EthError::InvalidPercentiles);
+        }
+
+        let newest = match newest_block {
+            Some(number) if number < self.state.block_count() => number,
+            None if self.state.block_count() > 0 => self.state.block_count() - 1,
+            _ => return Err(EthError::NotExistedBlock),
+        };
+        let oldest = newest + 1 - block_count.min(newest + 1);
+        let blocks: Vec<_> = (oldest..=newest)
+            .filter_map(|number| self.state.block(number))
+            .collect();
+        let gas_limit = self.state.block_gas_limit().low_u64() as f64;
+
+        let mut base_fee_per_gas: Vec<U256> =
+            blocks.iter().map(|block| block.get_base_fee()).collect();
+        base_fee_per_gas.push(match self.state.block(newest + 1) {
+            Some(block) => block.get_base_fee(),
+            None => self.state.base_fee(),
+        });
+        // each block has one transaction, whose priority fee is at every percentile
+        let reward = if reward_percentiles.is_empty() {
+            Vec::new()
+        } else {
+            blocks
+                .iter()
+                .map(|block| {
+                    let base_fee = block.get_base_fee();
+                    let reward = block.get_tx().effective_gas_price(base_fee) - base_fee;
+                    vec![reward; reward_percentiles.len()]
+                })
+                .collect()
+        };
+
+        Ok(EthResult::FeeHistory(FeeHistoryInfo {
+            oldest_block: oldest,
+            base_fee_per_gas,
+            gas_used_ratio: blocks
+                .iter()
+                .map(|block| block.get_gas_used() as f64 / gas_limit)
+                .collect(),
+            reward,
+        }))
+    }
+
+    fn coverage_start(&mut self) -> Result<EthResult, EthError> {
+        self.state.coverage_start();
+        Ok(EthResult::None)
+    }
+
+    fn coverage_report(&self) -> Result<EthResult, EthError> {
+        let coverage = self.state.coverage().ok_or(EthError::CoverageNotStarted)?;
+        let contracts: Vec<(&solc::Contract, &Code)> = self
+            .contracts
+            .iter()
+            .filter_map(|(address, contract)| {
+                Some((contract, self.state.account_get_code(address)?))
+            })
+            .collect();
+
+        Ok(EthResult::Lcov(lcov::report(&contracts, coverage)))
+    }
+
+    fn state_root(&self, block: Option<usize>) -> Result<EthResult, EthError> {
+        let state_root = match block {
+            Some(number) => self
+                .state
+                .block_state_root(number)
+                .ok_or(EthError::NotExistedBlock)?,
+            None => self.state.state_root(),
+        };
+
+        Ok(EthResult::Hash(String::ethfrom(&state_root)))
+    }
+
+    fn account_proof(
+        &self,
+        address: &str,
+        storage_keys: &[U256],
+        block: Option<usize>,
+    ) -> Result<EthResult, EthError> {
+        // unknown addresses are proven absent
+        let address = self
+            .parse_address(address)
+            .ok_or(EthError::NotExistedAddress)?;
+        let keys: Vec<H256> = storage_keys.iter().map(|key| H256::ethfrom(*key)).collect();
+        let proof = self
+            .proof(&address, &keys, block)
+            .ok_or(EthError::NotExistedBlock)?;
+        let hex = |nodes: &[Bytes]| {
+            nodes
+                .iter()
+                .map(|node| String::ethfrom(node.as_slice()))
+                .collect()
+        };
+
+        Ok(EthResult::Proof(AccountProofInfo {
+            address: String::ethfrom(&proof.address),
+            account_proof: hex(&proof.proof),
+            balance: proof.balance,
+            code_hash: String::ethfrom(&proof.code_hash),
+            nonce: proof.nonce,
+            storage_hash: String::ethfrom(&proof.storage_root),
+            storage_proof: proof
+                .storage
+                .iter()
+                .map(|storage| StorageProofInfo {
+                    key: String::ethfrom(&storage.key),
+                    value: storage.value,
+                    proof: hex(&storage.proof),
+                })
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_save_load() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        assert!(eth_simulator.account_add("alice").is_ok());
+        assert!(eth_simulator.account_add("bob").is_ok());
+        assert!(eth_simulator
+            .tx_send("alice", "bob", U256::from(100), "", None)
+            .is_ok());
+        let state_root = eth_simulator.state.state_root();
+
+        let path = env::temp_dir().join(format!("eth-simulator-{}.json", std::process::id()));
+        eth_simulator.save(&path).unwrap();
+        let loaded = EthSimulator::from_state_file(ExecutionLimits::default(), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.state.state_root(), state_root);
+        assert_eq!(loaded.state.block_state_root(0), Some(state_root));
+        let bob = loaded.get_address("bob").unwrap();
+        assert!(loaded.state.account_get_private_key(&bob).is_some());
+        assert!(matches!(
+            loaded.account_nonce("alice"),
+            Ok(EthResult::Value(nonce)) if nonce == U256::one()
+        ));
+    }
+
+    #[test]
+    fn test_cheat_codes() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        let contract = "0x00000000000000000000000000000000000000aa";
+        assert!(eth_simulator.account_add("alice").is_ok());
+        // PUSH1 0 SLOAD PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+        assert!(eth_simulator
+            .account_set_code(contract, "60005460005260206000f3")
+            .is_ok());
+        assert!(eth_simulator
+            .account_set_storage(contract, U256::zero(), U256::from(7))
+            .is_ok());
+        assert!(eth_simulator
+            .account_set_balance("alice", U256::exp10(18))
+            .is_ok());
+        assert!(eth_simulator.account_set_nonce("alice", 3).is_ok());
+        assert!(matches!(
+            eth_simulator.account_set_code(contract, "0x6"),
+            Err(EthError::InvalidCode)
+        ));
+
+        assert!(matches!(
+            eth_simulator.account_balance("alice"),
+            Ok(EthResult::Balance(balance)) if balance == U256::exp10(18)
+        ));
+        assert!(matches!(
+            eth_simulator.contract_call("alice", contract, ""),
+            Ok(EthResult::Value(value)) if value == U256::from(7)
+        ));
+        assert!(matches!(
+            eth_simulator.account_nonce("alice"),
+            Ok(EthResult::Value(nonce)) if nonce == U256::from(4)
+        ));
+    }
+
+    #[test]
+    fn test_view_call_is_static() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        let contract = "0x00000000000000000000000000000000000000aa";
+        assert!(eth_simulator.account_add("alice").is_ok());
+        // PUSH1 1 PUSH1 0 SSTORE
+        assert!(eth_simulator
+            .account_set_code(contract, "6001600055")
+            .is_ok());
+        let abi = serde_json::json!([
+            {"type": "function", "name": "get", "inputs": [], "stateMutability": "view"},
+        ]);
+        eth_simulator.contracts.insert(
+            eth_simulator.get_address(contract).unwrap(),
+            solc::Contract {
+                bin: String::new(),
+                bin_runtime: String::from("6001600055"),
+                abi,
+                srcmap_runtime: String::new(),
+                sources: Vec::new(),
+                storage_layout: serde_json::Value::Null,
+            },
+        );
+
+        // get() writes although it is view
+        assert!(matches!(
+            eth_simulator.contract_call("alice", contract, "6d4ce63c"),
+            Err(EthError::VMError(message)) if message.starts_with("StaticStateChange")
+        ));
+        // inputs not selecting a view function may write
+        assert!(eth_simulator
+            .contract_call("alice", contract, "00000000")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_impersonate() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        let whale = "0x00000000000000000000000000000000000000aa";
+        assert!(eth_simulator.account_add("alice").is_ok());
+        assert!(eth_simulator
+            .account_set_balance(whale, U256::exp10(18))
+            .is_ok());
+        assert!(matches!(
+            eth_simulator.tx_send(whale, "alice", U256::from(100), "", None),
+            Err(EthError::NoPrivateKey)
+        ));
+
+        assert!(eth_simulator.account_impersonate(whale).is_ok());
+        assert!(eth_simulator
+            .tx_send(whale, "alice", U256::from(100), "", None)
+            .is_ok());
+        // impersonated senders need not exist, but have to pay the base fee
+        let unknown = "0x00000000000000000000000000000000000000bb";
+        assert!(eth_simulator.account_impersonate(unknown).is_ok());
+        assert!(matches!(
+            eth_simulator.tx_send(unknown, whale, U256::zero(), "", None),
+            Err(EthError::NotEnoughBalance)
+        ));
+        assert!(eth_simulator
+            .tx_send(whale, unknown, U256::exp10(17), "", None)
+            .is_ok());
+        assert!(eth_simulator
+            .tx_send(unknown, whale, U256::zero(), "", None)
+            .is_ok());
+        assert!(matches!(
+            eth_simulator.account_nonce(unknown),
+            Ok(EthResult::Value(nonce)) if nonce == U256::one()
+        ));
+
+        assert!(eth_simulator.account_stop_impersonating(whale).is_ok());
+        assert!(matches!(
+            eth_simulator.tx_send(whale, "alice", U256::from(100), "", None),
+            Err(EthError::NoPrivateKey)
+        ));
+        assert!(matches!(
+            eth_simulator.account_stop_impersonating(whale),
+            Err(EthError::NotImpersonated)
+        ));
+    }
+
+    #[test]
+    fn test_gas() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        assert!(eth_simulator.account_add("alice").is_ok());
+        assert!(eth_simulator.account_add("bob").is_ok());
+        let balance = match eth_simulator.account_balance("alice") {
+            Ok(EthResult::Balance(balance)) => balance,
+            _ => panic!("no balance"),
+        };
+        let base_fee = eth_simulator.state.base_fee();
+        let options = |gas| TxOptions {
+            gas: Some(gas),
+            gas_price: Some(base_fee + 2),
+            ..TxOptions::default()
+        };
+
+        assert!(matches!(
+            eth_simulator.tx_send_with("alice", "bob", U256::from(100), "", options(20000)),
+            Err(EthError::IntrinsicGasTooLow {
+                intrinsic: 21000,
+                limit: 20000
+            })
+        ));
+        assert!(eth_simulator
+            .tx_send_with("alice", "bob", U256::from(100), "", options(30000))
+            .is_ok());
+
+        // the gas left is refunded, the base fee of the gas used is burnt
+        // and the rest of its fee goes to the coinbase
+        assert!(matches!(
+            eth_simulator.account_balance("alice"),
+            Ok(EthResult::Balance(left)) if left == balance - 100 - (base_fee + 2) * 21000
+        ));
+        assert!(matches!(
+            eth_simulator.account_balance(&String::ethfrom(&Address::zero())),
+            Ok(EthResult::Balance(fee)) if fee == U256::from(42000)
+        ));
+        let tx_hash = match eth_simulator.tx_list() {
+            Ok(EthResult::TxList(txs)) => txs[0].hash.clone(),
+            _ => panic!("no transactions"),
+        };
+        assert!(matches!(
+            eth_simulator.tx_receipt(&tx_hash),
+            Ok(EthResult::Receipt(receipt)) if receipt.gas_used == 21000 && receipt.status
+        ));
+    }
+
+    #[test]
+    fn test_fee_history() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        assert!(eth_simulator.account_add("alice").is_ok());
+        assert!(eth_simulator.account_add("bob").is_ok());
+        assert!(matches!(
+            eth_simulator.fee_history(1, None, &[]),
+            Err(EthError::NotExistedBlock)
+        ));
+        for _ in 0..3 {
+            assert!(eth_simulator
+                .tx_send("alice", "bob", U256::from(100), "", None)
+                .is_ok());
+        }
+
+        match eth_simulator.fee_history(2, None, &[25.0, 75.0]) {
+            Ok(EthResult::FeeHistory(history)) => {
+                assert_eq!(history.oldest_block, 1);
+                // from 1 gwei the base fee falls by almost 1/8 for blocks using little gas,
+                // the default gas price is 10 wei above it
+                let base_fees: Vec<U256> = [875_175_000u64, 765_931_281, 670_323_909]
+                    .into_iter()
+                    .map(U256::from)
+                    .collect();
+                assert_eq!(history.base_fee_per_gas, base_fees);
+                assert_eq!(history.gas_used_ratio, vec![0.0007; 2]);
+                assert_eq!(history.reward, vec![vec![U256::from(10); 2]; 2]);
+            }
+            _ => panic!("no fee history"),
+        }
+        assert!(matches!(
+            eth_simulator.fee_history(2, None, &[75.0, 25.0]),
+            Err(EthError::InvalidPercentiles)
+        ));
+    }
+
+    #[test]
+    fn test_dump_import() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        let contract = "0x00000000000000000000000000000000000000aa";
+        assert!(eth_simulator.account_add("alice").is_ok());
+        assert!(eth_simulator
+            .account_set_code(contract, "60005460005260206000f3")
+            .is_ok());
+        assert!(eth_simulator
+            .account_set_storage(contract, U256::zero(), U256::from(7))
+            .is_ok());
+        let state_root = eth_simulator.state.state_root();
+
+        let path = env::temp_dir().join(format!("eth-simulator-dump-{}.json", std::process::id()));
+        match eth_simulator.state_dump(None) {
+            Ok(EthResult::Dump(dump)) => fs::write(&path, dump).unwrap(),
+            _ => panic!("dump failed"),
         }
-        self.tx_send(from, contract, 20, input)
+        let mut imported = EthSimulator::new(ExecutionLimits::default());
+        assert!(matches!(imported.import_dump(&path), Ok(2)));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(imported.state.state_root(), state_root);
+        assert!(imported.get_address("alice").is_some());
+        // imported accounts have no key and are impersonated
+        assert!(matches!(
+            imported.contract_call("alice", contract, ""),
+            Ok(EthResult::Value(value)) if value == U256::from(7)
+        ));
+    }
+
+    #[test]
+    fn test_names() {
+        let mut eth_simulator = EthSimulator::new(ExecutionLimits::default());
+        assert!(eth_simulator.account_add("alice").is_ok());
+        assert!(matches!(
+            eth_simulator.account_add("alice"),
+            Err(EthError::ExistedName)
+        ));
+        assert!(matches!(
+            eth_simulator.account_add("0xalice"),
+            Err(EthError::InvalidName)
+        ));
+
+        // malformed addresses are not found, and unknown ones are not deployed to
+        assert!(matches!(
+            eth_simulator.account_rename("0x12", "bob"),
+            Err(EthError::NotExistedAddress)
+        ));
+        assert!(matches!(
+            eth_simulator.account_storage("0xzz"),
+            Err(EthError::NotExistedAddress)
+        ));
+        assert!(matches!(
+            eth_simulator.contract_storage("0x12"),
+            Err(EthError::NotExistedContract)
+        ));
+        assert!(matches!(
+            eth_simulator.tx_send("alice", "0x12", U256::zero(), "6000", None),
+            Err(EthError::NotExistedAddress)
+        ));
+        assert!(matches!(
+            eth_simulator.tx_send("alice", "", U256::zero(), "6000", None),
+            Err(EthError::InvalidName)
+        ));
+
+        assert!(eth_simulator.account_rename("alice", "carol").is_ok());
+        assert!(eth_simulator.get_address("alice").is_none());
+        assert!(eth_simulator.account_add("alice").is_ok());
+
+        let usdc = "0x00000000000000000000000000000000000000aa";
+        assert!(eth_simulator.alias_add("usdc", usdc).is_ok());
+        assert!(matches!(
+            eth_simulator.alias_add("carol", usdc),
+            Err(EthError::ExistedName)
+        ));
+        // aliases name addresses without an account until one is created
+        assert!(matches!(
+            eth_simulator.tx_send("carol", "usdc", U256::zero(), "", None),
+            Err(EthError::NotExistedAddress)
+        ));
+        assert!(eth_simulator
+            .account_set_balance("usdc", U256::from(5))
+            .is_ok());
+        assert!(eth_simulator
+            .tx_send("carol", "usdc", U256::one(), "", None)
+            .is_ok());
+        match eth_simulator.tx_list() {
+            Ok(EthResult::TxList(txs)) => {
+                assert_eq!(
+                    (txs[0].from_name.as_str(), txs[0].to_name.as_str()),
+                    ("carol", "usdc")
+                )
+            }
+            _ => panic!("no transactions"),
+        }
+
+        // names of accounts created after a snapshot are freed by reverting
+        let id = eth_simulator.snapshot();
+        assert!(eth_simulator.account_add("bob").is_ok());
+        assert!(eth_simulator.revert(id));
+        assert!(eth_simulator.get_address("bob").is_none());
+        assert!(eth_simulator.account_add("bob").is_ok());
+
+        assert!(eth_simulator.alias_remove("usdc").is_ok());
+        assert!(matches!(
+            eth_simulator.alias_remove("usdc"),
+            Err(EthError::NotExistedName)
+        ));
     }
 }
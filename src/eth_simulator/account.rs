@@ -1,34 +1,62 @@
 mod storage;
 
+use ethereum_types::BigEndianHash;
+use rlp::RlpStream;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+
 use self::storage::Storage;
 use super::{
-    eth_types::{Address, Code, Secret, H256},
-    hash,
+    eth_types::{hex_bytes, Address, Bytes, Code, Secret, H256, U256},
+    hash, trie,
 };
 
-pub enum AccountError {
-    NotEnoughBalance,
-}
+/// Balance of new accounts with a keypair: 10000 ether
+const INITIAL_BALANCE: U256 = U256([0x19e0_c9ba_b240_0000, 0x21e, 0, 0]);
 
+#[derive(Clone, Serialize, Deserialize)]
 pub enum AccountType {
     EoA,
     Contract,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Account {
     name: String,
     account_type: AccountType,
-    #[allow(dead_code)]
-    private_key: Secret,
+    /// Only accounts created from a keypair have one
+    private_key: Option<Secret>,
     address: Address,
-    balance: usize,
+    balance: U256,
+    nonce: usize,
     code_hash: H256,
+    #[serde(with = "hex_bytes")]
     code: Code,
     storage: Storage,
 }
 
 impl Account {
-    pub fn new(name: String, code: Code) -> Self {
+    /// Account of a random keypair
+    pub fn new(name: String) -> Self {
+        loop {
+            if let Some(account) = Self::from_private_key(name.clone(), Secret::random()) {
+                return account;
+            }
+        }
+    }
+
+    /// `None` if `private_key` is not a valid secp256k1 secret key
+    pub fn from_private_key(name: String, private_key: Secret) -> Option<Self> {
+        let address = address_from_private_key(&private_key)?;
+        let mut account = Self::with_address(name, address, Code::new());
+        account.private_key = Some(private_key);
+        account.balance = INITIAL_BALANCE;
+
+        Some(account)
+    }
+
+    /// Account without private key at a given address, like that of a created contract
+    pub fn with_address(name: String, address: Address, code: Code) -> Self {
         Account {
             name,
             account_type: if code.len() == 0 {
@@ -36,45 +64,66 @@ impl Account {
             } else {
                 AccountType::Contract
             },
-            private_key: Secret::random(),
-            address: Address::random(),
-            balance: 100,
+            private_key: None,
+            address,
+            balance: U256::zero(),
+            nonce: 0,
             code_hash: hash::keccak(&code),
             code,
             storage: Storage::new(),
         }
     }
 
-    pub fn get_balance(&self) -> usize {
+    pub fn get_balance(&self) -> U256 {
         self.balance
     }
 
-    pub fn add_balance(&mut self, value: usize) {
-        self.balance += value;
+    pub fn set_balance(&mut self, balance: U256) {
+        self.balance = balance;
     }
 
-    pub fn sub_balance(&mut self, value: usize) -> Result<(), AccountError> {
-        if self.balance >= value {
-            self.balance -= value;
-            Ok(())
-        } else {
-            Err(AccountError::NotEnoughBalance)
-        }
+    pub fn get_nonce(&self) -> usize {
+        self.nonce
+    }
+
+    pub fn set_nonce(&mut self, nonce: usize) {
+        self.nonce = nonce;
+    }
+
+    pub fn increment_nonce(&mut self) {
+        self.nonce += 1;
     }
 
     pub fn get_address(&self) -> &Address {
         &self.address
     }
 
+    pub fn get_private_key(&self) -> Option<&Secret> {
+        self.private_key.as_ref()
+    }
+
     pub fn get_name(&self) -> &str {
         &self.name
     }
 
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
     pub fn get_code(&self) -> &Code {
         &self.code
     }
 
+    pub fn get_code_hash(&self) -> H256 {
+        self.code_hash
+    }
+
     pub fn set_code(&mut self, code: Code) {
+        self.account_type = if code.is_empty() {
+            AccountType::EoA
+        } else {
+            AccountType::Contract
+        };
         self.code = code;
         self.code_hash = hash::keccak(&self.code);
     }
@@ -83,10 +132,45 @@ impl Account {
         self.storage.set(key, value);
     }
 
+    /// Slots which were written, including those set back to zero
+    pub fn storage(&self) -> impl Iterator<Item = (&H256, &H256)> {
+        self.storage.iter()
+    }
+
     pub fn get_storage(&self, key: &H256) -> H256 {
         self.storage.get(key)
     }
 
+    /// Non-zero slots as entries of the storage trie, keyed by the keccak hash
+    /// of the slot with the RLP of the value as integer
+    pub fn storage_items(&self) -> Vec<(Bytes, Bytes)> {
+        self.storage
+            .iter()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(key, value)| {
+                (
+                    hash::keccak(key).as_bytes().to_vec(),
+                    rlp::encode(&value.into_uint()).to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn storage_root(&self) -> H256 {
+        trie::root(&self.storage_items())
+    }
+
+    /// Account as stored in the state trie: RLP of nonce, balance, storage root and code hash
+    pub fn rlp(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(4);
+        stream.append(&self.nonce);
+        stream.append(&self.balance);
+        stream.append(&self.storage_root());
+        stream.append(&self.code_hash);
+
+        stream.out().to_vec()
+    }
+
     pub fn is_contract(&self) -> bool {
         match self.account_type {
             AccountType::Contract => true,
@@ -94,3 +178,38 @@ impl Account {
         }
     }
 }
+
+/// Last 20 bytes of the keccak hash of the uncompressed public key
+fn address_from_private_key(private_key: &Secret) -> Option<Address> {
+    let secret_key = SecretKey::from_slice(private_key.as_bytes()).ok()?;
+    let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key);
+
+    Some(Address::from(hash::keccak(
+        &public_key.serialize_uncompressed()[1..],
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{super::eth_types::EthFrom, *};
+
+    #[test]
+    fn test_address_from_private_key() {
+        // first default account of Hardhat
+        let private_key =
+            Secret::from_str("ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80")
+                .unwrap();
+        assert_eq!(
+            address_from_private_key(&private_key),
+            Some(Address::ethfrom("f39fd6e51aad88f6f4ce6ab8827279cfffb92266"))
+        );
+        assert_eq!(address_from_private_key(&Secret::zero()), None);
+    }
+
+    #[test]
+    fn test_initial_balance() {
+        assert_eq!(INITIAL_BALANCE, U256::exp10(18) * 10000);
+    }
+}
@@ -1,7 +1,10 @@
+use serde::{Deserialize, Serialize};
+
 use std::collections::HashMap;
 
 use super::super::eth_types::H256;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Storage {
     storage: HashMap<H256, H256>,
 }
@@ -17,6 +20,11 @@ impl Storage {
         self.storage.insert(key, value);
     }
 
+    /// Slots which were written, including those set back to zero
+    pub fn iter(&self) -> impl Iterator<Item = (&H256, &H256)> {
+        self.storage.iter()
+    }
+
     pub fn get(&self, key: &H256) -> H256 {
         match self.storage.get(key) {
             Some(&v) => v,
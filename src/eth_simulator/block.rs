@@ -1,18 +1,48 @@
-use super::{eth_types::H256, hash, tx::Tx};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    eth_types::{H256, U256},
+    evm::Log,
+    hash,
+    tx::Tx,
+};
 
 #[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
 pub struct Block {
     tx: Tx,
+    gas_used: usize,
+    /// EIP-1559: wei burnt per unit of gas used
+    #[serde(default)]
+    base_fee: U256,
+    /// Whether the transaction succeeded, failed ones only keep their nonce and fee effects
+    success: bool,
+    logs: Vec<Log>,
+    /// Root of the world state after the transaction
+    state_root: H256,
     block_hash: H256,
     prev_block_hash: H256,
 }
 
 impl Block {
-    pub fn new(tx: Tx, prev_block_hash: H256) -> Self {
+    pub fn new(
+        tx: Tx,
+        gas_used: usize,
+        base_fee: U256,
+        success: bool,
+        logs: Vec<Log>,
+        state_root: H256,
+        prev_block_hash: H256,
+    ) -> Self {
         let tx_hash = tx.hash();
         Block {
             tx,
-            block_hash: hash::keccak(format!("{}{}", tx_hash, prev_block_hash)),
+            gas_used,
+            base_fee,
+            success,
+            logs,
+            state_root,
+            block_hash: hash::keccak(format!("{}{}{}", tx_hash, state_root, prev_block_hash)),
             prev_block_hash,
         }
     }
@@ -20,4 +50,29 @@ impl Block {
     pub fn get_hash(&self) -> H256 {
         self.block_hash
     }
+
+    pub fn get_tx(&self) -> &Tx {
+        &self.tx
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.success
+    }
+
+    /// Gas used by the transaction after refunds
+    pub fn get_gas_used(&self) -> usize {
+        self.gas_used
+    }
+
+    pub fn get_base_fee(&self) -> U256 {
+        self.base_fee
+    }
+
+    pub fn get_logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    pub fn get_state_root(&self) -> H256 {
+        self.state_root
+    }
 }
@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::{
+    account::Account,
+    eth_types::{Address, Code, H256, U256},
+};
+
+/// Accounts changed by a transaction, in the shape of the diff mode of geth's `prestateTracer`:
+/// `pre` holds the touched accounts before with the slots that changed,
+/// `post` only the fields that changed. Created accounts are not in `pre`.
+#[derive(Debug, Default, PartialEq)]
+pub struct StateDiff {
+    pub pre: BTreeMap<Address, AccountDiff>,
+    pub post: BTreeMap<Address, AccountDiff>,
+}
+
+/// Fields of an account, `None` for those left out
+#[derive(Debug, Default, PartialEq)]
+pub struct AccountDiff {
+    pub balance: Option<U256>,
+    pub nonce: Option<usize>,
+    pub code: Option<Code>,
+    pub storage: BTreeMap<H256, H256>,
+}
+
+impl StateDiff {
+    /// Difference of the `touched` accounts with their written slots between `pre` and `post`
+    pub fn new(
+        pre: &HashMap<Address, Account>,
+        post: &HashMap<Address, Account>,
+        touched: &BTreeMap<Address, BTreeSet<H256>>,
+    ) -> Self {
+        let mut diff = StateDiff::default();
+
+        for (address, keys) in touched {
+            let (before, after) = (pre.get(address), post.get(address));
+            let changed_keys: Vec<&H256> = keys
+                .iter()
+                .filter(|key| slot(before, key) != slot(after, key))
+                .collect();
+
+            let post_diff = match (before, after) {
+                (Some(before), Some(after)) => AccountDiff {
+                    balance: Some(after.get_balance())
+                        .filter(|balance| *balance != before.get_balance()),
+                    nonce: Some(after.get_nonce()).filter(|nonce| *nonce != before.get_nonce()),
+                    code: Some(after.get_code())
+                        .filter(|code| *code != before.get_code())
+                        .cloned(),
+                    storage: changed_keys
+                        .iter()
+                        .map(|key| (**key, after.get_storage(key)))
+                        .collect(),
+                },
+                (None, Some(after)) => AccountDiff {
+                    storage: changed_keys
+                        .iter()
+                        .map(|key| (**key, after.get_storage(key)))
+                        .collect(),
+                    ..AccountDiff::full(after)
+                },
+                _ => AccountDiff::default(),
+            };
+            let unchanged = before.is_some() && post_diff == AccountDiff::default();
+            if unchanged || after.is_none() {
+                continue;
+            }
+
+            if let Some(before) = before {
+                diff.pre.insert(
+                    *address,
+                    AccountDiff {
+                        storage: changed_keys
+                            .iter()
+                            .map(|key| (**key, before.get_storage(key)))
+                            .filter(|(_, value)| !value.is_zero())
+                            .collect(),
+                        ..AccountDiff::full(before)
+                    },
+                );
+            }
+            diff.post.insert(*address, post_diff);
+        }
+
+        diff
+    }
+}
+
+impl AccountDiff {
+    /// Balance, nonce and code of `account`, code only if it has some
+    fn full(account: &Account) -> Self {
+        AccountDiff {
+            balance: Some(account.get_balance()),
+            nonce: Some(account.get_nonce()),
+            code: Some(account.get_code().clone()).filter(|code| !code.is_empty()),
+            storage: BTreeMap::new(),
+        }
+    }
+}
+
+fn slot(account: Option<&Account>, key: &H256) -> H256 {
+    account.map_or(H256::zero(), |account| account.get_storage(key))
+}
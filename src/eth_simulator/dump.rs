@@ -0,0 +1,136 @@
+use ethereum_types::BigEndianHash;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use super::{
+    account::Account,
+    eth_types::{EthFrom, H256, U256},
+    genesis::{self, GenesisError},
+    hash,
+};
+use crate::utils::units;
+
+/// World state in the format of geth's `dump`
+#[derive(Serialize, Deserialize)]
+pub struct Dump {
+    pub root: String,
+    pub accounts: BTreeMap<String, DumpAccount>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpAccount {
+    /// Name the account can be referred to by, not part of the geth format
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Decimal like geth, hex is accepted too
+    pub balance: String,
+    pub nonce: u64,
+    #[serde(default)]
+    pub root: String,
+    #[serde(default)]
+    pub code_hash: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub code: String,
+    /// Values are trimmed hex without prefix like geth
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Keccak hash of the address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl Dump {
+    pub fn new<'a>(accounts: impl Iterator<Item = &'a Account>, root: H256) -> Self {
+        Dump {
+            root: String::ethfrom(&root),
+            accounts: accounts
+                .map(|account| {
+                    let address = String::ethfrom(account.get_address());
+                    (address.clone(), DumpAccount::new(account, address))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, GenesisError> {
+        Self::from_json(&fs::read_to_string(path).map_err(GenesisError::Io)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, GenesisError> {
+        serde_json::from_str(json).map_err(GenesisError::Json)
+    }
+
+    /// Accounts without private keys, the roots and code hashes of the dump are recomputed
+    pub fn into_accounts(self) -> Result<Vec<Account>, GenesisError> {
+        let mut accounts = Vec::new();
+        let mut names = HashSet::new();
+        for (address, account) in self.accounts {
+            let account = account.into_account(address)?;
+            genesis::check_name(&mut names, &account)?;
+            accounts.push(account);
+        }
+
+        Ok(accounts)
+    }
+}
+
+impl DumpAccount {
+    fn new(account: &Account, address: String) -> Self {
+        let storage = account
+            .storage()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(key, value)| {
+                let value = format!("{:x}", value.into_uint());
+                (String::ethfrom(key), value)
+            })
+            .collect();
+
+        DumpAccount {
+            name: account.get_name().to_string(),
+            balance: account.get_balance().to_string(),
+            nonce: account.get_nonce() as u64,
+            root: String::ethfrom(&account.storage_root()),
+            code_hash: String::ethfrom(&account.get_code_hash()),
+            code: if account.get_code().is_empty() {
+                String::new()
+            } else {
+                String::ethfrom(account.get_code().as_slice())
+            },
+            storage,
+            key: Some(String::ethfrom(&hash::keccak(account.get_address()))),
+            address: Some(address),
+        }
+    }
+
+    /// `key` of the accounts map is used if there is no `address` field
+    fn into_account(self, key: String) -> Result<Account, GenesisError> {
+        let address = self.address.unwrap_or(key);
+        let parsed = genesis::parse_address(&address)?;
+        let code =
+            genesis::parse_hex(&self.code).ok_or(GenesisError::InvalidCode(address.clone()))?;
+        let balance = units::parse_amount(&self.balance)
+            .ok_or(GenesisError::InvalidBalance(address.clone()))?;
+
+        let mut account = Account::with_address(self.name, parsed, code);
+        account.set_balance(balance);
+        account.set_nonce(self.nonce as usize);
+        for (key, value) in self.storage {
+            let key = units::parse_amount(&key)
+                .ok_or(GenesisError::InvalidStorageKey(address.clone()))?;
+            // values are hex with or without prefix
+            let value = U256::from_str_radix(value.trim().trim_start_matches("0x"), 16)
+                .map_err(|_| GenesisError::InvalidStorageValue(address.clone()))?;
+            account.set_storage(H256::ethfrom(key), H256::ethfrom(value));
+        }
+
+        Ok(account)
+    }
+}
@@ -3,7 +3,7 @@ use serde_json::Value;
 use std::{fmt::Write, str::FromStr};
 
 use ethereum_types::BigEndianHash;
-pub use ethereum_types::{Address, Secret, H256, U256};
+pub use ethereum_types::{Address, Secret, H256, U256, U512};
 
 // type for code
 pub type Code = Vec<u8>;
@@ -128,6 +128,28 @@ impl EthFrom<&Address> for String {
     }
 }
 
+impl EthFrom<&H256> for String {
+    fn ethfrom(obj: &H256) -> Self {
+        let mut s = String::from("0x");
+        for byte in obj.as_bytes() {
+            write!(&mut s, "{:02x}", byte).unwrap();
+        }
+
+        s
+    }
+}
+
+impl EthFrom<&[u8]> for String {
+    fn ethfrom(obj: &[u8]) -> Self {
+        let mut s = String::from("0x");
+        for byte in obj {
+            write!(&mut s, "{:02x}", byte).unwrap();
+        }
+
+        s
+    }
+}
+
 impl EthFrom<&Value> for String {
     fn ethfrom(obj: &Value) -> Self {
         obj.to_string()
@@ -176,6 +198,27 @@ pub mod num_op {
     }
 }
 
+/// Serde of bytes as `0x` prefixed hex string, for `#[serde(with = "hex_bytes")]`
+pub mod hex_bytes {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use super::EthFrom;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::ethfrom(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        let hex = hex.strip_prefix("0x").unwrap_or(&hex).to_lowercase();
+        if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(de::Error::custom("invalid hex bytes"));
+        }
+
+        Ok(Vec::ethfrom(hex.as_str()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -1,9 +1,23 @@
+mod cfg;
+mod coverage;
 mod ext;
+mod fork;
+mod gas;
+mod host;
 mod instructions;
+mod limits;
 mod memory;
 mod pc;
 mod stack;
+mod substate;
 mod vm;
 
+pub use cfg::Cfg;
+pub use coverage::Coverage;
 pub use ext::Ext;
-pub use vm::{VMError, VMResult, VM};
+pub use fork::Fork;
+pub use gas::{intrinsic_gas, next_base_fee};
+pub use host::{BlockEnv, Host, Log};
+pub use limits::ExecutionLimits;
+pub use substate::Substate;
+pub use vm::{VMError, VMErrorKind, VMResult, VM};
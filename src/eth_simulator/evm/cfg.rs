@@ -0,0 +1,284 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Write,
+};
+
+use super::{super::eth_types::Code, instructions::Instruction, pc::PC};
+
+const OP_PUSH4: u8 = 0x63;
+
+struct BasicBlock {
+    instructions: Vec<(usize, Instruction)>,
+    successors: Vec<usize>,
+    dynamic_jump: bool,
+}
+
+/// Control-flow graph of contract bytecode
+pub struct Cfg {
+    blocks: BTreeMap<usize, BasicBlock>,
+    /// Function selector and entry block of each branch of the dispatcher
+    selectors: Vec<(u32, usize)>,
+    dispatcher: BTreeSet<usize>,
+    reachable: BTreeSet<usize>,
+}
+
+impl Cfg {
+    pub fn new(code: &Code) -> Self {
+        let instructions = Self::disassemble(code);
+        let mut cfg = Cfg {
+            blocks: Self::split_blocks(instructions),
+            selectors: Vec::new(),
+            dispatcher: BTreeSet::new(),
+            reachable: BTreeSet::new(),
+        };
+
+        cfg.resolve_jumps();
+        cfg.find_dispatcher(code);
+        cfg.find_reachable();
+
+        cfg
+    }
+
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut dot = String::new();
+
+        writeln!(dot, "digraph \"{}\" {{", name).unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for (start, block) in &self.blocks {
+            let mut label = String::new();
+            for (offset, instruction) in &block.instructions {
+                write!(label, "0x{:04x}: {}\\l", offset, instruction).unwrap();
+            }
+            if block.dynamic_jump {
+                label.push_str("(dynamic jump)\\l");
+            }
+
+            let style = if !self.reachable.contains(start) {
+                ", style=dashed, color=gray, fontcolor=gray"
+            } else if self.dispatcher.contains(start) {
+                ", style=filled, fillcolor=lightblue"
+            } else {
+                ""
+            };
+            writeln!(dot, "    b{} [label=\"{}\"{}];", start, label, style).unwrap();
+        }
+        for (start, block) in &self.blocks {
+            for successor in &block.successors {
+                match self
+                    .selectors
+                    .iter()
+                    .find(|(_, entry)| entry == successor && self.dispatcher.contains(start))
+                {
+                    Some((selector, _)) => writeln!(
+                        dot,
+                        "    b{} -> b{} [label=\"0x{:08x}\"];",
+                        start, successor, selector
+                    )
+                    .unwrap(),
+                    None => writeln!(dot, "    b{} -> b{};", start, successor).unwrap(),
+                }
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    fn disassemble(code: &Code) -> Vec<(usize, Instruction)> {
+        let mut pc = PC::new(code.clone());
+        let mut instructions = Vec::new();
+
+        loop {
+            let offset = pc.pc().as_usize();
+            match pc.next() {
+                Some(instruction) => instructions.push((offset, instruction)),
+                None => break,
+            }
+        }
+
+        instructions
+    }
+
+    /// Blocks start at offset 0, at every JUMPDEST and after every instruction ending a block
+    fn split_blocks(instructions: Vec<(usize, Instruction)>) -> BTreeMap<usize, BasicBlock> {
+        let mut blocks = BTreeMap::new();
+        let mut current: Vec<(usize, Instruction)> = Vec::new();
+
+        for (offset, instruction) in instructions {
+            if instruction == Instruction::JUMPDEST && !current.is_empty() {
+                Self::push_block(&mut blocks, std::mem::take(&mut current));
+            }
+            current.push((offset, instruction));
+            if Self::ends_block(&instruction) {
+                Self::push_block(&mut blocks, std::mem::take(&mut current));
+            }
+        }
+        if !current.is_empty() {
+            Self::push_block(&mut blocks, current);
+        }
+
+        blocks
+    }
+
+    fn push_block(
+        blocks: &mut BTreeMap<usize, BasicBlock>,
+        instructions: Vec<(usize, Instruction)>,
+    ) {
+        blocks.insert(
+            instructions[0].0,
+            BasicBlock {
+                instructions,
+                successors: Vec::new(),
+                dynamic_jump: false,
+            },
+        );
+    }
+
+    fn ends_block(instruction: &Instruction) -> bool {
+        matches!(
+            instruction,
+            Instruction::JUMP
+                | Instruction::JUMPI
+                | Instruction::STOP
+                | Instruction::RETURN
+                | Instruction::REVERT
+                | Instruction::INVALID
+                | Instruction::SELFDESTRUCT
+        )
+    }
+
+    /// Connect blocks by fallthrough and by jumps whose target is pushed right before the jump
+    fn resolve_jumps(&mut self) {
+        let starts: Vec<usize> = self.blocks.keys().copied().collect();
+        let jumpdests: HashSet<usize> = self
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.instructions[0].1 == Instruction::JUMPDEST)
+            .map(|(start, _)| *start)
+            .collect();
+
+        for (i, start) in starts.iter().enumerate() {
+            let next = starts.get(i + 1).copied();
+            let block = self.blocks.get_mut(start).unwrap();
+            let n = block.instructions.len();
+            let last = block.instructions[n - 1].1;
+
+            let target = if n >= 2 {
+                match block.instructions[n - 2].1 {
+                    Instruction::PUSH(value) => Some(value),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            let jump_target = target
+                .filter(|value| value.bits() <= 64)
+                .map(|value| value.as_u64() as usize)
+                .filter(|value| jumpdests.contains(value));
+
+            match last {
+                Instruction::JUMP | Instruction::JUMPI => {
+                    match jump_target {
+                        Some(target) => block.successors.push(target),
+                        None => block.dynamic_jump = target.is_none(),
+                    }
+                    if last == Instruction::JUMPI {
+                        block.successors.extend(next);
+                    }
+                }
+                instruction if Self::ends_block(&instruction) => {}
+                _ => block.successors.extend(next),
+            }
+        }
+    }
+
+    /// Find branches of the form `PUSH4 selector (DUP) EQ PUSH dest JUMPI`
+    fn find_dispatcher(&mut self, code: &Code) {
+        for (start, block) in &self.blocks {
+            let n = block.instructions.len();
+            if n < 4
+                || block.instructions[n - 1].1 != Instruction::JUMPI
+                || block.instructions[n - 3].1 != Instruction::EQ
+                || block.successors.len() < 2
+            {
+                continue;
+            }
+
+            let selector = block.instructions[n.saturating_sub(6)..n - 3]
+                .iter()
+                .find_map(|(offset, instruction)| match instruction {
+                    Instruction::PUSH(value) if code[*offset] == OP_PUSH4 => Some(value.low_u32()),
+                    _ => None,
+                });
+            if let Some(selector) = selector {
+                self.selectors.push((selector, block.successors[0]));
+                self.dispatcher.insert(*start);
+            }
+        }
+    }
+
+    /// Blocks reachable from offset 0. The target of a dynamic jump is assumed
+    /// to be any JUMPDEST whose offset is pushed as a constant somewhere in the code.
+    fn find_reachable(&mut self) {
+        let pushed: HashSet<usize> = self
+            .blocks
+            .values()
+            .flat_map(|block| block.instructions.iter())
+            .filter_map(|(_, instruction)| match instruction {
+                Instruction::PUSH(value) if value.bits() <= 64 => Some(value.as_u64() as usize),
+                _ => None,
+            })
+            .collect();
+        let dynamic_targets: Vec<usize> = self
+            .blocks
+            .iter()
+            .filter(|(start, block)| {
+                block.instructions[0].1 == Instruction::JUMPDEST && pushed.contains(start)
+            })
+            .map(|(start, _)| *start)
+            .collect();
+
+        let mut worklist: Vec<usize> = self.blocks.keys().take(1).copied().collect();
+        let mut dynamic_added = false;
+        while let Some(start) = worklist.pop() {
+            if !self.reachable.insert(start) {
+                continue;
+            }
+
+            let block = &self.blocks[&start];
+            worklist.extend(block.successors.iter());
+            if block.dynamic_jump && !dynamic_added {
+                worklist.extend(dynamic_targets.iter());
+                dynamic_added = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::super::eth_types::EthFrom, *};
+
+    #[test]
+    fn test_cfg() {
+        // 0x00: PUSH1 0 CALLDATALOAD PUSH1 0xe0 SHR
+        // 0x06: DUP1 PUSH4 0x12345678 EQ PUSH1 0x12 JUMPI
+        // 0x10: STOP
+        // 0x11: INVALID
+        // 0x12: JUMPDEST PUSH1 1 PUSH1 0 SSTORE STOP
+        let code = Code::ethfrom("60003560e01c8063123456781460125700fe5b600160005500");
+        let cfg = Cfg::new(&code);
+
+        assert_eq!(
+            cfg.blocks.keys().copied().collect::<Vec<usize>>(),
+            vec![0x00, 0x10, 0x11, 0x12]
+        );
+        assert_eq!(cfg.blocks[&0x00].successors, vec![0x12, 0x10]);
+        assert_eq!(cfg.selectors, vec![(0x12345678, 0x12)]);
+        assert!(cfg.dispatcher.contains(&0x00));
+        assert!(!cfg.reachable.contains(&0x11));
+        assert!(cfg
+            .to_dot("test")
+            .contains("b0 -> b18 [label=\"0x12345678\"];"));
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use super::super::eth_types::H256;
+
+/// Executed instructions and JUMPI outcomes per code hash
+pub struct Coverage {
+    hits: HashMap<H256, HashMap<usize, usize>>,
+    branches: HashMap<H256, HashMap<usize, [usize; 2]>>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage {
+            hits: HashMap::new(),
+            branches: HashMap::new(),
+        }
+    }
+
+    pub fn record_pc(&mut self, code_hash: &H256, pc: usize) {
+        *self
+            .hits
+            .entry(*code_hash)
+            .or_default()
+            .entry(pc)
+            .or_default() += 1;
+    }
+
+    /// Count the outcome of the JUMPI at `pc`, index 0 is the jump, 1 the fallthrough
+    pub fn record_branch(&mut self, code_hash: &H256, pc: usize, taken: bool) {
+        self.branches
+            .entry(*code_hash)
+            .or_default()
+            .entry(pc)
+            .or_default()[if taken { 0 } else { 1 }] += 1;
+    }
+
+    /// Number of executions of each executed pc of the code
+    pub fn hits(&self, code_hash: &H256) -> Option<&HashMap<usize, usize>> {
+        self.hits.get(code_hash)
+    }
+
+    /// Outcome counts of each executed JUMPI of the code
+    pub fn branches(&self, code_hash: &H256) -> Option<&HashMap<usize, [usize; 2]>> {
+        self.branches.get(code_hash)
+    }
+}
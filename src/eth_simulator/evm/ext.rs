@@ -1,49 +1,305 @@
-use std::collections::HashMap;
+use std::time::Instant;
 
-use super::super::{
-    account::Account,
-    eth_types::{Address, EthFrom, H256, U256},
-    hash,
-    tx::Tx,
+use super::{
+    super::{
+        eth_types::{Address, Bytes, Code, EthFrom, H256, U256},
+        hash,
+        tx::Tx,
+    },
+    gas,
+    host::{BlockEnv, CallOutcome, Host, Log},
+    limits::ExecutionLimits,
+    substate::{Substate, SubstateCheckpoint},
 };
 
+/// Steps between two checks of the wall-clock timeout
+const TIMEOUT_CHECK_INTERVAL: usize = 1024;
+
 #[derive(Debug)]
 pub enum ExtError {
     NotExistedAddress(Address),
+    OutOfGas,
+    StaticStateChange,
+    StepLimitExceeded,
+    Timeout,
+}
+
+/// Parameters of a message call frame
+pub struct CallParams {
+    pub address: Address,
+    pub caller: Address,
+    pub value: U256,
+    pub input: Bytes,
+    pub gas: usize,
+    pub is_static: bool,
 }
 
-pub struct Ext<'a> {
+/// Point to undo the changes of a failed call frame to
+pub struct Checkpoint {
+    host: usize,
+    substate: SubstateCheckpoint,
+}
+
+/// Execution frame of a message call
+pub struct Ext<'a, H: Host> {
     account: Address,
-    accounts: &'a mut HashMap<Address, Account>,
+    caller: Address,
+    value: U256,
+    input: Bytes,
+    is_static: bool,
+    depth: usize,
+    host: &'a mut H,
     tx: &'a Tx,
-    chainid: usize,
+    substate: &'a mut Substate,
+    limits: &'a ExecutionLimits,
+    deadline: Instant,
+    gas_limit: usize,
     gas: usize,
 }
 
-impl<'a> Ext<'a> {
-    pub fn new(account: Address, accounts: &'a mut HashMap<Address, Account>, tx: &'a Tx) -> Self {
+impl<'a, H: Host> Ext<'a, H> {
+    /// Frame of the top level call of `tx` with the `gas` left after the intrinsic cost
+    pub fn new(
+        account: Address,
+        host: &'a mut H,
+        tx: &'a Tx,
+        substate: &'a mut Substate,
+        limits: &'a ExecutionLimits,
+        is_static: bool,
+        gas: usize,
+    ) -> Self {
         Ext {
             account,
-            accounts,
+            caller: *tx.from(),
+            value: tx.value(),
+            input: tx.data().clone(),
+            is_static,
+            depth: 0,
+            host,
             tx,
-            chainid: 0,
-            gas: 100,
+            substate,
+            limits,
+            deadline: Instant::now() + limits.timeout,
+            gas_limit: gas,
+            gas,
+        }
+    }
+
+    /// Frame of a message call made from this frame
+    pub fn child(&mut self, params: CallParams) -> Ext<'_, H> {
+        Ext {
+            account: params.address,
+            caller: params.caller,
+            value: params.value,
+            input: params.input,
+            is_static: params.is_static,
+            depth: self.depth + 1,
+            host: self.host,
+            tx: self.tx,
+            substate: self.substate,
+            limits: self.limits,
+            deadline: self.deadline,
+            gas_limit: params.gas,
+            gas: params.gas,
+        }
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+
+    /// Fail if the frame is not allowed to modify state
+    pub fn check_static(&self) -> Result<(), ExtError> {
+        if self.is_static {
+            Err(ExtError::StaticStateChange)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn limits(&self) -> &ExecutionLimits {
+        self.limits
+    }
+
+    /// Count an executed instruction against the step limit and the timeout
+    pub fn step(&mut self) -> Result<(), ExtError> {
+        let steps = self.substate.step();
+        if steps > self.limits.max_steps {
+            return Err(ExtError::StepLimitExceeded);
+        }
+        if steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && Instant::now() > self.deadline {
+            return Err(ExtError::Timeout);
         }
+        Ok(())
     }
 
-    pub fn set_storage(&mut self, key: U256, value: U256) {
-        self.accounts
-            .get_mut(&self.account)
-            .unwrap()
-            .set_storage(H256::ethfrom(key), H256::ethfrom(value));
+    pub fn record_pc(&mut self, code_hash: &H256, pc: usize) {
+        self.substate.record_pc(code_hash, pc);
     }
 
-    pub fn get_storage(&self, key: U256) -> U256 {
+    pub fn record_branch(&mut self, code_hash: &H256, pc: usize, taken: bool) {
+        self.substate.record_branch(code_hash, pc, taken);
+    }
+
+    pub fn address(&self) -> Address {
+        self.account
+    }
+
+    pub fn caller(&self) -> Address {
+        self.caller
+    }
+
+    pub fn gas_left(&self) -> usize {
+        self.gas
+    }
+
+    /// Give back gas which was forwarded to a child frame but not used
+    pub fn return_gas(&mut self, gas: usize) {
+        self.gas += gas;
+    }
+
+    pub fn get_code(&self, address: &Address) -> Code {
+        self.host.code(address).cloned().unwrap_or_default()
+    }
+
+    /// Address of a contract created by the current account with CREATE,
+    /// or with CREATE2 if `salt` is given. Increments the nonce of the current account.
+    pub fn create_address(&mut self, salt: Option<H256>, init_code: &[u8]) -> Address {
+        let address = match salt {
+            Some(salt) => hash::create2_address(&self.account, &salt, init_code),
+            None => hash::create_address(&self.account, self.host.nonce(&self.account).unwrap()),
+        };
+        self.host.increment_nonce(&self.account);
+
+        address
+    }
+
+    /// Add the account of a contract being created with nonce 1 (EIP-161),
+    /// return false if there already is a contract at the address
+    pub fn create_account(&mut self, address: &Address) -> bool {
+        let collision = self.host.nonce(address).is_some_and(|nonce| nonce > 0)
+            || self.host.code(address).is_some_and(|code| !code.is_empty());
+        if collision {
+            return false;
+        }
+
+        if !self.host.exists(address) {
+            self.host.create_account(address);
+        }
+        self.host.increment_nonce(address);
+        true
+    }
+
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint {
+            host: self.host.checkpoint(),
+            substate: self.substate.checkpoint(),
+        }
+    }
+
+    /// Undo the state changes, logs, refunds, slot accesses and transient storage writes since `checkpoint`
+    pub fn revert(&mut self, checkpoint: Checkpoint) {
+        self.host.revert_to_checkpoint(checkpoint.host);
+        self.substate.revert(checkpoint.substate);
+    }
+
+    pub fn set_code(&mut self, address: &Address, code: Code) {
+        self.host.set_code(address, code);
+    }
+
+    /// Let the host answer a message call instead of the VM
+    pub fn host_call(&mut self, params: &CallParams) -> Option<CallOutcome> {
+        self.host.call(params)
+    }
+
+    /// Transfer `value` from the current account to `to`, return whether it succeeded
+    pub fn transfer(&mut self, to: &Address, value: U256) -> bool {
+        if value.is_zero() {
+            return true;
+        }
+        if !self.host.exists(to) {
+            return false;
+        }
+
+        let balance = self.host.balance(&self.account).unwrap_or_default();
+        if balance < value {
+            return false;
+        }
+        self.host.set_balance(&self.account, balance - value);
+        let to_balance = self.host.balance(to).unwrap();
+        self.host.set_balance(to, to_balance + value);
+        true
+    }
+
+    pub fn use_gas(&mut self, cost: usize) -> Result<(), ExtError> {
+        if self.gas < cost {
+            self.gas = 0;
+            return Err(ExtError::OutOfGas);
+        }
+        self.gas -= cost;
+        Ok(())
+    }
+
+    pub fn gas_used(&self) -> usize {
+        self.gas_limit - self.gas
+    }
+
+    /// SSTORE with net gas metering, see `gas::sstore_cost`
+    pub fn set_storage(&mut self, key: U256, value: U256) -> Result<(), ExtError> {
+        self.check_static()?;
+        if self.gas <= gas::G_SSTORE_SENTRY {
+            return Err(ExtError::OutOfGas);
+        }
+
+        let key = H256::ethfrom(key);
+        let value = H256::ethfrom(value);
+        let current = self.host.storage(&self.account, &key);
+        let original = self.substate.original_storage(&self.account, &key, current);
+
+        let (mut cost, refund) = gas::sstore_cost(original, current, value);
+        if self.substate.access_storage(&self.account, &key) {
+            cost += gas::G_COLD_SLOAD;
+        }
+        self.use_gas(cost)?;
+        self.substate.add_refund(refund);
+
+        self.host.set_storage(&self.account, key, value);
+        Ok(())
+    }
+
+    pub fn get_storage(&mut self, key: U256) -> Result<U256, ExtError> {
+        let key = H256::ethfrom(key);
+        let value = self.host.storage(&self.account, &key);
+        self.substate.original_storage(&self.account, &key, value);
+
+        let cost = if self.substate.access_storage(&self.account, &key) {
+            gas::G_COLD_SLOAD
+        } else {
+            gas::G_WARM_STORAGE_READ
+        };
+        self.use_gas(cost)?;
+
+        Ok(U256::ethfrom(value))
+    }
+
+    pub fn set_transient_storage(&mut self, key: U256, value: U256) -> Result<(), ExtError> {
+        self.check_static()?;
+        self.substate.set_transient_storage(
+            &self.account,
+            H256::ethfrom(key),
+            H256::ethfrom(value),
+        );
+        Ok(())
+    }
+
+    pub fn get_transient_storage(&self, key: U256) -> U256 {
         U256::ethfrom(
-            self.accounts
-                .get(&self.account)
-                .unwrap()
-                .get_storage(&H256::ethfrom(key)),
+            self.substate
+                .get_transient_storage(&self.account, &H256::ethfrom(key)),
         )
     }
 
@@ -52,11 +308,38 @@ impl<'a> Ext<'a> {
     }
 
     pub fn get_chainid(&self) -> U256 {
-        U256::ethfrom(self.chainid)
+        self.host.block_env().chain_id
+    }
+
+    pub fn get_block_env(&self) -> BlockEnv {
+        self.host.block_env()
+    }
+
+    pub fn get_block_hash(&self, number: U256) -> U256 {
+        U256::ethfrom(self.host.block_hash(number))
+    }
+
+    /// Keccak hash of `data`, which the host may record
+    pub fn keccak(&mut self, data: &[u8]) -> H256 {
+        let hash = hash::keccak(data);
+        self.host.keccak_preimage(hash, data);
+        hash
+    }
+
+    /// Emit a log from the current account
+    pub fn log(&mut self, topics: Vec<H256>, data: Bytes) -> Result<(), ExtError> {
+        self.check_static()?;
+        self.use_gas(gas::G_LOG_DATA * data.len())?;
+        self.host.log(Log {
+            address: self.account,
+            topics,
+            data,
+        });
+        Ok(())
     }
 
     pub fn get_callvalue(&self) -> U256 {
-        U256::ethfrom(self.tx.value())
+        self.value
     }
 
     pub fn get_address(&self) -> U256 {
@@ -64,7 +347,10 @@ impl<'a> Ext<'a> {
     }
 
     pub fn get_balance(&self, address: &Address) -> Result<U256, ExtError> {
-        self.get_account_and_then(address, |account| U256::ethfrom(account.get_balance()))
+        match self.host.balance(address) {
+            Some(balance) => Ok(balance),
+            None => Err(ExtError::NotExistedAddress(*address)),
+        }
     }
 
     pub fn get_origin(&self) -> U256 {
@@ -72,69 +358,62 @@ impl<'a> Ext<'a> {
     }
 
     pub fn get_caller(&self) -> U256 {
-        U256::ethfrom(self.tx.from())
+        U256::ethfrom(&self.caller)
     }
 
+    /// 32 bytes of the input from `i`, bytes past its end are zero
     pub fn get_calldata(&self, i: U256) -> U256 {
-        let idx = i.as_usize();
-        U256::ethfrom(&self.tx.data()[idx..idx + 32])
+        let mut word = [0u8; 32];
+        if i < U256::from(self.input.len()) {
+            let idx = i.as_usize();
+            let end = self.input.len().min(idx + 32);
+            word[..end - idx].copy_from_slice(&self.input[idx..end]);
+        }
+        U256::from_big_endian(&word)
     }
 
     pub fn get_calldatasize(&self) -> U256 {
-        U256::ethfrom(self.tx.data().len())
+        U256::ethfrom(self.input.len())
     }
 
-    pub fn get_calldata_slice(&self, offset: U256, length: U256) -> &[u8] {
-        let off = offset.as_usize();
-        let len = length.as_usize();
-        &self.tx.data()[off..off + len]
+    pub fn get_calldata_all(&self) -> &[u8] {
+        &self.input
     }
 
     pub fn get_codesize(&self) -> U256 {
         self.get_ext_codesize(&self.account).unwrap()
     }
 
-    pub fn get_code_slice(&self, offset: U256, length: U256) -> &[u8] {
-        self.get_ext_code_slice(&self.account, offset, length)
-            .unwrap()
+    pub fn get_code_all(&self) -> &[u8] {
+        self.get_ext_code(&self.account).unwrap()
     }
 
     pub fn get_gasprice(&self) -> U256 {
-        U256::ethfrom(self.tx.gasprice())
+        self.tx.effective_gas_price(self.host.block_env().base_fee)
     }
 
     pub fn get_ext_codesize(&self, address: &Address) -> Result<U256, ExtError> {
-        self.get_account_and_then(address, |account| U256::ethfrom(account.get_code().len()))
+        self.get_code_and_then(address, |code| U256::ethfrom(code.len()))
     }
 
-    pub fn get_ext_code_slice(
-        &self,
-        address: &Address,
-        offset: U256,
-        length: U256,
-    ) -> Result<&[u8], ExtError> {
-        let off = offset.as_usize();
-        let len = length.as_usize();
-
-        match self.accounts.get(address) {
-            Some(account) => Ok(&account.get_code()[off..off + len]),
-            None => Err(ExtError::NotExistedAddress(address.clone())),
+    pub fn get_ext_code(&self, address: &Address) -> Result<&[u8], ExtError> {
+        match self.host.code(address) {
+            Some(code) => Ok(code),
+            None => Err(ExtError::NotExistedAddress(*address)),
         }
     }
 
     pub fn get_ext_code_hash(&self, address: &Address) -> Result<U256, ExtError> {
-        self.get_account_and_then(address, |account| {
-            U256::ethfrom(hash::keccak(account.get_code()))
-        })
+        self.get_code_and_then(address, |code| U256::ethfrom(hash::keccak(code)))
     }
 
-    fn get_account_and_then<T, F>(&self, address: &Address, f: F) -> Result<T, ExtError>
+    fn get_code_and_then<T, F>(&self, address: &Address, f: F) -> Result<T, ExtError>
     where
-        F: FnOnce(&Account) -> T,
+        F: FnOnce(&Code) -> T,
     {
-        match self.accounts.get(address) {
-            Some(account) => Ok(f(account)),
-            None => Err(ExtError::NotExistedAddress(address.clone())),
+        match self.host.code(address) {
+            Some(code) => Ok(f(code)),
+            None => Err(ExtError::NotExistedAddress(*address)),
         }
     }
 }
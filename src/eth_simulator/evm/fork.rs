@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+use super::gas::{MAX_REFUND_QUOTIENT, MAX_REFUND_QUOTIENT_FRONTIER};
+
+/// Hard fork whose rules apply. The VM implements the rules of the latest fork,
+/// earlier forks only change the rules handled here.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum Fork {
+    Frontier,
+    Homestead,
+    Byzantium,
+    Constantinople,
+    Istanbul,
+    Berlin,
+    London,
+    Shanghai,
+    #[default]
+    Cancun,
+}
+
+impl Fork {
+    /// Refunds are capped at gas_used / max_refund_quotient
+    pub fn max_refund_quotient(self) -> usize {
+        if self >= Fork::London {
+            MAX_REFUND_QUOTIENT
+        } else {
+            MAX_REFUND_QUOTIENT_FRONTIER
+        }
+    }
+
+    /// EIP-1559: blocks have a base fee and dynamic fee transactions are accepted
+    pub fn has_base_fee(self) -> bool {
+        self >= Fork::London
+    }
+}
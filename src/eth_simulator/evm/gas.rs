@@ -0,0 +1,305 @@
+use super::{
+    super::eth_types::{H256, U256, U512},
+    fork::Fork,
+    instructions::Instruction,
+};
+
+pub const G_ZERO: usize = 0;
+pub const G_JUMPDEST: usize = 1;
+pub const G_BASE: usize = 2;
+pub const G_VERYLOW: usize = 3;
+pub const G_LOW: usize = 5;
+pub const G_MID: usize = 8;
+pub const G_HIGH: usize = 10;
+pub const G_EXP: usize = 10;
+pub const G_SHA3: usize = 30;
+pub const G_LOG: usize = 375;
+pub const G_LOG_DATA: usize = 8;
+pub const G_CREATE: usize = 32000;
+/// Cost per byte of the code of a created contract
+pub const G_CODE_DEPOSIT: usize = 200;
+
+pub const G_WARM_STORAGE_READ: usize = 100;
+pub const G_COLD_SLOAD: usize = 2100;
+pub const G_SSTORE_SET: usize = 20000;
+pub const G_SSTORE_RESET: usize = 5000 - G_COLD_SLOAD;
+/// EIP-2200: SSTORE fails if no more than the call stipend is left
+pub const G_SSTORE_SENTRY: usize = 2300;
+
+pub const G_TRANSACTION: usize = 21000;
+/// Homestead: extra cost of a transaction creating a contract
+pub const G_TX_CREATE: usize = 32000;
+pub const G_TX_DATA_ZERO: usize = 4;
+/// EIP-2028: cost per non-zero byte of calldata
+pub const G_TX_DATA_NON_ZERO: usize = 16;
+pub const G_TX_DATA_NON_ZERO_FRONTIER: usize = 68;
+/// EIP-3860: cost per word of the init code of a creation transaction
+pub const G_INITCODE_WORD: usize = 2;
+
+pub const G_CALL_VALUE: usize = 9000;
+/// Free gas given to the callee of a value transfer
+pub const G_CALL_STIPEND: usize = 2300;
+
+/// EIP-3529: refund for clearing a storage slot
+pub const R_SSTORE_CLEARS: i64 = 4800;
+/// EIP-3529: refunds are capped at gas_used / MAX_REFUND_QUOTIENT
+pub const MAX_REFUND_QUOTIENT: usize = 5;
+/// Cap of refunds before EIP-3529
+pub const MAX_REFUND_QUOTIENT_FRONTIER: usize = 2;
+
+/// EIP-1559: the base fee changes by at most 1/8 from one block to the next
+pub const BASE_FEE_MAX_CHANGE_DENOMINATOR: usize = 8;
+/// EIP-1559: blocks target gas_limit / ELASTICITY_MULTIPLIER gas
+pub const ELASTICITY_MULTIPLIER: usize = 2;
+
+/// Static part of the gas cost of an instruction.
+/// Dynamic parts (storage access, SSTORE) are charged by Ext.
+pub fn base_cost(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::STOP | Instruction::RETURN | Instruction::REVERT => G_ZERO,
+        Instruction::SLOAD | Instruction::SSTORE => G_ZERO,
+        Instruction::JUMPDEST => G_JUMPDEST,
+        Instruction::ADDRESS
+        | Instruction::ORIGIN
+        | Instruction::CALLER
+        | Instruction::CALLVALUE
+        | Instruction::CALLDATASIZE
+        | Instruction::CODESIZE
+        | Instruction::GASPRICE
+        | Instruction::RETURNDATASIZE
+        | Instruction::COINBASE
+        | Instruction::TIMESTAMP
+        | Instruction::NUMBER
+        | Instruction::DIFFICULT
+        | Instruction::GASLIMIT
+        | Instruction::CHAINID
+        | Instruction::BASEFEE
+        | Instruction::POP
+        | Instruction::PC
+        | Instruction::MSIZE
+        | Instruction::GAS => G_BASE,
+        Instruction::ADD
+        | Instruction::SUB
+        | Instruction::NOT
+        | Instruction::LT
+        | Instruction::GT
+        | Instruction::SLT
+        | Instruction::SGT
+        | Instruction::EQ
+        | Instruction::ISZERO
+        | Instruction::AND
+        | Instruction::OR
+        | Instruction::XOR
+        | Instruction::BYTE
+        | Instruction::SHL
+        | Instruction::SHR
+        | Instruction::SAR
+        | Instruction::CALLDATALOAD
+        | Instruction::CALLDATACOPY
+        | Instruction::CODECOPY
+        | Instruction::RETURNDATACOPY
+        | Instruction::MLOAD
+        | Instruction::MSTORE
+        | Instruction::MSTORE8
+        | Instruction::PUSH(_)
+        | Instruction::DUP(_)
+        | Instruction::SWAP(_) => G_VERYLOW,
+        Instruction::MUL
+        | Instruction::DIV
+        | Instruction::SDIV
+        | Instruction::MOD
+        | Instruction::SMOD
+        | Instruction::SIGNEXTEND
+        | Instruction::SELFBALANCE => G_LOW,
+        Instruction::ADDMOD | Instruction::MULMOD | Instruction::JUMP => G_MID,
+        Instruction::JUMPI => G_HIGH,
+        Instruction::EXP => G_EXP,
+        Instruction::SHA3 => G_SHA3,
+        Instruction::BALANCE
+        | Instruction::EXTCODESIZE
+        | Instruction::EXTCODECOPY
+        | Instruction::EXTCODEHASH
+        | Instruction::CALL
+        | Instruction::CALLCODE
+        | Instruction::DELEGATCALL
+        | Instruction::STATICCALL
+        | Instruction::TLOAD
+        | Instruction::TSTORE => G_WARM_STORAGE_READ,
+        Instruction::BLOCKHASH => 20,
+        Instruction::LOG(i) => G_LOG * (i + 1),
+        Instruction::CREAT | Instruction::CREAT2 => G_CREATE,
+        Instruction::SELFDESTRUCT => 5000,
+        Instruction::INVALID => G_ZERO,
+    }
+}
+
+/// Gas charged for a transaction before execution: the base cost,
+/// the calldata and the creation of a contract with `data` as init code
+pub fn intrinsic_gas(data: &[u8], is_create: bool, fork: Fork) -> usize {
+    let non_zero_cost = if fork >= Fork::Istanbul {
+        G_TX_DATA_NON_ZERO
+    } else {
+        G_TX_DATA_NON_ZERO_FRONTIER
+    };
+    let zeros = data.iter().filter(|byte| **byte == 0).count();
+    let mut gas = G_TRANSACTION + zeros * G_TX_DATA_ZERO + (data.len() - zeros) * non_zero_cost;
+
+    if is_create && fork >= Fork::Homestead {
+        gas += G_TX_CREATE;
+    }
+    if is_create && fork >= Fork::Shanghai {
+        gas += data.len().div_ceil(32) * G_INITCODE_WORD;
+    }
+
+    gas
+}
+
+/// EIP-1559: base fee of the block after one with `base_fee` which used `gas_used`
+/// of its `gas_limit`. It rises when more than the target is used, and falls otherwise.
+/// Products are taken in 512 bits and the rise saturates, as any genesis values are accepted.
+pub fn next_base_fee(base_fee: U256, gas_used: usize, gas_limit: U256) -> U256 {
+    let target = gas_limit / ELASTICITY_MULTIPLIER;
+    let gas_used = U256::from(gas_used);
+    if target.is_zero() || gas_used == target {
+        return base_fee;
+    }
+
+    let denominator = U512::from(target) * U512::from(BASE_FEE_MAX_CHANGE_DENOMINATOR);
+    // the change is at most an eighth of the base fee, as the gas used is within the limit
+    let change = |gas: U256| {
+        U256::try_from(base_fee.full_mul(gas) / denominator).unwrap_or(U256::max_value())
+    };
+    if gas_used > target {
+        base_fee.saturating_add(change(gas_used - target).max(U256::one()))
+    } else {
+        base_fee - change(target - gas_used)
+    }
+}
+
+/// Gas forwarded to a child call: the requested amount capped at
+/// all but one 64th of the available gas (EIP-150)
+pub fn call_gas(available: usize, requested: U256) -> usize {
+    let max = available - available / 64;
+    if requested > U256::from(max) {
+        max
+    } else {
+        requested.as_usize()
+    }
+}
+
+/// Gas cost and refund change of an SSTORE under EIP-2200 with the
+/// EIP-2929 and EIP-3529 amendments, not counting the cold access surcharge.
+///
+/// `original` is the value of the slot at the start of the transaction,
+/// `current` is its value before this SSTORE.
+pub fn sstore_cost(original: H256, current: H256, new: H256) -> (usize, i64) {
+    if current == new {
+        return (G_WARM_STORAGE_READ, 0);
+    }
+
+    if original == current {
+        if original.is_zero() {
+            return (G_SSTORE_SET, 0);
+        }
+        let refund = if new.is_zero() { R_SSTORE_CLEARS } else { 0 };
+        return (G_SSTORE_RESET, refund);
+    }
+
+    let mut refund = 0;
+    if !original.is_zero() {
+        if current.is_zero() {
+            refund -= R_SSTORE_CLEARS;
+        } else if new.is_zero() {
+            refund += R_SSTORE_CLEARS;
+        }
+    }
+    if original == new {
+        refund += if original.is_zero() {
+            (G_SSTORE_SET - G_WARM_STORAGE_READ) as i64
+        } else {
+            (G_SSTORE_RESET - G_WARM_STORAGE_READ) as i64
+        };
+    }
+
+    (G_WARM_STORAGE_READ, refund)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(v: u64) -> H256 {
+        H256::from_low_u64_be(v)
+    }
+
+    #[test]
+    fn test_intrinsic_gas() {
+        assert_eq!(intrinsic_gas(&[], false, Fork::Cancun), 21000);
+        assert_eq!(intrinsic_gas(&[0, 1], false, Fork::Cancun), 21000 + 4 + 16);
+        assert_eq!(
+            intrinsic_gas(&[0, 1], false, Fork::Byzantium),
+            21000 + 4 + 68
+        );
+        assert_eq!(
+            intrinsic_gas(&[1; 33], true, Fork::Cancun),
+            53000 + 33 * 16 + 4
+        );
+        assert_eq!(
+            intrinsic_gas(&[1; 33], true, Fork::Frontier),
+            21000 + 33 * 68
+        );
+    }
+
+    #[test]
+    fn test_next_base_fee() {
+        let base_fee = U256::from(1_000_000_000);
+        let gas_limit = U256::from(30_000_000);
+        assert_eq!(next_base_fee(base_fee, 15_000_000, gas_limit), base_fee);
+        assert_eq!(
+            next_base_fee(base_fee, 30_000_000, gas_limit),
+            U256::from(1_125_000_000)
+        );
+        assert_eq!(
+            next_base_fee(base_fee, 0, gas_limit),
+            U256::from(875_000_000)
+        );
+        // a full block raises even a zero base fee
+        assert_eq!(
+            next_base_fee(U256::zero(), 30_000_000, gas_limit),
+            U256::one()
+        );
+        // huge base fees and gas limits of a genesis do not overflow
+        let max = U256::max_value();
+        assert_eq!(next_base_fee(max, 30_000_000, gas_limit), max);
+        assert_eq!(next_base_fee(max, 0, gas_limit), max - max / 8);
+        assert_eq!(next_base_fee(max, 0, max), max - max / 8);
+    }
+
+    #[test]
+    fn test_call_gas() {
+        assert_eq!(call_gas(6400, U256::from(1000)), 1000);
+        assert_eq!(call_gas(6400, U256::from(10000)), 6300);
+        assert_eq!(call_gas(6400, U256::max_value()), 6300);
+    }
+
+    #[test]
+    fn test_sstore_cost() {
+        // no-op
+        assert_eq!(sstore_cost(h(0), h(0), h(0)), (G_WARM_STORAGE_READ, 0));
+        // fresh slot
+        assert_eq!(sstore_cost(h(0), h(0), h(1)), (G_SSTORE_SET, 0));
+        // update and clear of a clean slot
+        assert_eq!(sstore_cost(h(1), h(1), h(2)), (G_SSTORE_RESET, 0));
+        assert_eq!(
+            sstore_cost(h(1), h(1), h(0)),
+            (G_SSTORE_RESET, R_SSTORE_CLEARS)
+        );
+        // dirty slot: undo a clear, then restore the original value
+        assert_eq!(
+            sstore_cost(h(1), h(0), h(1)),
+            (G_WARM_STORAGE_READ, -R_SSTORE_CLEARS + 2800)
+        );
+        // dirty slot set from zero and reset to zero again
+        assert_eq!(sstore_cost(h(0), h(1), h(0)), (G_WARM_STORAGE_READ, 19900));
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    super::eth_types::{hex_bytes, Address, Bytes, Code, H256, U256},
+    ext::CallParams,
+};
+
+/// Environment of the block a transaction is executed in
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BlockEnv {
+    pub number: U256,
+    pub coinbase: Address,
+    pub timestamp: U256,
+    pub difficulty: U256,
+    pub gas_limit: U256,
+    pub base_fee: U256,
+    pub chain_id: U256,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    #[serde(with = "hex_bytes")]
+    pub data: Bytes,
+}
+
+/// Result of a message call answered by the host
+pub struct CallOutcome {
+    pub success: bool,
+    pub output: Bytes,
+}
+
+/// World state and environment the VM executes against
+pub trait Host {
+    fn exists(&self, address: &Address) -> bool;
+
+    /// `None` for accounts which do not exist
+    fn balance(&self, address: &Address) -> Option<U256>;
+
+    /// Only called for existing accounts
+    fn set_balance(&mut self, address: &Address, balance: U256);
+
+    /// `None` for accounts which do not exist
+    fn nonce(&self, address: &Address) -> Option<usize>;
+
+    /// Only called for existing accounts
+    fn increment_nonce(&mut self, address: &Address);
+
+    /// `None` for accounts which do not exist
+    fn code(&self, address: &Address) -> Option<&Code>;
+
+    /// Only called for existing accounts
+    fn set_code(&mut self, address: &Address, code: Code);
+
+    /// Add an empty account for a contract being created
+    fn create_account(&mut self, address: &Address);
+
+    fn storage(&self, address: &Address, key: &H256) -> H256;
+
+    /// Only called for existing accounts
+    fn set_storage(&mut self, address: &Address, key: H256, value: H256);
+
+    fn block_env(&self) -> BlockEnv;
+
+    /// Hash of block `number`, zero if it is not one of the 256 most recent blocks
+    fn block_hash(&self, number: U256) -> H256;
+
+    fn log(&mut self, log: Log);
+
+    /// Position in the changes made so far to revert to if a call frame fails
+    fn checkpoint(&mut self) -> usize;
+
+    /// Undo all changes made since `checkpoint`, including logs
+    fn revert_to_checkpoint(&mut self, checkpoint: usize);
+
+    /// Input of a keccak hash computed by the code, mapping and array slots
+    /// are hashes of their keys
+    fn keccak_preimage(&mut self, _hash: H256, _preimage: &[u8]) {}
+
+    /// Answer a message call without executing the code of the callee,
+    /// `None` lets the VM execute it
+    fn call(&mut self, _params: &CallParams) -> Option<CallOutcome> {
+        None
+    }
+}
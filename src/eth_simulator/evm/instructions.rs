@@ -1,6 +1,8 @@
+use std::fmt;
+
 use super::super::eth_types::U256;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Instruction {
     STOP,
     ADD,
@@ -66,6 +68,8 @@ pub enum Instruction {
     MSIZE,
     GAS,
     JUMPDEST,
+    TLOAD,
+    TSTORE,
     PUSH(U256),
     DUP(usize),
     SWAP(usize),
@@ -81,3 +85,15 @@ pub enum Instruction {
     SELFDESTRUCT,
     INVALID,
 }
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::PUSH(value) => write!(f, "PUSH 0x{:x}", value),
+            Instruction::DUP(i) => write!(f, "DUP{}", i),
+            Instruction::SWAP(i) => write!(f, "SWAP{}", i),
+            Instruction::LOG(i) => write!(f, "LOG{}", i),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+/// Depth of message calls allowed by the EVM
+const MAX_CALL_DEPTH: usize = 1024;
+
+/// Bounds on the execution of a single transaction,
+/// protecting a shared simulator from runaway contracts
+#[derive(Clone, Debug)]
+pub struct ExecutionLimits {
+    /// Instructions executed over all call frames
+    pub max_steps: usize,
+    /// Memory of all call frames active at once in bytes
+    pub max_memory: usize,
+    /// Nesting depth of message calls, at most the 1024 of the EVM
+    pub max_call_depth: usize,
+    /// Wall-clock time of the whole transaction
+    pub timeout: Duration,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        ExecutionLimits {
+            max_steps: 10_000_000,
+            max_memory: 32 * 1024 * 1024,
+            max_call_depth: MAX_CALL_DEPTH,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ExecutionLimits {
+    /// The call depth limit, clamped to the one of the EVM
+    pub fn call_depth(&self) -> usize {
+        self.max_call_depth.min(MAX_CALL_DEPTH)
+    }
+}
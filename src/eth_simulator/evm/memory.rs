@@ -1,59 +1,112 @@
 use super::super::eth_types::U256;
 
+#[derive(Debug)]
+pub enum MemoryError {
+    LimitExceeded,
+}
+
+/// Memory of a call frame. Reads and writes past the end grow it with zeros
+/// up to the limit, which also bounds the memory of the frames calling it.
 pub struct Memory {
     memory: Vec<u8>,
+    limit: usize,
 }
 
 impl Memory {
-    pub fn new() -> Self {
-        Memory { memory: Vec::new() }
+    pub fn new(limit: usize) -> Self {
+        Memory {
+            memory: Vec::new(),
+            limit,
+        }
     }
 
     pub fn size(&self) -> U256 {
         U256::from(self.memory.len())
     }
 
-    pub fn read(&self, offset: U256) -> U256 {
-        let off = offset.as_usize();
-        U256::from(&self.memory[off..off + 32])
+    /// Memory left to a child frame, what this frame uses is taken from the limit
+    pub fn child_limit(&self) -> usize {
+        self.limit - self.memory.len()
+    }
+
+    pub fn read(&mut self, offset: U256) -> Result<U256, MemoryError> {
+        let off = self.expand(offset, U256::from(32))?;
+        Ok(U256::from(&self.memory[off..off + 32]))
     }
 
-    pub fn read_slice(&self, offset: U256, length: U256) -> &[u8] {
-        let off = offset.as_usize();
+    pub fn read_slice(&mut self, offset: U256, length: U256) -> Result<&[u8], MemoryError> {
+        if length.is_zero() {
+            return Ok(&[]);
+        }
+        let off = self.expand(offset, length)?;
         let len = length.as_usize();
 
-        &self.memory[off..off + len]
+        Ok(&self.memory[off..off + len])
     }
 
-    pub fn write(&mut self, offset: U256, value: U256) {
-        let off = offset.as_usize();
-
-        if self.memory.len() < off + 32 {
-            self.resize(off + 32);
-        }
+    pub fn write(&mut self, offset: U256, value: U256) -> Result<(), MemoryError> {
+        let off = self.expand(offset, U256::from(32))?;
 
         value.to_big_endian(&mut self.memory[off..off + 32]);
+        Ok(())
     }
 
-    pub fn write_slice(&mut self, offset: U256, value: &[u8]) {
-        let off = offset.as_usize();
-
-        if self.memory.len() < off + value.len() {
-            self.resize(off + value.len());
+    pub fn write_slice(&mut self, offset: U256, value: &[u8]) -> Result<(), MemoryError> {
+        if value.is_empty() {
+            return Ok(());
         }
+        let off = self.expand(offset, U256::from(value.len()))?;
 
         self.memory[off..off + value.len()].copy_from_slice(value);
+        Ok(())
     }
 
-    pub fn write_byte(&mut self, offset: U256, value: U256) {
-        let off = offset.as_usize();
+    /// Copy `length` bytes of `data` from `data_offset` to `offset`,
+    /// bytes past the end of `data` are copied as zeros
+    pub fn copy(
+        &mut self,
+        offset: U256,
+        data: &[u8],
+        data_offset: U256,
+        length: U256,
+    ) -> Result<(), MemoryError> {
+        if length.is_zero() {
+            return Ok(());
+        }
+        let off = self.expand(offset, length)?;
+        let len = length.as_usize();
 
-        if self.memory.len() < off + 1 {
-            self.resize(off + 1);
+        let target = &mut self.memory[off..off + len];
+        target.fill(0);
+        if data_offset < U256::from(data.len()) {
+            let start = data_offset.as_usize();
+            let end = data.len().min(start + len);
+            target[..end - start].copy_from_slice(&data[start..end]);
         }
+        Ok(())
+    }
+
+    pub fn write_byte(&mut self, offset: U256, value: U256) -> Result<(), MemoryError> {
+        let off = self.expand(offset, U256::one())?;
 
         let val = value.low_u64() as u8;
         self.memory[off] = val;
+        Ok(())
+    }
+
+    /// Grow memory to cover `length` bytes from `offset` within the limit
+    fn expand(&mut self, offset: U256, length: U256) -> Result<usize, MemoryError> {
+        let end = offset
+            .checked_add(length)
+            .filter(|end| *end <= U256::from(self.limit))
+            .ok_or(MemoryError::LimitExceeded)?;
+        let end = end.as_usize();
+
+        if self.memory.len() < end {
+            self.resize(end);
+        }
+
+        Ok(offset.as_usize())
     }
 
     fn resize(&mut self, size: usize) {
@@ -3,6 +3,8 @@ use super::{
     instructions::Instruction,
 };
 
+const OP_JUMPDEST: u8 = 0x5b;
+
 pub struct PC {
     code: Code,
     pc: usize,
@@ -13,9 +15,18 @@ impl PC {
         PC { code, pc: 0 }
     }
 
-    pub fn jump(&mut self, destination: U256) {
+    /// Jump to `destination`, false if it is not a JUMPDEST
+    pub fn jump(&mut self, destination: U256) -> bool {
+        if destination >= U256::from(self.code.len()) {
+            return false;
+        }
         let dest = destination.as_usize();
+        if self.code[dest] != OP_JUMPDEST {
+            return false;
+        }
+
         self.pc = dest;
+        true
     }
 
     pub fn pc(&self) -> U256 {
@@ -97,11 +108,15 @@ impl PC {
             0x59 => Instruction::MSIZE,
             0x5a => Instruction::GAS,
             0x5b => Instruction::JUMPDEST,
+            0x5c => Instruction::TLOAD,
+            0x5d => Instruction::TSTORE,
             0x60..=0x7f => {
                 let size = (self.code[self.pc] - 0x60 + 1) as usize;
-                let instr = Instruction::PUSH(U256::from_big_endian(
-                    &self.code[self.pc + 1..self.pc + size + 1],
-                ));
+                // push data running past the end of code is padded with zeros
+                let mut data = [0u8; 32];
+                let end = (self.pc + size + 1).min(self.code.len());
+                data[..end - self.pc - 1].copy_from_slice(&self.code[self.pc + 1..end]);
+                let instr = Instruction::PUSH(U256::from_big_endian(&data[..size]));
                 self.pc += size;
                 instr
             }
@@ -1,5 +1,16 @@
 use super::super::eth_types::U256;
 
+/// Items the stack holds at most
+const MAX_STACK_SIZE: usize = 1024;
+
+#[derive(Debug)]
+pub enum StackError {
+    /// An instruction takes more items than the stack holds
+    Underflow,
+    /// Pushing past `MAX_STACK_SIZE` items
+    Overflow,
+}
+
 pub struct Stack {
     stack: Vec<U256>,
 }
@@ -14,52 +25,64 @@ impl Stack {
         U256::from(self.stack.len())
     }
 
-    pub fn push(&mut self, v: U256) {
+    pub fn push(&mut self, v: U256) -> Result<(), StackError> {
+        if self.stack.len() >= MAX_STACK_SIZE {
+            return Err(StackError::Overflow);
+        }
         self.stack.push(v);
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> U256 {
-        self.stack.pop().unwrap()
+    pub fn pop(&mut self) -> Result<U256, StackError> {
+        self.stack.pop().ok_or(StackError::Underflow)
     }
 
-    pub fn dup_top(&mut self, i: usize) {
-        let idx = self.stack.len() - i;
-        let value = self.stack[idx].clone();
-        self.stack.push(value);
+    /// Push a copy of the `i`th item from the top, counting from 1
+    pub fn dup_top(&mut self, i: usize) -> Result<(), StackError> {
+        let idx = self
+            .stack
+            .len()
+            .checked_sub(i)
+            .ok_or(StackError::Underflow)?;
+        self.push(self.stack[idx])
     }
 
-    pub fn swap_with_top(&mut self, i: usize) {
-        let last_value = self.stack.pop().unwrap();
-        let idx = self.stack.len() - i;
-        let tmp = self.stack[idx].clone();
-        self.stack[idx] = last_value;
-        self.stack.push(tmp);
+    /// Swap the top item with the `i`th item below it
+    pub fn swap_with_top(&mut self, i: usize) -> Result<(), StackError> {
+        let top = self
+            .stack
+            .len()
+            .checked_sub(1)
+            .ok_or(StackError::Underflow)?;
+        let idx = top.checked_sub(i).ok_or(StackError::Underflow)?;
+        self.stack.swap(idx, top);
+        Ok(())
     }
 
-    pub fn one_item_op<F>(&mut self, op: F)
+    pub fn one_item_op<F>(&mut self, op: F) -> Result<(), StackError>
     where
         F: FnOnce(U256) -> U256,
     {
-        let a = self.stack.pop().unwrap();
-        self.stack.push(op(a));
+        let a = self.pop()?;
+        self.push(op(a))
     }
 
-    pub fn two_items_op<F>(&mut self, op: F)
+    pub fn two_items_op<F>(&mut self, op: F) -> Result<(), StackError>
     where
         F: FnOnce(U256, U256) -> U256,
     {
-        let a = self.stack.pop().unwrap();
-        let b = self.stack.pop().unwrap();
-        self.stack.push(op(a, b));
+        let a = self.pop()?;
+        let b = self.pop()?;
+        self.push(op(a, b))
     }
 
-    pub fn three_items_op<F>(&mut self, op: F)
+    pub fn three_items_op<F>(&mut self, op: F) -> Result<(), StackError>
     where
         F: FnOnce(U256, U256, U256) -> U256,
     {
-        let a = self.stack.pop().unwrap();
-        let b = self.stack.pop().unwrap();
-        let c = self.stack.pop().unwrap();
-        self.stack.push(op(a, b, c));
+        let a = self.pop()?;
+        let b = self.pop()?;
+        let c = self.pop()?;
+        self.push(op(a, b, c))
     }
 }
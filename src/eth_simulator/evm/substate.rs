@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    super::eth_types::{Address, H256},
+    coverage::Coverage,
+};
+
+/// Parts of the substate which are undone when a call frame fails
+pub struct SubstateCheckpoint {
+    accessed_storage: HashSet<(Address, H256)>,
+    transient_storage: HashMap<(Address, H256), H256>,
+    refund: i64,
+}
+
+/// State accrued during the execution of a transaction
+pub struct Substate {
+    original_storage: HashMap<(Address, H256), H256>,
+    accessed_storage: HashSet<(Address, H256)>,
+    transient_storage: HashMap<(Address, H256), H256>,
+    refund: i64,
+    steps: usize,
+    coverage: Option<Coverage>,
+}
+
+impl Substate {
+    /// Executed code is recorded into `coverage` if given
+    pub fn new(coverage: Option<Coverage>) -> Self {
+        Substate {
+            original_storage: HashMap::new(),
+            accessed_storage: HashSet::new(),
+            transient_storage: HashMap::new(),
+            refund: 0,
+            steps: 0,
+            coverage,
+        }
+    }
+
+    /// Value of the slot at the start of the transaction.
+    /// `current` is recorded as the original value on first access.
+    pub fn original_storage(&mut self, address: &Address, key: &H256, current: H256) -> H256 {
+        *self
+            .original_storage
+            .entry((*address, *key))
+            .or_insert(current)
+    }
+
+    /// Mark the slot as accessed, return whether it was cold before
+    pub fn access_storage(&mut self, address: &Address, key: &H256) -> bool {
+        self.accessed_storage.insert((*address, *key))
+    }
+
+    /// EIP-1153 storage which is discarded at the end of the transaction
+    pub fn set_transient_storage(&mut self, address: &Address, key: H256, value: H256) {
+        self.transient_storage.insert((*address, key), value);
+    }
+
+    pub fn get_transient_storage(&self, address: &Address, key: &H256) -> H256 {
+        match self.transient_storage.get(&(*address, *key)) {
+            Some(&v) => v,
+            None => H256::zero(),
+        }
+    }
+
+    pub fn checkpoint(&self) -> SubstateCheckpoint {
+        SubstateCheckpoint {
+            accessed_storage: self.accessed_storage.clone(),
+            transient_storage: self.transient_storage.clone(),
+            refund: self.refund,
+        }
+    }
+
+    pub fn revert(&mut self, checkpoint: SubstateCheckpoint) {
+        self.accessed_storage = checkpoint.accessed_storage;
+        self.transient_storage = checkpoint.transient_storage;
+        self.refund = checkpoint.refund;
+    }
+
+    pub fn add_refund(&mut self, refund: i64) {
+        self.refund += refund;
+    }
+
+    pub fn refund(&self) -> usize {
+        self.refund.max(0) as usize
+    }
+
+    /// Count an executed instruction, return the number of steps so far
+    pub fn step(&mut self) -> usize {
+        self.steps += 1;
+        self.steps
+    }
+
+    pub fn record_pc(&mut self, code_hash: &H256, pc: usize) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_pc(code_hash, pc);
+        }
+    }
+
+    pub fn record_branch(&mut self, code_hash: &H256, pc: usize, taken: bool) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_branch(code_hash, pc, taken);
+        }
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+}
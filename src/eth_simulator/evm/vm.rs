@@ -1,17 +1,23 @@
-use core::panic;
+use std::thread;
 
 use super::{
     super::{
-        eth_types::{Address, Bytes, Code, EthFrom, EthSign, U256},
+        eth_types::{Address, Bytes, Code, EthFrom, EthSign, H256, U256, U512},
         hash,
     },
-    ext::{Ext, ExtError},
+    ext::{CallParams, Ext, ExtError},
+    gas,
+    host::Host,
     instructions::Instruction,
-    memory::Memory,
+    limits::ExecutionLimits,
+    memory::{Memory, MemoryError},
     pc::PC,
-    stack::Stack,
+    stack::{Stack, StackError},
 };
 
+/// Stack size needed by each nested call frame
+const STACK_SIZE_PER_CALL: usize = 64 * 1024;
+
 pub enum VMResult {
     Ok,
     Stop,
@@ -21,6 +27,55 @@ pub enum VMResult {
 #[derive(Debug)]
 pub enum VMErrorKind {
     NotExistedAddress(Address),
+    OutOfGas,
+    StaticStateChange,
+    StepLimitExceeded,
+    MemoryLimitExceeded,
+    Timeout,
+    /// An instruction takes more items than the stack holds
+    StackUnderflow,
+    /// The stack grows past 1024 items
+    StackOverflow,
+    /// RETURNDATACOPY past the end of the return data
+    ReturnDataOutOfBounds,
+    /// The designated invalid instruction 0xfe, which solc emits for `assert`
+    InvalidInstruction,
+    /// JUMP or JUMPI to a position which is not a JUMPDEST
+    InvalidJump,
+    /// A bug of the simulator panicked during execution,
+    /// the transaction fails instead of the simulator
+    Internal,
+    /// Execution stopped by REVERT with the given output
+    Revert(Bytes),
+}
+
+impl From<ExtError> for VMErrorKind {
+    fn from(err: ExtError) -> Self {
+        match err {
+            ExtError::NotExistedAddress(address) => VMErrorKind::NotExistedAddress(address),
+            ExtError::OutOfGas => VMErrorKind::OutOfGas,
+            ExtError::StaticStateChange => VMErrorKind::StaticStateChange,
+            ExtError::StepLimitExceeded => VMErrorKind::StepLimitExceeded,
+            ExtError::Timeout => VMErrorKind::Timeout,
+        }
+    }
+}
+
+impl From<StackError> for VMErrorKind {
+    fn from(err: StackError) -> Self {
+        match err {
+            StackError::Underflow => VMErrorKind::StackUnderflow,
+            StackError::Overflow => VMErrorKind::StackOverflow,
+        }
+    }
+}
+
+impl From<MemoryError> for VMErrorKind {
+    fn from(err: MemoryError) -> Self {
+        match err {
+            MemoryError::LimitExceeded => VMErrorKind::MemoryLimitExceeded,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -31,268 +86,901 @@ pub struct VMError {
 }
 
 pub struct VM {
+    code_hash: H256,
     stack: Stack,
     memory: Memory,
     pc: PC,
+    /// Instruction being executed and its offset
+    instruction: Instruction,
+    instruction_pc: usize,
+    return_data: Bytes,
 }
 
 impl VM {
-    pub fn new(code: Code) -> Self {
+    /// VM of the top level call of a transaction
+    pub fn new(code: Code, limits: &ExecutionLimits) -> Self {
+        Self::with_memory_limit(code, limits.max_memory)
+    }
+
+    fn with_memory_limit(code: Code, memory_limit: usize) -> Self {
         VM {
+            code_hash: hash::keccak(&code),
             stack: Stack::new(),
-            memory: Memory::new(),
+            memory: Memory::new(memory_limit),
             pc: PC::new(code),
+            instruction: Instruction::STOP,
+            instruction_pc: 0,
+            return_data: Bytes::new(),
         }
     }
 
-    pub fn execute(&mut self, ext: &mut Ext) -> Result<VMResult, VMError> {
-        while let Some(instruction) = self.pc.next() {
-            match instruction {
-                Instruction::STOP => return Ok(VMResult::Stop),
-                Instruction::ADD => self.stack.two_items_op(|a, b| a + b),
-                Instruction::MUL => self.stack.two_items_op(|a, b| a * b),
-                Instruction::SUB => self.stack.two_items_op(|a, b| a - b),
-                Instruction::DIV => self.stack.two_items_op(|a, b| a / b),
-                Instruction::SDIV => self.stack.two_items_op(|a, b| a.to_sign() / b.to_sign()),
-                Instruction::MOD => self.stack.two_items_op(|a, b| a % b),
-                Instruction::SMOD => self.stack.two_items_op(|a, b| a.to_sign() % b.to_sign()),
-                Instruction::ADDMOD => self.stack.three_items_op(|a, b, n| (a + b) % n),
-                Instruction::MULMOD => self.stack.three_items_op(|a, b, n| (a * b) % n),
-                Instruction::EXP => self.stack.two_items_op(U256::pow),
-                Instruction::SIGNEXTEND => {
-                    let bit_num = self.stack.pop();
-                    let number = self.stack.pop();
-                    let bit_position = bit_num.as_usize() * 8 + 7;
+    /// VM of a child frame, which shares the memory limit with this frame
+    fn child(&self, code: Code) -> Self {
+        Self::with_memory_limit(code, self.memory.child_limit())
+    }
+
+    /// Execute the top level call of a transaction on a thread whose stack
+    /// can hold nested calls up to the call depth limit
+    pub fn execute_tx<H: Host + Send>(&mut self, ext: &mut Ext<H>) -> Result<VMResult, VMError> {
+        let stack_size = (ext.limits().call_depth() + 1) * STACK_SIZE_PER_CALL;
+
+        let result = thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(stack_size)
+                .spawn_scoped(scope, || self.execute(ext))
+                .map(|handle| handle.join())
+        });
+        // a failed spawn or a panic must not take down a shared simulator
+        match result {
+            Ok(Ok(result)) => result,
+            _ => Err(self.error(self.instruction, VMErrorKind::Internal)),
+        }
+    }
+
+    pub fn execute<H: Host>(&mut self, ext: &mut Ext<H>) -> Result<VMResult, VMError> {
+        loop {
+            self.instruction_pc = self.pc.pc().as_usize();
+            let instruction = match self.pc.next() {
+                Some(instruction) => instruction,
+                None => break,
+            };
+            self.instruction = instruction;
+            if let Err(err) = ext
+                .step()
+                .and_then(|_| ext.use_gas(gas::base_cost(&instruction)))
+            {
+                return Err(self.ext_error(instruction, err));
+            }
+            ext.record_pc(&self.code_hash, self.instruction_pc);
+
+            match self.execute_instruction(ext, instruction) {
+                Ok(Some(result)) => return Ok(result),
+                Ok(None) => {}
+                Err(error_kind) => return Err(self.error(instruction, error_kind)),
+            }
+        }
+
+        Ok(VMResult::Ok)
+    }
+
+    /// Execute `instruction`, giving the result of the frame if it ends the frame
+    fn execute_instruction<H: Host>(
+        &mut self,
+        ext: &mut Ext<H>,
+        instruction: Instruction,
+    ) -> Result<Option<VMResult>, VMErrorKind> {
+        match instruction {
+            Instruction::STOP => return Ok(Some(VMResult::Stop)),
+            // arithmetic wraps around and division by zero gives zero
+            Instruction::ADD => self.stack.two_items_op(|a, b| a.overflowing_add(b).0)?,
+            Instruction::MUL => self.stack.two_items_op(|a, b| a.overflowing_mul(b).0)?,
+            Instruction::SUB => self.stack.two_items_op(|a, b| a.overflowing_sub(b).0)?,
+            Instruction::DIV => self
+                .stack
+                .two_items_op(|a, b| a.checked_div(b).unwrap_or_default())?,
+            Instruction::SDIV => self
+                .stack
+                .two_items_op(|a, b| a.to_sign().checked_div(b.to_sign()).unwrap_or_default())?,
+            Instruction::MOD => self
+                .stack
+                .two_items_op(|a, b| a.checked_rem(b).unwrap_or_default())?,
+            Instruction::SMOD => self
+                .stack
+                .two_items_op(|a, b| a.to_sign().checked_rem(b.to_sign()).unwrap_or_default())?,
+            Instruction::ADDMOD => self
+                .stack
+                .three_items_op(|a, b, n| modulo(U512::from(a) + U512::from(b), n))?,
+            Instruction::MULMOD => self
+                .stack
+                .three_items_op(|a, b, n| modulo(a.full_mul(b), n))?,
+            Instruction::EXP => self.stack.two_items_op(|a, b| a.overflowing_pow(b).0)?,
+            Instruction::SIGNEXTEND => {
+                let byte_num = self.stack.pop()?;
+                let number = self.stack.pop()?;
+                if byte_num < U256::from(31) {
+                    let bit_position = byte_num.as_usize() * 8 + 7;
                     let bit = number.bit(bit_position);
-                    let mask = (U256::one() << bit_position) - U256::one();
+                    let mask = (U256::one() << (bit_position + 1)) - U256::one();
                     self.stack
-                        .push(if bit { number | !mask } else { number | mask });
+                        .push(if bit { number | !mask } else { number & mask })?;
+                } else {
+                    self.stack.push(number)?;
                 }
-                Instruction::LT => self.stack.two_items_op(|a, b| U256::ethfrom(a < b)),
-                Instruction::SLT => {
-                    let a = self.stack.pop().to_sign();
-                    let neg_a = a.is_neg();
-                    let b = self.stack.pop();
-                    let neg_b = b.is_neg();
-
-                    let is_positive_lt = a < b && !(neg_a | neg_b);
-                    let is_negative_lt = a > b && (neg_a & neg_b);
-                    let has_different_signs = neg_a && !neg_b;
-
-                    self.stack.push(U256::ethfrom(
-                        is_positive_lt | is_negative_lt | has_different_signs,
-                    ));
-                }
-                Instruction::GT => self.stack.two_items_op(|a, b| U256::ethfrom(a > b)),
-                Instruction::SGT => {
-                    let a = self.stack.pop().to_sign();
-                    let neg_a = a.is_neg();
-                    let b = self.stack.pop().to_sign();
-                    let neg_b = b.is_neg();
-
-                    let is_positive_gt = a > b && !(neg_a | neg_b);
-                    let is_negative_gt = a < b && (neg_a & neg_b);
-                    let has_different_signs = !neg_a && neg_b;
-
-                    self.stack.push(U256::ethfrom(
-                        is_positive_gt | is_negative_gt | has_different_signs,
-                    ));
+            }
+            Instruction::LT => self.stack.two_items_op(|a, b| U256::ethfrom(a < b))?,
+            Instruction::SLT => {
+                let a = self.stack.pop()?.to_sign();
+                let neg_a = a.is_neg();
+                let b = self.stack.pop()?;
+                let neg_b = b.is_neg();
+
+                let is_positive_lt = a < b && !(neg_a | neg_b);
+                let is_negative_lt = a > b && (neg_a & neg_b);
+                let has_different_signs = neg_a && !neg_b;
+
+                self.stack.push(U256::ethfrom(
+                    is_positive_lt | is_negative_lt | has_different_signs,
+                ))?;
+            }
+            Instruction::GT => self.stack.two_items_op(|a, b| U256::ethfrom(a > b))?,
+            Instruction::SGT => {
+                let a = self.stack.pop()?.to_sign();
+                let neg_a = a.is_neg();
+                let b = self.stack.pop()?.to_sign();
+                let neg_b = b.is_neg();
+
+                let is_positive_gt = a > b && !(neg_a | neg_b);
+                let is_negative_gt = a < b && (neg_a & neg_b);
+                let has_different_signs = !neg_a && neg_b;
+
+                self.stack.push(U256::ethfrom(
+                    is_positive_gt | is_negative_gt | has_different_signs,
+                ))?;
+            }
+            Instruction::EQ => self.stack.two_items_op(|a, b| U256::ethfrom(a == b))?,
+            Instruction::ISZERO => self.stack.one_item_op(|a| U256::ethfrom(a.is_zero()))?,
+            Instruction::AND => self.stack.two_items_op(|a, b| a & b)?,
+            Instruction::OR => self.stack.two_items_op(|a, b| a | b)?,
+            Instruction::XOR => self.stack.two_items_op(|a, b| a ^ b)?,
+            Instruction::NOT => self.stack.one_item_op(|a| !a)?,
+            // byte i counts from the most significant byte
+            Instruction::BYTE => self.stack.two_items_op(|i, x| {
+                if i < U256::from(32) {
+                    U256::from(x.byte(31 - i.as_usize()))
+                } else {
+                    U256::zero()
                 }
-                Instruction::EQ => self.stack.two_items_op(|a, b| U256::ethfrom(a == b)),
-                Instruction::ISZERO => self.stack.one_item_op(|a| U256::ethfrom(a.is_zero())),
-                Instruction::AND => self.stack.two_items_op(|a, b| a & b),
-                Instruction::OR => self.stack.two_items_op(|a, b| a | b),
-                Instruction::XOR => self.stack.two_items_op(|a, b| a ^ b),
-                Instruction::NOT => self.stack.one_item_op(|a| !a),
-                Instruction::BYTE => self
-                    .stack
-                    .two_items_op(|i, x| U256::from(x.byte(i.as_usize()))),
-                Instruction::SHL => self.stack.two_items_op(|shl, value| value << shl),
-                Instruction::SHR => self.stack.two_items_op(|shr, value| value >> shr),
-                Instruction::SAR => {
-                    const CONST_256: U256 = U256([256, 0, 0, 0]);
-                    const CONST_HIBIT: U256 = U256([0, 0, 0, 0x8000000000000000]);
-
-                    let shift = self.stack.pop();
-                    let value = self.stack.pop();
-                    let sign = value & CONST_HIBIT != U256::zero();
-
-                    let result = if shift >= CONST_256 {
-                        if sign {
-                            U256::max_value()
-                        } else {
-                            U256::zero()
-                        }
+            })?,
+            Instruction::SHL => self.stack.two_items_op(|shl, value| value << shl)?,
+            Instruction::SHR => self.stack.two_items_op(|shr, value| value >> shr)?,
+            Instruction::SAR => {
+                const CONST_256: U256 = U256([256, 0, 0, 0]);
+                const CONST_HIBIT: U256 = U256([0, 0, 0, 0x8000000000000000]);
+
+                let shift = self.stack.pop()?;
+                let value = self.stack.pop()?;
+                let sign = value & CONST_HIBIT != U256::zero();
+
+                let result = if shift >= CONST_256 {
+                    if sign {
+                        U256::max_value()
                     } else {
-                        let shift = shift.as_u32() as usize;
-                        let mut shifted = value >> shift;
-                        if sign {
-                            shifted = shifted | (U256::max_value() << (256 - shift));
-                        }
-                        shifted
-                    };
-                    self.stack.push(result);
-                }
-                Instruction::SHA3 => self.stack.two_items_op(|offset, length| {
-                    U256::ethfrom(hash::keccak(self.memory.read_slice(offset, length)))
-                }),
-                Instruction::ADDRESS => self.stack.push(ext.get_address()),
-                Instruction::BALANCE => {
-                    let address = Address::ethfrom(self.stack.pop());
-                    match ext.get_balance(&address) {
-                        Ok(balance) => self.stack.push(balance),
-                        Err(err) => match err {
-                            ExtError::NotExistedAddress(address) => {
-                                return Err(VMError {
-                                    instruction: Instruction::BALANCE,
-                                    pc: self.pc.pc().as_usize(),
-                                    error_kind: VMErrorKind::NotExistedAddress(address),
-                                })
-                            }
-                        },
+                        U256::zero()
                     }
-                }
-                Instruction::ORIGIN => self.stack.push(ext.get_origin()),
-                Instruction::CALLER => self.stack.push(ext.get_caller()),
-                Instruction::CALLVALUE => self.stack.push(ext.get_callvalue()),
-                Instruction::CALLDATALOAD => self.stack.one_item_op(|i| ext.get_calldata(i)),
-                Instruction::CALLDATASIZE => self.stack.push(ext.get_calldatasize()),
-                Instruction::CALLDATACOPY => {
-                    let dest_offset = self.stack.pop();
-                    let offset = self.stack.pop();
-                    let length = self.stack.pop();
-                    self.memory
-                        .write_slice(dest_offset, ext.get_calldata_slice(offset, length));
-                }
-                Instruction::CODESIZE => self.stack.push(ext.get_codesize()),
-                Instruction::CODECOPY => {
-                    let dest_offset = self.stack.pop();
-                    let offset = self.stack.pop();
-                    let length = self.stack.pop();
-                    self.memory
-                        .write_slice(dest_offset, ext.get_code_slice(offset, length));
-                }
-                Instruction::GASPRICE => self.stack.push(ext.get_gasprice()),
-                Instruction::EXTCODESIZE => {
-                    let address = Address::ethfrom(self.stack.pop());
-
-                    match ext.get_ext_codesize(&address) {
-                        Ok(value) => self.stack.push(value),
-                        Err(err) => match err {
-                            ExtError::NotExistedAddress(address) => {
-                                return Err(VMError {
-                                    instruction: Instruction::EXTCODESIZE,
-                                    pc: self.pc.pc().as_usize(),
-                                    error_kind: VMErrorKind::NotExistedAddress(address),
-                                })
-                            }
-                        },
-                    }
-                }
-                Instruction::EXTCODECOPY => {
-                    let address = Address::ethfrom(self.stack.pop());
-                    let dest_offset = self.stack.pop();
-                    let offset = self.stack.pop();
-                    let length = self.stack.pop();
-
-                    match ext.get_ext_code_slice(&address, offset, length) {
-                        Ok(value) => self.memory.write_slice(dest_offset, value),
-                        Err(err) => match err {
-                            ExtError::NotExistedAddress(address) => {
-                                return Err(VMError {
-                                    instruction: Instruction::EXTCODECOPY,
-                                    pc: self.pc.pc().as_usize(),
-                                    error_kind: VMErrorKind::NotExistedAddress(address),
-                                })
-                            }
-                        },
+                } else {
+                    let shift = shift.as_u32() as usize;
+                    let mut shifted = value >> shift;
+                    if sign {
+                        shifted = shifted | (U256::max_value() << (256 - shift));
                     }
+                    shifted
+                };
+                self.stack.push(result)?;
+            }
+            Instruction::SHA3 => {
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+                let data = self.memory.read_slice(offset, length)?;
+                self.stack.push(U256::ethfrom(ext.keccak(data)))?;
+            }
+            Instruction::ADDRESS => self.stack.push(ext.get_address())?,
+            Instruction::BALANCE => {
+                let address = Address::ethfrom(self.stack.pop()?);
+                let balance = ext.get_balance(&address)?;
+                self.stack.push(balance)?;
+            }
+            Instruction::ORIGIN => self.stack.push(ext.get_origin())?,
+            Instruction::CALLER => self.stack.push(ext.get_caller())?,
+            Instruction::CALLVALUE => self.stack.push(ext.get_callvalue())?,
+            Instruction::CALLDATALOAD => self.stack.one_item_op(|i| ext.get_calldata(i))?,
+            Instruction::CALLDATASIZE => self.stack.push(ext.get_calldatasize())?,
+            Instruction::CALLDATACOPY => {
+                let dest_offset = self.stack.pop()?;
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+                self.memory
+                    .copy(dest_offset, ext.get_calldata_all(), offset, length)?;
+            }
+            Instruction::CODESIZE => self.stack.push(ext.get_codesize())?,
+            Instruction::CODECOPY => {
+                let dest_offset = self.stack.pop()?;
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+                self.memory
+                    .copy(dest_offset, ext.get_code_all(), offset, length)?;
+            }
+            Instruction::GASPRICE => self.stack.push(ext.get_gasprice())?,
+            Instruction::EXTCODESIZE => {
+                let address = Address::ethfrom(self.stack.pop()?);
+
+                let value = ext.get_ext_codesize(&address)?;
+                self.stack.push(value)?;
+            }
+            Instruction::EXTCODECOPY => {
+                let address = Address::ethfrom(self.stack.pop()?);
+                let dest_offset = self.stack.pop()?;
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+
+                let code = ext.get_ext_code(&address)?;
+                self.memory.copy(dest_offset, code, offset, length)?;
+            }
+            Instruction::RETURNDATASIZE => self.stack.push(U256::from(self.return_data.len()))?,
+            Instruction::RETURNDATACOPY => {
+                let dest_offset = self.stack.pop()?;
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+                let in_bounds = offset
+                    .checked_add(length)
+                    .is_some_and(|end| end <= U256::from(self.return_data.len()));
+                if !in_bounds {
+                    return Err(VMErrorKind::ReturnDataOutOfBounds);
                 }
-                Instruction::RETURNDATASIZE => {}
-                Instruction::RETURNDATACOPY => {}
-                Instruction::EXTCODEHASH => {
-                    let address = Address::ethfrom(self.stack.pop());
-
-                    match ext.get_ext_code_hash(&address) {
-                        Ok(value) => self.stack.push(value),
-                        Err(err) => match err {
-                            ExtError::NotExistedAddress(address) => {
-                                return Err(VMError {
-                                    instruction: Instruction::EXTCODEHASH,
-                                    pc: self.pc.pc().as_usize(),
-                                    error_kind: VMErrorKind::NotExistedAddress(address),
-                                })
-                            }
-                        },
-                    }
-                }
-                Instruction::BLOCKHASH => {}
-                Instruction::COINBASE => {}
-                Instruction::TIMESTAMP => {}
-                Instruction::NUMBER => {}
-                Instruction::DIFFICULT => {}
-                Instruction::GASLIMIT => {}
-                Instruction::CHAINID => self.stack.push(ext.get_chainid()),
-                Instruction::SELFBALANCE => {}
-                Instruction::BASEFEE => {}
-                Instruction::POP => {
-                    self.stack.pop();
-                }
-                Instruction::MLOAD => self.stack.one_item_op(|offset| self.memory.read(offset)),
-                Instruction::MSTORE => {
-                    let offset = self.stack.pop();
-                    let value = self.stack.pop();
-                    self.memory.write(offset, value);
-                }
-                Instruction::MSTORE8 => {
-                    let offset = self.stack.pop();
-                    let value = self.stack.pop();
-                    self.memory.write_byte(offset, value);
-                }
-                Instruction::SLOAD => {
-                    let key = self.stack.pop();
-                    self.stack.push(ext.get_storage(key));
-                }
-                Instruction::SSTORE => {
-                    let key = self.stack.pop();
-                    let value = self.stack.pop();
-                    ext.set_storage(key, value);
+                let (offset, length) = (offset.as_usize(), length.as_usize());
+                self.memory
+                    .write_slice(dest_offset, &self.return_data[offset..offset + length])?;
+            }
+            Instruction::EXTCODEHASH => {
+                let address = Address::ethfrom(self.stack.pop()?);
+
+                let value = ext.get_ext_code_hash(&address)?;
+                self.stack.push(value)?;
+            }
+            Instruction::BLOCKHASH => self
+                .stack
+                .one_item_op(|number| ext.get_block_hash(number))?,
+            Instruction::COINBASE => self
+                .stack
+                .push(U256::ethfrom(ext.get_block_env().coinbase))?,
+            Instruction::TIMESTAMP => self.stack.push(ext.get_block_env().timestamp)?,
+            Instruction::NUMBER => self.stack.push(ext.get_block_env().number)?,
+            Instruction::DIFFICULT => self.stack.push(ext.get_block_env().difficulty)?,
+            Instruction::GASLIMIT => self.stack.push(ext.get_block_env().gas_limit)?,
+            Instruction::CHAINID => self.stack.push(ext.get_chainid())?,
+            Instruction::SELFBALANCE => {
+                let address = ext.address();
+                let balance = ext.get_balance(&address)?;
+                self.stack.push(balance)?;
+            }
+            Instruction::BASEFEE => self.stack.push(ext.get_block_env().base_fee)?,
+            Instruction::POP => {
+                self.stack.pop()?;
+            }
+            Instruction::MLOAD => {
+                let offset = self.stack.pop()?;
+                let value = self.memory.read(offset)?;
+                self.stack.push(value)?;
+            }
+            Instruction::MSTORE => {
+                let offset = self.stack.pop()?;
+                let value = self.stack.pop()?;
+                self.memory.write(offset, value)?;
+            }
+            Instruction::MSTORE8 => {
+                let offset = self.stack.pop()?;
+                let value = self.stack.pop()?;
+                self.memory.write_byte(offset, value)?;
+            }
+            Instruction::SLOAD => {
+                let key = self.stack.pop()?;
+                let value = ext.get_storage(key)?;
+                self.stack.push(value)?;
+            }
+            Instruction::SSTORE => {
+                let key = self.stack.pop()?;
+                let value = self.stack.pop()?;
+                ext.set_storage(key, value)?;
+            }
+            Instruction::JUMP => {
+                if !self.pc.jump(self.stack.pop()?) {
+                    return Err(VMErrorKind::InvalidJump);
                 }
-                Instruction::JUMP => self.pc.jump(self.stack.pop()),
-                Instruction::JUMPI => {
-                    let destination = self.stack.pop();
-                    let condition = self.stack.pop();
-                    if !condition.is_zero() {
-                        self.pc.jump(destination);
-                    }
+            }
+            Instruction::JUMPI => {
+                let destination = self.stack.pop()?;
+                let condition = self.stack.pop()?;
+                ext.record_branch(&self.code_hash, self.instruction_pc, !condition.is_zero());
+                if !condition.is_zero() && !self.pc.jump(destination) {
+                    return Err(VMErrorKind::InvalidJump);
                 }
-                Instruction::PC => self.stack.push(self.pc.pc()),
-                Instruction::MSIZE => self.stack.push(self.memory.size()),
-                Instruction::GAS => self.stack.push(ext.get_gas()),
-                Instruction::JUMPDEST => {}
-                Instruction::PUSH(value) => self.stack.push(value),
-                Instruction::DUP(i) => self.stack.dup_top(i),
-                Instruction::SWAP(i) => self.stack.swap_with_top(i),
-                Instruction::LOG(_i) => {}
-                Instruction::CREAT => {}
-                Instruction::CALL => {}
-                Instruction::CALLCODE => {}
-                Instruction::RETURN => {
-                    let offset = self.stack.pop();
-                    let length = self.stack.pop();
-                    return Ok(VMResult::Return(Bytes::from(
-                        self.memory.read_slice(offset, length),
-                    )));
+            }
+            Instruction::PC => self.stack.push(U256::from(self.instruction_pc))?,
+            Instruction::MSIZE => self.stack.push(self.memory.size())?,
+            Instruction::GAS => self.stack.push(ext.get_gas())?,
+            Instruction::JUMPDEST => {}
+            Instruction::TLOAD => self
+                .stack
+                .one_item_op(|key| ext.get_transient_storage(key))?,
+            Instruction::TSTORE => {
+                let key = self.stack.pop()?;
+                let value = self.stack.pop()?;
+                ext.set_transient_storage(key, value)?;
+            }
+            Instruction::PUSH(value) => self.stack.push(value)?,
+            Instruction::DUP(i) => self.stack.dup_top(i)?,
+            Instruction::SWAP(i) => self.stack.swap_with_top(i)?,
+            Instruction::LOG(topic_count) => {
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+                let topics = (0..topic_count)
+                    .map(|_| self.stack.pop().map(H256::ethfrom))
+                    .collect::<Result<_, _>>()?;
+                let data = Bytes::from(self.memory.read_slice(offset, length)?);
+                ext.log(topics, data)?;
+            }
+            Instruction::CREAT | Instruction::CREAT2 => {
+                self.create(ext, instruction)?;
+            }
+            Instruction::SELFDESTRUCT => {
+                ext.check_static()?;
+                return Ok(Some(VMResult::Stop));
+            }
+            Instruction::CALL
+            | Instruction::CALLCODE
+            | Instruction::DELEGATCALL
+            | Instruction::STATICCALL => {
+                self.call(ext, instruction)?;
+            }
+            Instruction::RETURN => {
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+                let output = self.memory.read_slice(offset, length)?;
+                return Ok(Some(VMResult::Return(Bytes::from(output))));
+            }
+            Instruction::REVERT => {
+                let offset = self.stack.pop()?;
+                let length = self.stack.pop()?;
+                let output = self.memory.read_slice(offset, length)?;
+                return Err(VMErrorKind::Revert(Bytes::from(output)));
+            }
+            Instruction::INVALID => return Err(VMErrorKind::InvalidInstruction),
+        }
+
+        Ok(None)
+    }
+
+    /// Execute a message call in a child frame and push whether it succeeded
+    fn call<H: Host>(
+        &mut self,
+        ext: &mut Ext<H>,
+        instruction: Instruction,
+    ) -> Result<(), VMErrorKind> {
+        let gas = self.stack.pop()?;
+        let code_address = Address::ethfrom(self.stack.pop()?);
+        let value = match instruction {
+            Instruction::CALL | Instruction::CALLCODE => self.stack.pop()?,
+            _ => U256::zero(),
+        };
+        let args_offset = self.stack.pop()?;
+        let args_length = self.stack.pop()?;
+        let ret_offset = self.stack.pop()?;
+        let ret_length = self.stack.pop()?;
+
+        if instruction == Instruction::CALL && !value.is_zero() {
+            ext.check_static()?;
+        }
+
+        let input = Bytes::from(self.memory.read_slice(args_offset, args_length)?);
+        let transfers_value =
+            !value.is_zero() && matches!(instruction, Instruction::CALL | Instruction::CALLCODE);
+        if transfers_value {
+            ext.use_gas(gas::G_CALL_VALUE)?;
+        }
+        let gas = gas::call_gas(ext.gas_left(), gas);
+        ext.use_gas(gas)?;
+        let child_gas = if transfers_value {
+            gas + gas::G_CALL_STIPEND
+        } else {
+            gas
+        };
+
+        let params = match instruction {
+            Instruction::CALL => CallParams {
+                address: code_address,
+                caller: ext.address(),
+                value,
+                input,
+                gas: child_gas,
+                is_static: ext.is_static(),
+            },
+            Instruction::CALLCODE => CallParams {
+                address: ext.address(),
+                caller: ext.address(),
+                value,
+                input,
+                gas: child_gas,
+                is_static: ext.is_static(),
+            },
+            Instruction::DELEGATCALL => CallParams {
+                address: ext.address(),
+                caller: ext.caller(),
+                value: ext.get_callvalue(),
+                input,
+                gas: child_gas,
+                is_static: ext.is_static(),
+            },
+            _ => CallParams {
+                address: code_address,
+                caller: ext.address(),
+                value,
+                input,
+                gas: child_gas,
+                is_static: true,
+            },
+        };
+
+        // exceeding the depth limit or the balance fails the call, not the transaction
+        let checkpoint = ext.checkpoint();
+        let transfer_to = match instruction {
+            Instruction::CALL => code_address,
+            _ => ext.address(),
+        };
+        if ext.depth() >= ext.limits().call_depth()
+            || (transfers_value && !ext.transfer(&transfer_to, value))
+        {
+            ext.return_gas(gas);
+            self.return_data = Bytes::new();
+            self.stack.push(U256::zero())?;
+            return Ok(());
+        }
+
+        let (success, output, gas_left) = match ext.host_call(&params) {
+            Some(outcome) => (outcome.success, outcome.output, params.gas),
+            None => {
+                let code = ext.get_code(&code_address);
+                let mut child = ext.child(params);
+                match self.child(code).execute(&mut child) {
+                    Ok(VMResult::Return(bytes)) => (true, bytes, child.gas_left()),
+                    Ok(VMResult::Ok | VMResult::Stop) => (true, Bytes::new(), child.gas_left()),
+                    // REVERT gives back the gas left, other errors consume all of it
+                    Err(VMError {
+                        error_kind: VMErrorKind::Revert(output),
+                        ..
+                    }) => (false, output, child.gas_left()),
+                    Err(_) => (false, Bytes::new(), 0),
                 }
-                Instruction::DELEGATCALL => {}
-                Instruction::CREAT2 => {}
-                Instruction::STATICCALL => {}
-                Instruction::REVERT => {}
-                Instruction::SELFDESTRUCT => {}
-                Instruction::INVALID => panic!("Invalid instruction"),
             }
+        };
+        ext.return_gas(gas_left);
+        if !success {
+            ext.revert(checkpoint);
         }
 
-        Ok(VMResult::Ok)
+        let length = if ret_length < U256::from(output.len()) {
+            ret_length.as_usize()
+        } else {
+            output.len()
+        };
+        self.memory.write_slice(ret_offset, &output[..length])?;
+        self.return_data = output;
+        self.stack.push(U256::ethfrom(success))?;
+
+        Ok(())
+    }
+
+    /// Execute init code in a child frame and push the address of the created
+    /// contract, or zero if the creation failed
+    fn create<H: Host>(
+        &mut self,
+        ext: &mut Ext<H>,
+        instruction: Instruction,
+    ) -> Result<(), VMErrorKind> {
+        let value = self.stack.pop()?;
+        let offset = self.stack.pop()?;
+        let length = self.stack.pop()?;
+        let salt = match instruction {
+            Instruction::CREAT2 => Some(H256::ethfrom(self.stack.pop()?)),
+            _ => None,
+        };
+        ext.check_static()?;
+
+        let init_code = Code::from(self.memory.read_slice(offset, length)?);
+        let gas = gas::call_gas(ext.gas_left(), U256::max_value());
+        ext.use_gas(gas)?;
+        self.return_data = Bytes::new();
+
+        let balance = ext.get_balance(&ext.address())?;
+        if ext.depth() >= ext.limits().call_depth() || balance < value {
+            ext.return_gas(gas);
+            self.stack.push(U256::zero())?;
+            return Ok(());
+        }
+
+        // the nonce of the creator stays incremented even if the creation fails
+        let address = ext.create_address(salt, &init_code);
+        let checkpoint = ext.checkpoint();
+        // a collision consumes all forwarded gas
+        if !ext.create_account(&address) {
+            self.stack.push(U256::zero())?;
+            return Ok(());
+        }
+        ext.transfer(&address, value);
+
+        let params = CallParams {
+            address,
+            caller: ext.address(),
+            value,
+            input: Bytes::new(),
+            gas,
+            is_static: false,
+        };
+        let mut child = ext.child(params);
+        let (code, gas_left) = match self.child(init_code).execute(&mut child) {
+            Ok(VMResult::Return(code)) => match child.use_gas(gas::G_CODE_DEPOSIT * code.len()) {
+                Ok(_) => (Some(code), child.gas_left()),
+                Err(_) => (None, 0),
+            },
+            Ok(VMResult::Ok | VMResult::Stop) => (Some(Code::new()), child.gas_left()),
+            // the output of REVERT is the return data of the creation
+            Err(VMError {
+                error_kind: VMErrorKind::Revert(output),
+                ..
+            }) => {
+                self.return_data = output;
+                (None, child.gas_left())
+            }
+            Err(_) => (None, 0),
+        };
+        ext.return_gas(gas_left);
+
+        match code {
+            Some(code) => {
+                ext.set_code(&address, code);
+                self.stack.push(U256::ethfrom(address))?;
+            }
+            None => {
+                ext.revert(checkpoint);
+                self.stack.push(U256::zero())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn ext_error(&self, instruction: Instruction, err: ExtError) -> VMError {
+        self.error(instruction, err.into())
+    }
+
+    fn error(&self, instruction: Instruction, error_kind: VMErrorKind) -> VMError {
+        VMError {
+            instruction,
+            pc: self.instruction_pc,
+            error_kind,
+        }
+    }
+}
+
+/// `value` modulo `n` computed without overflow, zero for a zero `n`
+fn modulo(value: U512, n: U256) -> U256 {
+    if n.is_zero() {
+        return U256::zero();
+    }
+    U256::try_from(value % U512::from(n)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{
+        super::{
+            super::{
+                eth_types::H256,
+                tx::{Tx, TxType},
+            },
+            host::{BlockEnv, CallOutcome, Log},
+            substate::Substate,
+        },
+        *,
+    };
+
+    #[derive(Default)]
+    struct MockHost {
+        code: HashMap<Address, Code>,
+        storage: HashMap<(Address, H256), H256>,
+        logs: Vec<Log>,
+        calls: Vec<Address>,
+    }
+
+    impl Host for MockHost {
+        fn exists(&self, address: &Address) -> bool {
+            self.code.contains_key(address)
+        }
+
+        fn balance(&self, address: &Address) -> Option<U256> {
+            self.code.get(address).map(|_| U256::zero())
+        }
+
+        fn set_balance(&mut self, _address: &Address, _balance: U256) {}
+
+        fn nonce(&self, address: &Address) -> Option<usize> {
+            self.code.get(address).map(|_| 0)
+        }
+
+        fn increment_nonce(&mut self, _address: &Address) {}
+
+        fn code(&self, address: &Address) -> Option<&Code> {
+            self.code.get(address)
+        }
+
+        fn set_code(&mut self, address: &Address, code: Code) {
+            self.code.insert(*address, code);
+        }
+
+        fn create_account(&mut self, address: &Address) {
+            self.code.insert(*address, Code::new());
+        }
+
+        fn storage(&self, address: &Address, key: &H256) -> H256 {
+            self.storage
+                .get(&(*address, *key))
+                .copied()
+                .unwrap_or_default()
+        }
+
+        fn set_storage(&mut self, address: &Address, key: H256, value: H256) {
+            self.storage.insert((*address, key), value);
+        }
+
+        fn block_env(&self) -> BlockEnv {
+            BlockEnv {
+                number: U256::from(5),
+                ..BlockEnv::default()
+            }
+        }
+
+        fn block_hash(&self, _number: U256) -> H256 {
+            H256::zero()
+        }
+
+        fn log(&mut self, log: Log) {
+            self.logs.push(log);
+        }
+
+        fn checkpoint(&mut self) -> usize {
+            0
+        }
+
+        fn revert_to_checkpoint(&mut self, _checkpoint: usize) {}
+
+        fn call(&mut self, params: &CallParams) -> Option<CallOutcome> {
+            self.calls.push(params.address);
+            Some(CallOutcome {
+                success: true,
+                output: H256::from_low_u64_be(7).as_bytes().to_vec(),
+            })
+        }
+    }
+
+    fn execute(code: &str, is_static: bool) -> Result<VMResult, VMError> {
+        let address = Address::from_low_u64_be(0xaa);
+        let code = Code::ethfrom(code);
+        let mut host = MockHost::default();
+        host.code.insert(address, code.clone());
+        let tx = Tx::new(
+            Address::zero(),
+            address,
+            0,
+            U256::zero(),
+            Bytes::new(),
+            TxType::CallContract,
+            String::new(),
+        );
+        let limits = ExecutionLimits::default();
+        let mut substate = Substate::new(None);
+        let mut ext = Ext::new(
+            address,
+            &mut host,
+            &tx,
+            &mut substate,
+            &limits,
+            is_static,
+            tx.gas(),
+        );
+
+        VM::new(code, &limits).execute(&mut ext)
+    }
+
+    #[test]
+    fn test_static_state_change() {
+        let codes = [
+            // PUSH1 1 PUSH1 0 SSTORE
+            "6001600055",
+            // PUSH1 0 PUSH1 0 LOG0
+            "60006000a0",
+            // CREATE of empty init code
+            "600060006000f0",
+            // CREATE2 of empty init code
+            "6000600060006000f5",
+            // PUSH1 0 SELFDESTRUCT
+            "6000ff",
+            // PUSH1 1 PUSH1 0 TSTORE
+            "600160005d",
+            // CALL 0xbb with a value of 1
+            "6000600060006000600160bb6000f1",
+        ];
+
+        for code in codes {
+            assert!(execute(code, false).is_ok(), "{}", code);
+            assert!(
+                matches!(
+                    execute(code, true),
+                    Err(VMError {
+                        error_kind: VMErrorKind::StaticStateChange,
+                        ..
+                    })
+                ),
+                "{}",
+                code
+            );
+        }
+        // calls without value are allowed
+        assert!(execute("6000600060006000600060bb6000f1", true).is_ok());
+    }
+
+    #[test]
+    fn test_selfdestruct_ends_frame() {
+        // PUSH1 0 SELFDESTRUCT, then INVALID which must not run
+        assert!(matches!(execute("6000fffe", false), Ok(VMResult::Stop)));
+    }
+
+    #[test]
+    fn test_return_data_out_of_bounds() {
+        // CALL 0xbb returning 32 bytes, then RETURNDATACOPY of 32 bytes from offset 1
+        let call = "6000600060006000600060bb6000f1";
+        assert!(execute(&format!("{}6020600060003e", call), false).is_ok());
+        assert!(matches!(
+            execute(&format!("{}6020600160003e", call), false),
+            Err(VMError {
+                error_kind: VMErrorKind::ReturnDataOutOfBounds,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_bad_operands_halt() {
+        let error_kind = |code: &str| execute(code, false).err().map(|err| err.error_kind);
+        // PUSH32 2^255
+        let huge = "7f8000000000000000000000000000000000000000000000000000000000000000";
+
+        assert!(matches!(
+            error_kind("fe"),
+            Some(VMErrorKind::InvalidInstruction)
+        ));
+        // MLOAD, RETURN and SHA3 reading far away
+        for code in ["51", "6000f3", "600020"] {
+            assert!(matches!(
+                error_kind(&format!("{}{}", huge, code)),
+                Some(VMErrorKind::MemoryLimitExceeded)
+            ));
+        }
+        // JUMP far away and to a byte which is not a JUMPDEST
+        assert!(matches!(
+            error_kind(&format!("{}56", huge)),
+            Some(VMErrorKind::InvalidJump)
+        ));
+        assert!(matches!(
+            error_kind("600056"),
+            Some(VMErrorKind::InvalidJump)
+        ));
+
+        // CALLDATALOAD, BYTE and SIGNEXTEND far away, CALLDATACOPY past the end
+        for code in ["35", "60ff1a", "60ff0b"] {
+            assert!(error_kind(&format!("60ff{}{}", huge, code)).is_none());
+        }
+        assert!(error_kind(&format!("6020{}600037", huge)).is_none());
+
+        // ADD and MUL overflowing, SUB below zero, EXP overflowing,
+        // DIV, MOD, SDIV, SMOD, ADDMOD and MULMOD by zero
+        for code in [
+            "600160001901",
+            "600260001902",
+            "6001600003",
+            "61010060020a",
+            "6000600104",
+            "6000600106",
+            "6000600105",
+            "6000600107",
+            "60006001600108",
+            "60006001600109",
+        ] {
+            assert!(error_kind(code).is_none());
+        }
+    }
+
+    #[test]
+    fn test_stack_limits() {
+        let error_kind = |code: &str| execute(code, false).err().map(|err| err.error_kind);
+
+        // ADD, DUP2 and SWAP1 with one item, POP with none
+        for code in ["600101", "600181", "600190", "50"] {
+            assert!(matches!(
+                error_kind(code),
+                Some(VMErrorKind::StackUnderflow)
+            ));
+        }
+        // 1024 pushes fit, the 1025th does not
+        assert!(error_kind(&"6000".repeat(1024)).is_none());
+        assert!(matches!(
+            error_kind(&"6000".repeat(1025)),
+            Some(VMErrorKind::StackOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_execute_with_mock_host() {
+        // PUSH1 0x2a PUSH1 0 SSTORE
+        // NUMBER PUSH1 0 MSTORE PUSH1 0xaa PUSH1 0x20 PUSH1 0 LOG1
+        // CALL 0xbb with 32 bytes of output written to memory 0, RETURN memory 0..32
+        let code = Code::ethfrom(
+            "602a600055\
+             4360005260aa60206000a1\
+             6020600060006000600060bb61fffff1\
+             60206000f3",
+        );
+        let address = Address::from_low_u64_be(0xaa);
+        let callee = Address::from_low_u64_be(0xbb);
+        let mut host = MockHost::default();
+        host.code.insert(address, code.clone());
+        let tx = Tx::new(
+            Address::zero(),
+            address,
+            0,
+            U256::zero(),
+            Bytes::new(),
+            TxType::CallContract,
+            String::new(),
+        );
+        let limits = ExecutionLimits::default();
+        let mut substate = Substate::new(None);
+
+        let mut ext = Ext::new(
+            address,
+            &mut host,
+            &tx,
+            &mut substate,
+            &limits,
+            false,
+            tx.gas(),
+        );
+        let output = match VM::new(code, &limits).execute(&mut ext) {
+            Ok(VMResult::Return(output)) => output,
+            _ => std::panic!("execution failed"),
+        };
+
+        assert_eq!(output, H256::from_low_u64_be(7).as_bytes());
+        assert_eq!(
+            host.storage(&address, &H256::zero()),
+            H256::from_low_u64_be(0x2a)
+        );
+        assert_eq!(
+            host.logs,
+            vec![Log {
+                address,
+                topics: vec![H256::from_low_u64_be(0xaa)],
+                data: H256::from_low_u64_be(5).as_bytes().to_vec(),
+            }]
+        );
+        assert_eq!(host.calls, vec![callee]);
     }
 }
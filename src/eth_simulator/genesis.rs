@@ -0,0 +1,355 @@
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs, io,
+    path::Path,
+    str::FromStr,
+};
+
+use super::{
+    account::Account,
+    eth_types::{Address, Bytes, EthFrom, Secret, H256, U256},
+    evm::{BlockEnv, Fork},
+};
+use crate::utils::units;
+
+/// Block numbers or timestamps at which forks activate in a geth chain config, oldest first
+const FORK_KEYS: [(&str, Fork); 8] = [
+    ("homesteadBlock", Fork::Homestead),
+    ("byzantiumBlock", Fork::Byzantium),
+    ("constantinopleBlock", Fork::Constantinople),
+    ("istanbulBlock", Fork::Istanbul),
+    ("berlinBlock", Fork::Berlin),
+    ("londonBlock", Fork::London),
+    ("shanghaiTime", Fork::Shanghai),
+    ("cancunTime", Fork::Cancun),
+];
+
+/// Base fee of the first block from London on without `baseFeePerGas`, 1 gwei like geth
+const INITIAL_BASE_FEE: u64 = 1_000_000_000;
+
+#[derive(Debug)]
+pub enum GenesisError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    InvalidAddress(String),
+    InvalidSecretKey(String),
+    /// The secret key of an account does not belong to its address
+    WrongSecretKey(String),
+    InvalidBalance(String),
+    InvalidNonce(String),
+    InvalidCode(String),
+    InvalidStorageKey(String),
+    InvalidStorageValue(String),
+    DuplicateAddress(String),
+    /// Two accounts have the same name, or a name is taken by the state accounts are added to
+    DuplicateName(String),
+}
+
+impl fmt::Display for GenesisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenesisError::Io(err) => write!(f, "{}", err),
+            GenesisError::Json(err) => write!(f, "{}", err),
+            GenesisError::InvalidAddress(address) => write!(f, "invalid address {}", address),
+            GenesisError::InvalidSecretKey(address) => {
+                write!(f, "invalid secret key of {}", address)
+            }
+            GenesisError::WrongSecretKey(address) => {
+                write!(f, "secret key does not belong to {}", address)
+            }
+            GenesisError::InvalidBalance(address) => write!(f, "invalid balance of {}", address),
+            GenesisError::InvalidNonce(address) => write!(f, "invalid nonce of {}", address),
+            GenesisError::InvalidCode(address) => write!(f, "invalid code of {}", address),
+            GenesisError::InvalidStorageKey(address) => {
+                write!(f, "invalid storage key of {}", address)
+            }
+            GenesisError::InvalidStorageValue(address) => {
+                write!(f, "invalid storage value of {}", address)
+            }
+            GenesisError::DuplicateAddress(address) => {
+                write!(f, "duplicate address {}", address)
+            }
+            GenesisError::DuplicateName(name) => write!(f, "duplicate name {}", name),
+        }
+    }
+}
+
+/// Initial state and environment of the chain
+pub struct Genesis {
+    pub fork: Fork,
+    pub block_env: BlockEnv,
+    /// Timestamp of the first block, wall clock time if not set
+    pub timestamp: Option<U256>,
+    pub accounts: Vec<Account>,
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Genesis {
+            fork: Fork::default(),
+            block_env: BlockEnv {
+                gas_limit: U256::from(30_000_000),
+                base_fee: initial_base_fee(Fork::default()),
+                ..BlockEnv::default()
+            },
+            timestamp: None,
+            accounts: Vec::new(),
+        }
+    }
+}
+
+fn initial_base_fee(fork: Fork) -> U256 {
+    if fork.has_base_fee() {
+        U256::from(INITIAL_BASE_FEE)
+    } else {
+        U256::zero()
+    }
+}
+
+/// Number given as hex or decimal string, or as JSON number.
+/// Strings may carry a unit like `100ether`.
+struct Quantity(U256);
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let quantity = match Value::deserialize(deserializer)? {
+            Value::String(quantity) => units::parse_amount(&quantity),
+            Value::Number(quantity) => quantity.as_u64().map(U256::from),
+            _ => None,
+        };
+
+        quantity
+            .map(Quantity)
+            .ok_or_else(|| de::Error::custom("invalid quantity"))
+    }
+}
+
+/// Error if `account` has one of `names`, which its name is added to. Unnamed accounts are fine.
+pub(super) fn check_name(
+    names: &mut HashSet<String>,
+    account: &Account,
+) -> Result<(), GenesisError> {
+    let name = account.get_name();
+    if !name.is_empty() && !names.insert(name.to_string()) {
+        return Err(GenesisError::DuplicateName(name.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Genesis file in the format of geth
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenesisFile {
+    #[serde(default)]
+    config: Map<String, Value>,
+    coinbase: Option<String>,
+    timestamp: Option<Quantity>,
+    difficulty: Option<Quantity>,
+    gas_limit: Option<Quantity>,
+    base_fee_per_gas: Option<Quantity>,
+    #[serde(default)]
+    alloc: BTreeMap<String, GenesisAccount>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenesisAccount {
+    /// Name the account can be referred to by, not part of the geth format
+    #[serde(default)]
+    name: String,
+    secret_key: Option<String>,
+    balance: Option<Quantity>,
+    nonce: Option<Quantity>,
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    storage: BTreeMap<String, Quantity>,
+}
+
+impl Genesis {
+    pub fn from_file(path: &Path) -> Result<Self, GenesisError> {
+        Self::from_json(&fs::read_to_string(path).map_err(GenesisError::Io)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, GenesisError> {
+        let file: GenesisFile = serde_json::from_str(json).map_err(GenesisError::Json)?;
+        let default = Genesis::default();
+        let fork = Self::fork(&file.config);
+
+        let coinbase = match &file.coinbase {
+            Some(coinbase) => parse_address(coinbase)?,
+            None => Address::zero(),
+        };
+        let block_env = BlockEnv {
+            coinbase,
+            difficulty: file.difficulty.map_or(U256::zero(), |q| q.0),
+            gas_limit: file.gas_limit.map_or(default.block_env.gas_limit, |q| q.0),
+            base_fee: file
+                .base_fee_per_gas
+                .map_or(initial_base_fee(fork), |q| q.0),
+            chain_id: file
+                .config
+                .get("chainId")
+                .and_then(Value::as_u64)
+                .map_or(U256::zero(), U256::from),
+            ..default.block_env
+        };
+
+        let mut accounts: Vec<Account> = Vec::new();
+        let mut names = HashSet::new();
+        for (address, alloc) in file.alloc {
+            let account = Self::account(&address, alloc)?;
+            if accounts
+                .iter()
+                .any(|other| other.get_address() == account.get_address())
+            {
+                return Err(GenesisError::DuplicateAddress(address));
+            }
+            check_name(&mut names, &account)?;
+            accounts.push(account);
+        }
+
+        Ok(Genesis {
+            fork,
+            block_env,
+            timestamp: file.timestamp.map(|q| q.0),
+            accounts,
+        })
+    }
+
+    /// Latest fork active at genesis, the latest fork of all without config
+    fn fork(config: &Map<String, Value>) -> Fork {
+        if config.is_empty() {
+            return Fork::default();
+        }
+
+        FORK_KEYS
+            .iter()
+            .rev()
+            .find(|(key, _)| config.get(*key).and_then(Value::as_u64) == Some(0))
+            .map_or(Fork::Frontier, |(_, fork)| *fork)
+    }
+
+    fn account(address: &str, alloc: GenesisAccount) -> Result<Account, GenesisError> {
+        let parsed = parse_address(address)?;
+        let code = parse_hex(&alloc.code).ok_or(GenesisError::InvalidCode(address.to_string()))?;
+
+        let mut account = match &alloc.secret_key {
+            Some(secret_key) => {
+                let secret_key = secret_key.trim();
+                let secret_key =
+                    Secret::from_str(secret_key.strip_prefix("0x").unwrap_or(secret_key))
+                        .map_err(|_| GenesisError::InvalidSecretKey(address.to_string()))?;
+                let mut account = Account::from_private_key(alloc.name, secret_key)
+                    .ok_or(GenesisError::InvalidSecretKey(address.to_string()))?;
+                if *account.get_address() != parsed {
+                    return Err(GenesisError::WrongSecretKey(address.to_string()));
+                }
+                account.set_code(code);
+                account
+            }
+            None => Account::with_address(alloc.name, parsed, code),
+        };
+
+        account.set_balance(alloc.balance.map_or(U256::zero(), |q| q.0));
+        if let Some(nonce) = alloc.nonce {
+            if nonce.0 > U256::from(usize::MAX) {
+                return Err(GenesisError::InvalidNonce(address.to_string()));
+            }
+            account.set_nonce(nonce.0.as_usize());
+        }
+        for (key, value) in alloc.storage {
+            let key = units::parse_amount(&key)
+                .ok_or(GenesisError::InvalidStorageKey(address.to_string()))?;
+            account.set_storage(H256::ethfrom(key), H256::ethfrom(value.0));
+        }
+
+        Ok(account)
+    }
+}
+
+/// Address with or without `0x` prefix
+pub(super) fn parse_address(address: &str) -> Result<Address, GenesisError> {
+    let trimmed = address.trim();
+    let hex = trimmed.strip_prefix("0x").unwrap_or(trimmed);
+    if hex.len() != 40 {
+        return Err(GenesisError::InvalidAddress(address.to_string()));
+    }
+
+    Address::from_str(hex).map_err(|_| GenesisError::InvalidAddress(address.to_string()))
+}
+
+/// Bytes given as hex with or without `0x` prefix
+pub(super) fn parse_hex(hex: &str) -> Option<Bytes> {
+    let hex = hex.trim();
+    let hex = hex.strip_prefix("0x").unwrap_or(hex).to_lowercase();
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(Bytes::ethfrom(hex.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_json() {
+        let genesis = Genesis::from_json(
+            r#"{
+                "config": { "chainId": 1337, "homesteadBlock": 0, "berlinBlock": 0, "londonBlock": 5 },
+                "coinbase": "0x00000000000000000000000000000000000000cc",
+                "gasLimit": "0x1c9c380",
+                "alloc": {
+                    "f39fd6e51aad88f6f4ce6ab8827279cfffb92266": {
+                        "name": "alice",
+                        "secretKey": "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+                        "balance": "1000000000000000000"
+                    },
+                    "0x00000000000000000000000000000000000000aa": {
+                        "balance": "0x10",
+                        "nonce": "0x1",
+                        "code": "0x6000",
+                        "storage": { "0x01": "0x02" }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(genesis.fork, Fork::Berlin);
+        assert_eq!(genesis.block_env.chain_id, U256::from(1337));
+        assert_eq!(genesis.block_env.gas_limit, U256::from(30_000_000));
+        // London is not active at genesis
+        assert_eq!(genesis.block_env.base_fee, U256::zero());
+        assert_eq!(
+            Genesis::from_json("{}").unwrap().block_env.base_fee,
+            U256::from(INITIAL_BASE_FEE)
+        );
+        assert_eq!(genesis.accounts.len(), 2);
+
+        let contract = &genesis.accounts[0];
+        assert_eq!(contract.get_balance(), U256::from(16));
+        assert_eq!(contract.get_nonce(), 1);
+        assert!(contract.is_contract());
+        assert_eq!(
+            contract.get_storage(&H256::from_low_u64_be(1)),
+            H256::from_low_u64_be(2)
+        );
+
+        let alice = &genesis.accounts[1];
+        assert_eq!(alice.get_name(), "alice");
+        assert_eq!(alice.get_balance(), U256::exp10(18));
+        assert!(alice.get_private_key().is_some());
+
+        assert!(matches!(
+            Genesis::from_json(r#"{ "alloc": { "0x12": {} } }"#),
+            Err(GenesisError::InvalidAddress(_))
+        ));
+    }
+}
@@ -1,9 +1,52 @@
 use keccak_hash::write_keccak;
+use rlp::RlpStream;
 
-use super::eth_types::H256;
+use super::eth_types::{Address, H256};
 
 pub fn keccak<T: AsRef<[u8]>>(s: T) -> H256 {
     let mut result = [0u8; 32];
     write_keccak(s, &mut result);
     H256(result)
 }
+
+/// Address of a contract created by `sender` with CREATE or a deploy transaction
+pub fn create_address(sender: &Address, nonce: usize) -> Address {
+    let mut stream = RlpStream::new_list(2);
+    stream.append(sender);
+    stream.append(&nonce);
+
+    Address::from(keccak(stream.out()))
+}
+
+/// Address of a contract created by `sender` with CREATE2
+pub fn create2_address(sender: &Address, salt: &H256, init_code: &[u8]) -> Address {
+    let mut input = vec![0xff];
+    input.extend_from_slice(sender.as_bytes());
+    input.extend_from_slice(salt.as_bytes());
+    input.extend_from_slice(keccak(init_code).as_bytes());
+
+    Address::from(keccak(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::eth_types::EthFrom, *};
+
+    #[test]
+    fn test_create_address() {
+        let sender = Address::ethfrom("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        assert_eq!(
+            create_address(&sender, 0),
+            Address::ethfrom("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+        );
+        assert_eq!(
+            create_address(&sender, 1),
+            Address::ethfrom("343c43a37d37dff08ae8c4a11544c718abb4fcf8")
+        );
+        // EIP-1014 example 0
+        assert_eq!(
+            create2_address(&Address::zero(), &H256::zero(), &[0x00]),
+            Address::ethfrom("4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38")
+        );
+    }
+}
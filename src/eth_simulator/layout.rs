@@ -0,0 +1,398 @@
+use serde_json::Value;
+
+use std::collections::HashMap;
+
+use super::{
+    eth_types::{Address, Bytes, EthFrom, H256, U256},
+    hash,
+};
+
+/// Elements of arrays and bytes of strings decoded at most
+const MAX_ELEMENTS: usize = 1000;
+
+/// State variable, or part of one like a mapping entry, decoded from storage
+#[derive(Debug, PartialEq)]
+pub struct StorageVariable {
+    /// Path of the value like `balances[0x..]`, `items[2]` or `owner.name`
+    pub name: String,
+    /// Solidity type like `uint256` or `string`
+    pub label: String,
+    pub slot: H256,
+    /// Byte offset inside the slot of values packed with others
+    pub offset: usize,
+    pub value: String,
+}
+
+/// Decoder of storage following the `storageLayout` output of solc
+pub struct Layout<'a, F: Fn(&H256) -> H256> {
+    layout: &'a Value,
+    storage: F,
+    /// Keys of the mapping entries by the slot of the mapping, with the slot of each entry
+    keys: HashMap<H256, Vec<(&'a [u8], H256)>>,
+}
+
+impl<'a, F: Fn(&H256) -> H256> Layout<'a, F> {
+    /// Keys of mappings are taken from the inputs of keccak hashes seen during execution,
+    /// whose last 32 bytes are the slot of the mapping
+    pub fn new(layout: &'a Value, storage: F, preimages: &'a HashMap<H256, Bytes>) -> Self {
+        let mut keys: HashMap<H256, Vec<(&[u8], H256)>> = HashMap::new();
+        for (hash, preimage) in preimages {
+            let (key, slot) = preimage.split_at(preimage.len() - 32);
+            keys.entry(H256::from_slice(slot))
+                .or_default()
+                .push((key, *hash));
+        }
+        for entries in keys.values_mut() {
+            entries.sort();
+        }
+
+        Layout {
+            layout,
+            storage,
+            keys,
+        }
+    }
+
+    /// All state variables in declaration order, `None` if the layout is not valid
+    pub fn decode(&self) -> Option<Vec<StorageVariable>> {
+        let mut variables = Vec::new();
+        for variable in self.layout["storage"].as_array()? {
+            self.decode_type(
+                variable["label"].as_str()?.to_string(),
+                variable["type"].as_str()?,
+                U256::from_dec_str(variable["slot"].as_str()?).ok()?,
+                variable["offset"].as_u64()? as usize,
+                false,
+                &mut variables,
+            )?;
+        }
+
+        Some(variables)
+    }
+
+    /// Values of type `ty` at `slot`, in mappings only those which are not zero
+    fn decode_type(
+        &self,
+        name: String,
+        ty: &str,
+        slot: U256,
+        offset: usize,
+        in_mapping: bool,
+        variables: &mut Vec<StorageVariable>,
+    ) -> Option<()> {
+        let info = &self.layout["types"][ty];
+        let label = info["label"].as_str()?;
+
+        match info["encoding"].as_str()? {
+            "mapping" => {
+                let slot = H256::ethfrom(slot);
+                let key_type = info["key"].as_str()?;
+                let value_type = info["value"].as_str()?;
+                for (key, entry) in self.keys.get(&slot).into_iter().flatten() {
+                    // other hashes may end with the slot too
+                    let key = match self.format_key(key_type, key) {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    self.decode_type(
+                        format!("{}[{}]", name, key),
+                        value_type,
+                        U256::ethfrom(*entry),
+                        0,
+                        true,
+                        variables,
+                    )?;
+                }
+            }
+            "dynamic_array" => {
+                let length = U256::ethfrom(self.load(slot));
+                variables.push(StorageVariable {
+                    name: format!("{}.length", name),
+                    label: "uint256".to_string(),
+                    slot: H256::ethfrom(slot),
+                    offset: 0,
+                    value: length.to_string(),
+                });
+                let start = U256::ethfrom(hash::keccak(H256::ethfrom(slot)));
+                let length = length.min(U256::from(MAX_ELEMENTS)).as_usize();
+                self.decode_elements(&name, info["base"].as_str()?, start, length, variables)?;
+            }
+            "bytes" => {
+                let (length, value) = self.load_bytes(slot);
+                if !(in_mapping && length == 0) {
+                    variables.push(StorageVariable {
+                        name,
+                        label: label.to_string(),
+                        slot: H256::ethfrom(slot),
+                        offset: 0,
+                        value: format_bytes(label, &value),
+                    });
+                }
+            }
+            "inplace" => {
+                if let Some(members) = info["members"].as_array() {
+                    for member in members {
+                        self.decode_type(
+                            format!("{}.{}", name, member["label"].as_str()?),
+                            member["type"].as_str()?,
+                            slot + U256::from_dec_str(member["slot"].as_str()?).ok()?,
+                            member["offset"].as_u64()? as usize,
+                            in_mapping,
+                            variables,
+                        )?;
+                    }
+                } else if let Some(base) = info["base"].as_str() {
+                    // the length of static arrays is the last dimension of the label
+                    let length = label
+                        .rsplit('[')
+                        .next()?
+                        .trim_end_matches(']')
+                        .parse()
+                        .ok()?;
+                    self.decode_elements(&name, base, slot, length, variables)?;
+                } else {
+                    let size = self.size(ty)?;
+                    let word = self.load(slot);
+                    let bytes = word.as_bytes().get(32 - offset - size..32 - offset)?;
+                    if !(in_mapping && bytes.iter().all(|byte| *byte == 0)) {
+                        variables.push(StorageVariable {
+                            name,
+                            label: label.to_string(),
+                            slot: H256::ethfrom(slot),
+                            offset,
+                            value: format_value(label, bytes),
+                        });
+                    }
+                }
+            }
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    /// `length` elements of type `base` from `start`, at most `MAX_ELEMENTS`,
+    /// elements of up to 16 bytes are packed into slots
+    fn decode_elements(
+        &self,
+        name: &str,
+        base: &str,
+        start: U256,
+        length: usize,
+        variables: &mut Vec<StorageVariable>,
+    ) -> Option<()> {
+        let size = self.size(base)?;
+        for index in 0..length.min(MAX_ELEMENTS) {
+            let (slot, offset) = if size <= 16 {
+                let per_slot = 32 / size;
+                (start + index / per_slot, index % per_slot * size)
+            } else {
+                (start + index * size.div_ceil(32), 0)
+            };
+            self.decode_type(
+                format!("{}[{}]", name, index),
+                base,
+                slot,
+                offset,
+                false,
+                variables,
+            )?;
+        }
+
+        Some(())
+    }
+
+    fn size(&self, ty: &str) -> Option<usize> {
+        self.layout["types"][ty]["numberOfBytes"]
+            .as_str()?
+            .parse()
+            .ok()
+    }
+
+    fn load(&self, slot: U256) -> H256 {
+        (self.storage)(&H256::ethfrom(slot))
+    }
+
+    /// Length and content of a string or bytes, short ones are stored with
+    /// twice their length in the last byte, long ones with twice their length
+    /// plus one and the content from the keccak hash of the slot
+    fn load_bytes(&self, slot: U256) -> (usize, Bytes) {
+        let word = self.load(slot);
+        let value = U256::ethfrom(word);
+        if !value.bit(0) {
+            let length = (word[31] / 2) as usize;
+            return (length, word[..length.min(31)].to_vec());
+        }
+
+        let length = ((value - 1) / 2).min(U256::from(MAX_ELEMENTS)).as_usize();
+        let start = U256::ethfrom(hash::keccak(H256::ethfrom(slot)));
+        let mut bytes: Bytes = (0..length.div_ceil(32))
+            .flat_map(|index| self.load(start + index).as_bytes().to_vec())
+            .collect();
+        bytes.truncate(length);
+
+        (length, bytes)
+    }
+
+    /// Mapping key as hashed: value types padded to 32 bytes, strings and bytes as they are
+    fn format_key(&self, ty: &str, key: &[u8]) -> Option<String> {
+        let info = &self.layout["types"][ty];
+        let label = info["label"].as_str()?;
+        if info["encoding"] == "bytes" {
+            return Some(format_bytes(label, key));
+        }
+        if key.len() != 32 {
+            return None;
+        }
+
+        let size = self.size(ty)?;
+        // fixed bytes are left aligned, the other value types right aligned
+        let bytes = if label.starts_with("bytes") {
+            &key[..size]
+        } else {
+            &key[32 - size..]
+        };
+        Some(format_value(label, bytes))
+    }
+}
+
+/// Value type given by its bytes, in the way Solidity writes it
+fn format_value(label: &str, bytes: &[u8]) -> String {
+    let value = U256::from_big_endian(bytes);
+
+    if label == "bool" {
+        (!value.is_zero()).to_string()
+    } else if label.starts_with("address") || label.starts_with("contract ") {
+        String::ethfrom(&Address::from_slice(&H256::ethfrom(value)[12..]))
+    } else if label.starts_with("uint") || label.starts_with("enum ") {
+        value.to_string()
+    } else if label.starts_with("int") {
+        let bits = bytes.len() * 8;
+        if !value.bit(bits - 1) {
+            return value.to_string();
+        }
+        // two's complement in `bits` bits
+        let magnitude = if bits == 256 {
+            (!value).overflowing_add(U256::one()).0
+        } else {
+            ((U256::one() << bits) - value) & ((U256::one() << bits) - 1)
+        };
+        format!("-{}", magnitude)
+    } else {
+        String::ethfrom(bytes)
+    }
+}
+
+fn format_bytes(label: &str, bytes: &[u8]) -> String {
+    if label == "string" {
+        format!("{:?}", String::from_utf8_lossy(bytes))
+    } else {
+        String::ethfrom(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // uint256 total; int8 delta; bool open; mapping(address => uint256) balances;
+        // uint16[] items; string name;
+        let layout = json!({
+            "storage": [
+                {"label": "total", "offset": 0, "slot": "0", "type": "t_uint256"},
+                {"label": "delta", "offset": 0, "slot": "1", "type": "t_int8"},
+                {"label": "open", "offset": 1, "slot": "1", "type": "t_bool"},
+                {"label": "balances", "offset": 0, "slot": "2",
+                    "type": "t_mapping(t_address,t_uint256)"},
+                {"label": "items", "offset": 0, "slot": "3",
+                    "type": "t_array(t_uint16)dyn_storage"},
+                {"label": "name", "offset": 0, "slot": "4", "type": "t_string_storage"}
+            ],
+            "types": {
+                "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+                "t_bool": {"encoding": "inplace", "label": "bool", "numberOfBytes": "1"},
+                "t_int8": {"encoding": "inplace", "label": "int8", "numberOfBytes": "1"},
+                "t_uint16": {"encoding": "inplace", "label": "uint16", "numberOfBytes": "2"},
+                "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"},
+                "t_mapping(t_address,t_uint256)": {"encoding": "mapping", "key": "t_address",
+                    "label": "mapping(address => uint256)", "numberOfBytes": "32",
+                    "value": "t_uint256"},
+                "t_array(t_uint16)dyn_storage": {"base": "t_uint16", "encoding": "dynamic_array",
+                    "label": "uint16[]", "numberOfBytes": "32"},
+                "t_string_storage": {"encoding": "bytes", "label": "string",
+                    "numberOfBytes": "32"}
+            }
+        });
+
+        let slot = |n: u64| H256::from_low_u64_be(n);
+        let owner = Address::repeat_byte(0xaa);
+        let mut preimage = H256::from(owner).as_bytes().to_vec();
+        preimage.extend(slot(2).as_bytes());
+        let entry = hash::keccak(&preimage);
+        let items = U256::ethfrom(hash::keccak(slot(3)));
+
+        let mut storage = HashMap::new();
+        storage.insert(slot(0), slot(42));
+        storage.insert(slot(1), H256::from_low_u64_be(0x01ff));
+        storage.insert(entry, slot(7));
+        storage.insert(slot(3), slot(2));
+        storage.insert(H256::ethfrom(items), H256::from_low_u64_be(0x0009_0005));
+        let mut name = [0u8; 32];
+        name[..3].copy_from_slice(b"abc");
+        name[31] = 6;
+        storage.insert(slot(4), H256(name));
+        let preimages = HashMap::from([(entry, preimage)]);
+
+        let decoder = Layout::new(
+            &layout,
+            |key: &H256| storage.get(key).copied().unwrap_or_default(),
+            &preimages,
+        );
+        let values: Vec<(String, String)> = decoder
+            .decode()
+            .unwrap()
+            .into_iter()
+            .map(|variable| (variable.name, variable.value))
+            .collect();
+        let expected = [
+            ("total", "42"),
+            ("delta", "-1"),
+            ("open", "true"),
+            (&format!("balances[{}]", String::ethfrom(&owner)), "7"),
+            ("items.length", "2"),
+            ("items[0]", "5"),
+            ("items[1]", "9"),
+            ("name", "\"abc\""),
+        ];
+        assert_eq!(
+            values,
+            expected
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_static_array_capped() {
+        let layout = json!({
+            "storage": [
+                {"label": "values", "offset": 0, "slot": "0",
+                    "type": "t_array(t_uint256)5000_storage"}
+            ],
+            "types": {
+                "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"},
+                "t_array(t_uint256)5000_storage": {"base": "t_uint256", "encoding": "inplace",
+                    "label": "uint256[5000]", "numberOfBytes": "160000"}
+            }
+        });
+        let preimages = HashMap::new();
+        let decoder = Layout::new(&layout, |_: &H256| H256::zero(), &preimages);
+
+        assert_eq!(decoder.decode().unwrap().len(), MAX_ELEMENTS);
+    }
+}
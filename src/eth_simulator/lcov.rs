@@ -0,0 +1,126 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+};
+
+use super::{eth_types::Code, evm::Coverage, hash};
+use crate::utils::solc;
+
+const OP_JUMPI: u8 = 0x57;
+
+#[derive(Default)]
+struct FileCoverage {
+    /// Execution count of each line with code
+    lines: BTreeMap<usize, usize>,
+    /// Line and outcome counts of each JUMPI, `None` if it was never executed
+    branches: Vec<(usize, Option<[usize; 2]>)>,
+}
+
+/// Line and branch coverage of deployed contracts in LCOV format.
+/// A line is as often executed as its most executed instruction,
+/// the branches of a line are the two outcomes of each of its JUMPIs.
+pub fn report(contracts: &[(&solc::Contract, &Code)], coverage: &Coverage) -> String {
+    let mut files: BTreeMap<String, FileCoverage> = BTreeMap::new();
+    let mut code_hashes = HashSet::new();
+
+    for (contract, code) in contracts {
+        let code_hash = hash::keccak(code);
+        // contracts deployed from the same code share their coverage
+        if !code_hashes.insert(code_hash) {
+            continue;
+        }
+        let hits = coverage.hits(&code_hash);
+        let branches = coverage.branches(&code_hash);
+
+        for (pc, location) in contract.source_locations(code) {
+            let location = match location {
+                Some(location) => location,
+                None => continue,
+            };
+            let file = files.entry(location.file).or_default();
+
+            let count = hits.and_then(|hits| hits.get(&pc)).copied().unwrap_or(0);
+            let line = file.lines.entry(location.line).or_default();
+            *line = (*line).max(count);
+
+            if code[pc] == OP_JUMPI {
+                let outcomes = branches.and_then(|branches| branches.get(&pc)).copied();
+                file.branches.push((location.line, outcomes));
+            }
+        }
+    }
+
+    let mut lcov = String::new();
+    for (path, file) in files {
+        writeln!(lcov, "TN:").unwrap();
+        writeln!(lcov, "SF:{}", path).unwrap();
+
+        let mut branches_hit = 0;
+        for (block, (line, outcomes)) in file.branches.iter().enumerate() {
+            for branch in 0..2 {
+                let taken = match outcomes {
+                    Some(outcomes) => {
+                        if outcomes[branch] > 0 {
+                            branches_hit += 1;
+                        }
+                        outcomes[branch].to_string()
+                    }
+                    None => "-".to_string(),
+                };
+                writeln!(lcov, "BRDA:{},{},{},{}", line, block, branch, taken).unwrap();
+            }
+        }
+        writeln!(lcov, "BRF:{}", file.branches.len() * 2).unwrap();
+        writeln!(lcov, "BRH:{}", branches_hit).unwrap();
+
+        for (line, count) in &file.lines {
+            writeln!(lcov, "DA:{},{}", line, count).unwrap();
+        }
+        writeln!(lcov, "LF:{}", file.lines.len()).unwrap();
+        writeln!(
+            lcov,
+            "LH:{}",
+            file.lines.values().filter(|&&count| count > 0).count()
+        )
+        .unwrap();
+        writeln!(lcov, "end_of_record").unwrap();
+    }
+
+    lcov
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        // 0x00: PUSH1 0 CALLDATALOAD PUSH1 7 JUMPI
+        // 0x06: STOP
+        // 0x07: JUMPDEST STOP
+        let code: Code = vec![0x60, 0x00, 0x35, 0x60, 0x07, 0x57, 0x00, 0x5b, 0x00];
+        let contract = solc::Contract {
+            bin: String::new(),
+            bin_runtime: String::new(),
+            abi: serde_json::Value::Null,
+            srcmap_runtime: "0:30:0;15:3;;;22:3;;".to_string(),
+            sources: vec![(
+                "A.sol".to_string(),
+                "contract A {\n  f();\n  g();\n}\n".to_string(),
+            )],
+            storage_layout: serde_json::Value::Null,
+        };
+        let code_hash = hash::keccak(&code);
+        let mut coverage = Coverage::new();
+        for pc in [0x00, 0x02, 0x03, 0x05, 0x06] {
+            coverage.record_pc(&code_hash, pc);
+        }
+        coverage.record_branch(&code_hash, 0x05, false);
+
+        assert_eq!(
+            report(&[(&contract, &code)], &coverage),
+            "TN:\nSF:A.sol\nBRDA:2,0,0,0\nBRDA:2,0,1,1\nBRF:2\nBRH:1\n\
+             DA:1,1\nDA:2,1\nDA:3,1\nLF:3\nLH:3\nend_of_record\n"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use ethereum_types::BigEndianHash;
+use rlp::Rlp;
+
+use super::{
+    account::Account,
+    eth_types::{Address, Bytes, H256, U256},
+    hash,
+    trie::{self, ProofError},
+};
+
+/// Account with the trie nodes proving it against a state root,
+/// an account that does not exist is proven absent with empty fields
+pub struct AccountProof {
+    pub address: Address,
+    pub nonce: U256,
+    pub balance: U256,
+    pub code_hash: H256,
+    pub storage_root: H256,
+    pub proof: Vec<Bytes>,
+    pub storage: Vec<StorageProof>,
+}
+
+/// Storage slot with the trie nodes proving it against a storage root
+pub struct StorageProof {
+    pub key: H256,
+    pub value: U256,
+    pub proof: Vec<Bytes>,
+}
+
+/// Nonce, balance, storage root and code hash of an account in the state trie
+#[derive(Debug, PartialEq)]
+pub struct ProvenAccount {
+    pub nonce: U256,
+    pub balance: U256,
+    pub storage_root: H256,
+    pub code_hash: H256,
+}
+
+impl AccountProof {
+    /// Proof of `address` and its `keys` in the trie of `accounts`
+    pub fn new<'a>(
+        accounts: impl Iterator<Item = &'a Account>,
+        address: &Address,
+        keys: &[H256],
+    ) -> Self {
+        let accounts: Vec<&Account> = accounts.collect();
+        let items: Vec<(Bytes, Bytes)> = accounts
+            .iter()
+            .map(|account| {
+                (
+                    hash::keccak(account.get_address()).as_bytes().to_vec(),
+                    account.rlp(),
+                )
+            })
+            .collect();
+        let proof = trie::proof(&items, hash::keccak(address).as_bytes());
+
+        match accounts
+            .iter()
+            .find(|account| account.get_address() == address)
+        {
+            Some(account) => {
+                let slots = account.storage_items();
+                AccountProof {
+                    address: *address,
+                    nonce: U256::from(account.get_nonce()),
+                    balance: account.get_balance(),
+                    code_hash: account.get_code_hash(),
+                    storage_root: trie::root(&slots),
+                    proof,
+                    storage: keys
+                        .iter()
+                        .map(|key| StorageProof {
+                            key: *key,
+                            value: account.get_storage(key).into_uint(),
+                            proof: trie::proof(&slots, hash::keccak(key).as_bytes()),
+                        })
+                        .collect(),
+                }
+            }
+            None => AccountProof {
+                address: *address,
+                nonce: U256::zero(),
+                balance: U256::zero(),
+                code_hash: hash::keccak([]),
+                storage_root: trie::EMPTY_ROOT,
+                proof,
+                storage: keys
+                    .iter()
+                    .map(|key| StorageProof {
+                        key: *key,
+                        value: U256::zero(),
+                        proof: Vec::new(),
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Account of `address` proven by `proof` against `state_root`,
+/// `None` if the proof shows the account does not exist
+pub fn verify_account_proof(
+    state_root: &H256,
+    address: &Address,
+    proof: &[Bytes],
+) -> Result<Option<ProvenAccount>, ProofError> {
+    let account = match trie::verify_proof(state_root, hash::keccak(address).as_bytes(), proof)? {
+        Some(account) => account,
+        None => return Ok(None),
+    };
+
+    let rlp = Rlp::new(&account);
+    let field = |index| rlp.at(index).map_err(|_| ProofError::InvalidNode);
+    let hash = |index| -> Result<H256, ProofError> {
+        let bytes = field(index)?.data().map_err(|_| ProofError::InvalidNode)?;
+        if bytes.len() != 32 {
+            return Err(ProofError::InvalidNode);
+        }
+        Ok(H256::from_slice(bytes))
+    };
+
+    Ok(Some(ProvenAccount {
+        nonce: field(0)?.as_val().map_err(|_| ProofError::InvalidNode)?,
+        balance: field(1)?.as_val().map_err(|_| ProofError::InvalidNode)?,
+        storage_root: hash(2)?,
+        code_hash: hash(3)?,
+    }))
+}
+
+/// Value of slot `key` proven by `proof` against `storage_root`, zero if the slot is absent
+pub fn verify_storage_proof(
+    storage_root: &H256,
+    key: &H256,
+    proof: &[Bytes],
+) -> Result<U256, ProofError> {
+    match trie::verify_proof(storage_root, hash::keccak(key).as_bytes(), proof)? {
+        Some(value) => rlp::decode(&value).map_err(|_| ProofError::InvalidNode),
+        None => Ok(U256::zero()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::eth_types::Code, *};
+
+    #[test]
+    fn test_account_proof() {
+        let mut contract =
+            Account::with_address(String::new(), Address::repeat_byte(0xaa), Code::from([0]));
+        contract.set_storage(H256::from_low_u64_be(1), H256::from_low_u64_be(42));
+        let accounts = [Account::new("alice".to_string()), contract];
+        let state_root = trie::root(
+            &accounts
+                .iter()
+                .map(|account| {
+                    (
+                        hash::keccak(account.get_address()).as_bytes().to_vec(),
+                        account.rlp(),
+                    )
+                })
+                .collect::<Vec<(Bytes, Bytes)>>(),
+        );
+
+        let keys = [H256::from_low_u64_be(1), H256::from_low_u64_be(2)];
+        let proof = AccountProof::new(accounts.iter(), &Address::repeat_byte(0xaa), &keys);
+        let account = verify_account_proof(&state_root, &proof.address, &proof.proof)
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.storage_root, proof.storage_root);
+        assert_eq!(account.code_hash, hash::keccak([0]));
+        for storage in &proof.storage {
+            assert_eq!(
+                verify_storage_proof(&account.storage_root, &storage.key, &storage.proof),
+                Ok(storage.value)
+            );
+        }
+        assert_eq!(proof.storage[0].value, U256::from(42));
+
+        let absent = AccountProof::new(accounts.iter(), &Address::repeat_byte(0xbb), &keys);
+        assert_eq!(
+            verify_account_proof(&state_root, &absent.address, &absent.proof),
+            Ok(None)
+        );
+    }
+}
@@ -1,12 +1,29 @@
-use std::collections::HashMap;
+mod journal;
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use super::{
     account::Account,
     block::Block,
-    eth_types::{Address, Bytes, Code, EthFrom, H256},
-    evm::{Ext, VMError, VMResult, VM},
+    diff::StateDiff,
+    dump::Dump,
+    eth_types::{Address, Bytes, Code, Secret, H256, U256},
+    evm::{
+        intrinsic_gas, next_base_fee, BlockEnv, Coverage, ExecutionLimits, Ext, Fork, Host, Log,
+        Substate, VMError, VMErrorKind, VMResult, VM,
+    },
+    genesis::Genesis,
+    hash,
+    proof::AccountProof,
+    trie,
     tx::{Tx, TxType},
 };
+use journal::{Journal, JournalEntry};
 
 #[derive(Debug)]
 pub enum StateError {
@@ -20,21 +37,232 @@ pub enum TxError {
     CallEoAAccount,
     WrongFromAddress(Address),
     WrongToAddress(Address),
+    NonceTooLow {
+        expected: usize,
+        actual: usize,
+    },
+    NonceTooHigh {
+        expected: usize,
+        actual: usize,
+    },
+    /// The sender has no private key to sign with and is not impersonated
+    NoPrivateKey,
+    /// The gas limit does not cover the intrinsic gas
+    IntrinsicGasTooLow {
+        intrinsic: usize,
+        limit: usize,
+    },
+    /// The gas limit is above the gas limit of the block
+    GasLimitExceeded,
+    /// The max fee per gas, or the gas price, is below the base fee of the block
+    FeeCapTooLow {
+        fee_cap: U256,
+        base_fee: U256,
+    },
+    /// The max priority fee per gas is above the max fee per gas
+    TipAboveFeeCap,
+    /// Dynamic fee transactions before London
+    TxTypeNotSupported,
+}
+
+/// Keccak preimages recorded at most, to keep the state of long simulations bounded
+const MAX_PREIMAGES: usize = 100_000;
+
+/// Id of a snapshot, counting from 1. Ids are not reused after a revert.
+pub type SnapshotId = usize;
+
+/// Position of the journal at the time of a snapshot, the changes made since
+/// are undone to revert. Blocks and transactions are only appended,
+/// so their numbers are enough to restore them.
+struct Snapshot {
+    id: SnapshotId,
+    checkpoint: usize,
+    blocks: usize,
+    txs: usize,
+}
+
+/// Changes made up to a block since the block before
+#[derive(Serialize, Deserialize)]
+struct BlockChanges {
+    entries: Vec<JournalEntry>,
+    /// Position of the first change made by the transaction of the block,
+    /// those before were made outside of transactions
+    tx_start: usize,
 }
 
+/// Limits, coverage and snapshots are not saved with the state
+#[derive(Serialize, Deserialize)]
 pub struct State {
     accounts: HashMap<Address, Account>,
     blocks: Vec<Block>,
     txs: Vec<Tx>,
+    #[serde(skip)]
+    limits: ExecutionLimits,
+    #[serde(skip)]
+    coverage: Option<Coverage>,
+    fork: Fork,
+    block_env: BlockEnv,
+    /// Timestamp of the first block, wall clock time if not set
+    timestamp: Option<U256>,
+    /// Logs of the transaction being executed
+    #[serde(skip)]
+    logs: Vec<Log>,
+    /// Changes since the last mined block
+    journal: Journal,
+    /// Changes of each block, undone to go back to the state of earlier blocks
+    block_changes: Vec<BlockChanges>,
+    #[serde(skip)]
+    snapshots: Vec<Snapshot>,
+    #[serde(skip)]
+    last_snapshot_id: SnapshotId,
+    /// Addresses sending transactions without a private key
+    #[serde(default)]
+    impersonated: HashSet<Address>,
+    /// Inputs of the keccak hashes computed by contracts which end with a slot,
+    /// to find the keys of mapping entries, up to `MAX_PREIMAGES`
+    #[serde(default)]
+    preimages: HashMap<H256, Bytes>,
+    /// Names of addresses which need not have an account or key,
+    /// like contracts known from a real chain
+    #[serde(default)]
+    aliases: BTreeMap<String, Address>,
+    /// Addresses of the named accounts and aliases, rebuilt from them by `index_names`
+    #[serde(skip)]
+    names: HashMap<String, Address>,
 }
 
 impl State {
-    pub fn new() -> Self {
-        State {
-            accounts: HashMap::new(),
+    pub fn new(limits: ExecutionLimits) -> Self {
+        Self::from_genesis(limits, Genesis::default())
+    }
+
+    /// Accounts of the genesis without a secret key are impersonated to send from them
+    pub fn from_genesis(limits: ExecutionLimits, genesis: Genesis) -> Self {
+        let impersonated = genesis
+            .accounts
+            .iter()
+            .filter(|account| account.get_private_key().is_none())
+            .map(|account| *account.get_address())
+            .collect();
+        let mut state = State {
+            accounts: genesis
+                .accounts
+                .into_iter()
+                .map(|account| (*account.get_address(), account))
+                .collect(),
             blocks: Vec::new(),
             txs: Vec::new(),
+            limits,
+            coverage: None,
+            fork: genesis.fork,
+            block_env: genesis.block_env,
+            timestamp: genesis.timestamp,
+            logs: Vec::new(),
+            journal: Journal::new(),
+            block_changes: Vec::new(),
+            snapshots: Vec::new(),
+            last_snapshot_id: 0,
+            impersonated,
+            preimages: HashMap::new(),
+            aliases: BTreeMap::new(),
+            names: HashMap::new(),
+        };
+        state.index_names();
+
+        state
+    }
+
+    /// Rebuild the index of names after the accounts were replaced, like by loading
+    pub fn index_names(&mut self) {
+        self.names = self
+            .aliases
+            .iter()
+            .map(|(name, address)| (name.clone(), *address))
+            .collect();
+        for (address, account) in &self.accounts {
+            if !account.get_name().is_empty() {
+                self.names.insert(account.get_name().to_string(), *address);
+            }
+        }
+    }
+
+    pub fn limits(&self) -> &ExecutionLimits {
+        &self.limits
+    }
+
+    /// Execute with `limits`, which are not part of a saved state
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.limits = limits;
+    }
+
+    pub fn snapshot(&mut self) -> SnapshotId {
+        self.last_snapshot_id += 1;
+        self.snapshots.push(Snapshot {
+            id: self.last_snapshot_id,
+            checkpoint: self.journal.checkpoint(),
+            blocks: self.blocks.len(),
+            txs: self.txs.len(),
+        });
+
+        self.last_snapshot_id
+    }
+
+    /// Restore the state of snapshot `id`, which is dropped together with all later snapshots.
+    /// `false` if there is no such snapshot.
+    pub fn revert(&mut self, id: SnapshotId) -> bool {
+        let index = match self.snapshots.iter().position(|snapshot| snapshot.id == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let snapshot = self.snapshots.drain(index..).next().unwrap();
+
+        if self.blocks.len() > snapshot.blocks {
+            // latest changes first, as for `block_accounts`
+            for entry in self.journal.take().into_iter().rev() {
+                entry.undo(&mut self.accounts);
+            }
+            for changes in self.block_changes.drain(snapshot.blocks + 1..).rev() {
+                for entry in changes.entries.into_iter().rev() {
+                    entry.undo(&mut self.accounts);
+                }
+            }
+            // the first block mined since holds the journal of the time of the snapshot
+            let mut entries = self.block_changes.pop().unwrap().entries;
+            for entry in entries.split_off(snapshot.checkpoint).into_iter().rev() {
+                entry.undo(&mut self.accounts);
+            }
+            for entry in entries {
+                self.journal.push(entry);
+            }
+            self.blocks.truncate(snapshot.blocks);
+        }
+        for entry in self.journal.revert(snapshot.checkpoint) {
+            entry.undo(&mut self.accounts);
         }
+        self.txs.truncate(snapshot.txs);
+        self.index_names();
+
+        true
+    }
+
+    /// Send transactions from `address` without its key,
+    /// the account is created by its first transaction if missing
+    pub fn impersonate(&mut self, address: Address) {
+        self.impersonated.insert(address);
+    }
+
+    /// `false` if `address` was not impersonated
+    pub fn stop_impersonating(&mut self, address: &Address) -> bool {
+        self.impersonated.remove(address)
+    }
+
+    /// Start recording executed code, discarding what was recorded before
+    pub fn coverage_start(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
     }
 
     pub fn address_exist(&self, address: &Address) -> bool {
@@ -46,11 +274,16 @@ impl State {
     }
 
     pub fn account_add(&mut self, name: &str) -> Address {
-        self.account_add_inner(name, Code::ethfrom(""))
+        self.account_insert(Account::new(name.to_string())).unwrap()
     }
 
-    pub fn account_list(&self) -> Vec<(&str, &Address, usize)> {
-        let mut account_list: Vec<(&str, &Address, usize)> = Vec::new();
+    /// Add an account created from a known key, `None` if its address already exists
+    pub fn account_import(&mut self, account: Account) -> Option<Address> {
+        self.account_insert(account)
+    }
+
+    pub fn account_list(&self) -> Vec<(&str, &Address, U256)> {
+        let mut account_list: Vec<(&str, &Address, U256)> = Vec::new();
 
         for (k, v) in &self.accounts {
             account_list.push((v.get_name(), k, v.get_balance()));
@@ -59,33 +292,381 @@ impl State {
         account_list
     }
 
-    pub fn account_get_balance(&self, address: &Address) -> Option<usize> {
+    pub fn account_get_balance(&self, address: &Address) -> Option<U256> {
         self.accounts
             .get(address)
             .and_then(|account| Some(account.get_balance()))
     }
 
+    pub fn account_get_private_key(&self, address: &Address) -> Option<&Secret> {
+        self.accounts
+            .get(address)
+            .and_then(|account| account.get_private_key())
+    }
+
+    pub fn account_get_nonce(&self, address: &Address) -> Option<usize> {
+        self.accounts
+            .get(address)
+            .map(|account| account.get_nonce())
+    }
+
+    pub fn account_get_code(&self, address: &Address) -> Option<&Code> {
+        self.accounts.get(address).map(|account| account.get_code())
+    }
+
+    /// Non-zero storage slots of an account, ordered by slot
+    pub fn account_get_storage(&self, address: &Address) -> Option<Vec<(H256, H256)>> {
+        let account = self.accounts.get(address)?;
+        let mut storage: Vec<(H256, H256)> = account
+            .storage()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(key, value)| (*key, *value))
+            .collect();
+        storage.sort();
+
+        Some(storage)
+    }
+
+    pub fn keccak_preimages(&self) -> &HashMap<H256, Bytes> {
+        &self.preimages
+    }
+
+    /// Set the balance outside of transactions, creating the account if missing
+    pub fn account_set_balance(&mut self, address: &Address, balance: U256) {
+        self.account_ensure(address);
+        self.set_balance(address, balance);
+    }
+
+    /// Set the nonce outside of transactions, creating the account if missing
+    pub fn account_set_nonce(&mut self, address: &Address, nonce: usize) {
+        self.account_ensure(address);
+        let account = self.accounts.get_mut(address).unwrap();
+        self.journal.push(JournalEntry::NonceChanged {
+            address: *address,
+            previous: account.get_nonce(),
+        });
+        account.set_nonce(nonce);
+    }
+
+    /// Set the code outside of transactions, creating the account if missing
+    pub fn account_set_code(&mut self, address: &Address, code: Code) {
+        self.account_ensure(address);
+        self.set_code(address, code);
+    }
+
+    /// Set a storage slot outside of transactions, creating the account if missing
+    pub fn account_set_storage(&mut self, address: &Address, key: H256, value: H256) {
+        self.account_ensure(address);
+        self.set_storage(address, key, value);
+    }
+
+    fn account_ensure(&mut self, address: &Address) {
+        if !self.address_exist(address) {
+            self.create_account(address);
+        }
+    }
+
+    /// Address of a named account or of an alias, which may have no account
     pub fn account_query_address_by_name(&self, name: &str) -> Option<Address> {
-        for (address, account) in &self.accounts {
-            if account.get_name() == name {
-                return Some(address.clone());
+        self.names.get(name).copied()
+    }
+
+    /// Whether an account or alias has the name
+    pub fn name_exists(&self, name: &str) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /// Name of the account, or else an alias of the address
+    pub fn address_label(&self, address: &Address) -> Option<&str> {
+        self.accounts
+            .get(address)
+            .map(|account| account.get_name())
+            .filter(|name| !name.is_empty())
+            .or_else(|| {
+                self.aliases
+                    .iter()
+                    .find(|(_, aliased)| *aliased == address)
+                    .map(|(name, _)| name.as_str())
+            })
+    }
+
+    /// Name an existing account, names have to be checked to be unused by the caller
+    pub fn account_rename(&mut self, address: &Address, name: &str) {
+        let account = self.accounts.get_mut(address).unwrap();
+        self.names.remove(account.get_name());
+        self.journal.push(JournalEntry::NameChanged {
+            address: *address,
+            previous: account.get_name().to_string(),
+        });
+        account.set_name(name.to_string());
+        self.names.insert(name.to_string(), *address);
+    }
+
+    /// Name an address whether or not it has an account,
+    /// names have to be checked to be unused by the caller
+    pub fn alias_add(&mut self, name: &str, address: Address) {
+        self.aliases.insert(name.to_string(), address);
+        self.names.insert(name.to_string(), address);
+    }
+
+    /// `false` if `name` is not an alias
+    pub fn alias_remove(&mut self, name: &str) -> bool {
+        if self.aliases.remove(name).is_none() {
+            return false;
+        }
+        self.names.remove(name);
+
+        true
+    }
+
+    pub fn aliases(&self) -> &BTreeMap<String, Address> {
+        &self.aliases
+    }
+
+    /// Root of the trie of all accounts, keyed by the keccak hash of the address
+    pub fn state_root(&self) -> H256 {
+        let items: Vec<(Bytes, Bytes)> = self
+            .accounts
+            .iter()
+            .map(|(address, account)| (hash::keccak(address).as_bytes().to_vec(), account.rlp()))
+            .collect();
+
+        trie::root(&items)
+    }
+
+    /// State root recorded after block `number`
+    pub fn block_state_root(&self, number: usize) -> Option<H256> {
+        self.blocks.get(number).map(|block| block.get_state_root())
+    }
+
+    /// Proof of `address` and its storage `keys` against the state root after
+    /// block `number`, or against the current state root without a block
+    pub fn account_proof(
+        &self,
+        address: &Address,
+        keys: &[H256],
+        number: Option<usize>,
+    ) -> Option<AccountProof> {
+        match number {
+            Some(number) => {
+                let accounts = self.block_accounts(number)?;
+                Some(AccountProof::new(accounts.values(), address, keys))
+            }
+            None => Some(AccountProof::new(self.accounts.values(), address, keys)),
+        }
+    }
+
+    /// All accounts after block `number`, or the current ones without a block
+    pub fn dump(&self, number: Option<usize>) -> Option<Dump> {
+        match number {
+            Some(number) => {
+                let accounts = self.block_accounts(number)?;
+                Some(Dump::new(
+                    accounts.values(),
+                    self.blocks[number].get_state_root(),
+                ))
+            }
+            None => Some(Dump::new(self.accounts.values(), self.state_root())),
+        }
+    }
+
+    /// Set balance, nonce, code and the written slots of `account` outside of
+    /// transactions, creating it if missing. Names and keys of existing accounts are kept,
+    /// new accounts without a key are impersonated.
+    pub fn account_merge(&mut self, account: Account) {
+        let address = *account.get_address();
+        if !self.address_exist(&address) {
+            if account.get_private_key().is_none() {
+                self.impersonated.insert(address);
+            }
+            self.account_create(account);
+            return;
+        }
+
+        self.account_set_balance(&address, account.get_balance());
+        self.account_set_nonce(&address, account.get_nonce());
+        if self.account_get_code(&address) != Some(account.get_code()) {
+            self.set_code(&address, account.get_code().clone());
+        }
+        for (key, value) in account.storage() {
+            self.set_storage(&address, *key, *value);
+        }
+    }
+
+    /// Accounts as they were after block `number`
+    fn block_accounts(&self, number: usize) -> Option<HashMap<Address, Account>> {
+        if number >= self.blocks.len() {
+            return None;
+        }
+
+        let mut accounts = self.accounts.clone();
+        // latest changes first
+        let later = std::iter::once(self.journal.entries()).chain(
+            self.block_changes[number + 1..]
+                .iter()
+                .rev()
+                .map(|changes| changes.entries.as_slice()),
+        );
+        for changes in later {
+            for entry in changes.iter().rev() {
+                entry.clone().undo(&mut accounts);
             }
         }
 
-        None
+        Some(accounts)
+    }
+
+    /// Base fee of the next block: the one of the genesis for the first block,
+    /// then adjusted by EIP-1559 to the gas used by the previous block. Zero before London.
+    pub fn base_fee(&self) -> U256 {
+        if !self.fork.has_base_fee() {
+            return U256::zero();
+        }
+
+        match self.blocks.last() {
+            Some(block) => next_base_fee(
+                block.get_base_fee(),
+                block.get_gas_used(),
+                self.block_env.gas_limit,
+            ),
+            None => self.block_env.base_fee,
+        }
+    }
+
+    /// Mined block `number`
+    pub fn block(&self, number: usize) -> Option<&Block> {
+        self.blocks.get(number)
+    }
+
+    /// Number of mined blocks, which is the number of the next block
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn block_gas_limit(&self) -> U256 {
+        self.block_env.gas_limit
+    }
+
+    /// Mined transactions with their block number and whether they succeeded
+    pub fn tx_list(&self) -> Vec<(usize, &Tx, bool)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(number, block)| (number, block.get_tx(), block.is_success()))
+            .collect()
+    }
+
+    /// Block number and block of the mined transaction with hash `tx_hash`
+    pub fn tx_block(&self, tx_hash: &H256) -> Option<(usize, &Block)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .find(|(_, block)| block.get_tx().hash() == *tx_hash)
+    }
+
+    /// Logs of the mined transaction with hash `tx_hash`
+    pub fn tx_logs(&self, tx_hash: &H256) -> Option<&[Log]> {
+        self.blocks
+            .iter()
+            .find(|block| block.get_tx().hash() == *tx_hash)
+            .map(|block| block.get_logs())
+    }
+
+    /// Accounts changed by the mined transaction with hash `tx_hash`
+    pub fn tx_state_diff(&self, tx_hash: &H256) -> Option<StateDiff> {
+        let number = self
+            .blocks
+            .iter()
+            .position(|block| block.get_tx().hash() == *tx_hash)?;
+        let changes = &self.block_changes[number];
+        let changes = &changes.entries[changes.tx_start..];
+
+        let post = self.block_accounts(number)?;
+        let mut pre = post.clone();
+        let mut touched: BTreeMap<Address, BTreeSet<H256>> = BTreeMap::new();
+        for entry in changes.iter().rev() {
+            if let Some((address, key)) = entry.touched() {
+                touched.entry(address).or_default().extend(key);
+            }
+            entry.clone().undo(&mut pre);
+        }
+
+        Some(StateDiff::new(&pre, &post, &touched))
     }
 
     pub fn tx_send(&mut self, tx: Tx) -> Result<Bytes, StateError> {
+        self.tx_send_inner(tx, false)
+    }
+
+    /// Send a transaction whose execution is not allowed to modify state
+    pub fn tx_send_static(&mut self, tx: Tx) -> Result<Bytes, StateError> {
+        self.tx_send_inner(tx, true)
+    }
+
+    /// Invalid transactions are rejected without a trace. A transaction failing
+    /// during execution is mined as failed, only its nonce increment and fee are kept.
+    fn tx_send_inner(&mut self, tx: Tx, is_static: bool) -> Result<Bytes, StateError> {
+        if self.impersonated.contains(tx.from()) {
+            self.account_ensure(tx.from());
+        }
         self.check_tx(&tx)
             .or_else(|tx_error| Err(StateError::TxError(tx_error)))?;
 
-        self.txs.push(tx);
-        let last_tx = self.txs.last().unwrap().clone();
+        let base_fee = self.base_fee();
+        let tx_start = self.journal.checkpoint();
+        self.increment_nonce(tx.from());
+        // the whole gas limit is paid upfront, what is not used is refunded after execution
+        let from_balance = self.account_get_balance(tx.from()).unwrap();
+        self.set_balance(
+            tx.from(),
+            from_balance - tx.effective_gas_price(base_fee) * tx.gas(),
+        );
+
+        let intrinsic = self.intrinsic_gas(&tx);
+        let mut substate = Substate::new(self.coverage.take());
+        let checkpoint = self.journal.checkpoint();
+        let (result, gas_used) =
+            self.handle_tx(&tx, &mut substate, is_static, tx.gas() - intrinsic);
+        let gas_used = intrinsic + gas_used;
+        self.coverage = substate.take_coverage();
+
+        match result {
+            Ok(output) => {
+                let logs = std::mem::take(&mut self.logs);
+                let refund = substate
+                    .refund()
+                    .min(gas_used / self.fork.max_refund_quotient());
+                self.pay_gas(&tx, gas_used - refund, base_fee);
+                self.mine(tx, tx_start, gas_used - refund, base_fee, true, logs);
+                Ok(output)
+            }
+            Err(err) => {
+                self.revert_to_checkpoint(checkpoint);
+                self.pay_gas(&tx, gas_used, base_fee);
+                self.mine(tx, tx_start, gas_used, base_fee, false, Vec::new());
+                Err(StateError::VMError(err))
+            }
+        }
+    }
+
+    fn intrinsic_gas(&self, tx: &Tx) -> usize {
+        intrinsic_gas(tx.data(), tx.tx_type() == TxType::DeployContract, self.fork)
+    }
+
+    /// Refund the gas left to the sender and pay the priority fee of the gas used
+    /// to the coinbase. The base fee of the gas used is burnt.
+    fn pay_gas(&mut self, tx: &Tx, gas_used: usize, base_fee: U256) {
+        let gas_price = tx.effective_gas_price(base_fee);
+        let from_balance = self.account_get_balance(tx.from()).unwrap();
+        self.set_balance(tx.from(), from_balance + gas_price * (tx.gas() - gas_used));
 
-        self.handle_tx(&last_tx).and_then(|result| {
-            self.mine(last_tx);
-            Ok(result)
-        })
+        let fee = (gas_price - base_fee) * gas_used;
+        if !fee.is_zero() {
+            let coinbase = self.block_env.coinbase;
+            self.account_ensure(&coinbase);
+            let coinbase_balance = self.account_get_balance(&coinbase).unwrap();
+            self.set_balance(&coinbase, coinbase_balance + fee);
+        }
     }
 
     fn check_tx(&self, tx: &Tx) -> Result<(), TxError> {
@@ -95,82 +676,776 @@ impl State {
         if tx.tx_type() != TxType::DeployContract && !self.address_exist(tx.to()) {
             return Err(TxError::WrongToAddress(tx.to().clone()));
         }
+        if tx.tx_type() == TxType::CallContract && !self.address_is_contract(tx.to()) {
+            return Err(TxError::CallEoAAccount);
+        }
+
+        if self.account_get_private_key(tx.from()).is_none()
+            && !self.impersonated.contains(tx.from())
+        {
+            return Err(TxError::NoPrivateKey);
+        }
+
+        let expected = self.account_get_nonce(tx.from()).unwrap();
+        let actual = tx.nonce();
+        if actual < expected {
+            return Err(TxError::NonceTooLow { expected, actual });
+        }
+        if actual > expected {
+            return Err(TxError::NonceTooHigh { expected, actual });
+        }
+
+        let intrinsic = self.intrinsic_gas(tx);
+        if tx.gas() < intrinsic {
+            return Err(TxError::IntrinsicGasTooLow {
+                intrinsic,
+                limit: tx.gas(),
+            });
+        }
+        if U256::from(tx.gas()) > self.block_env.gas_limit {
+            return Err(TxError::GasLimitExceeded);
+        }
+
+        if tx.is_dynamic_fee() && !self.fork.has_base_fee() {
+            return Err(TxError::TxTypeNotSupported);
+        }
+        if tx
+            .max_priority_fee_per_gas()
+            .is_some_and(|tip| tip > tx.max_fee_per_gas())
+        {
+            return Err(TxError::TipAboveFeeCap);
+        }
+        let base_fee = self.base_fee();
+        if tx.max_fee_per_gas() < base_fee {
+            return Err(TxError::FeeCapTooLow {
+                fee_cap: tx.max_fee_per_gas(),
+                base_fee,
+            });
+        }
+
+        // value and the whole gas limit at the max fee have to be covered
+        let cost = tx
+            .max_fee_per_gas()
+            .checked_mul(U256::from(tx.gas()))
+            .and_then(|fee| fee.checked_add(tx.value()));
+        match cost {
+            Some(cost) if cost <= self.account_get_balance(tx.from()).unwrap() => {}
+            _ => return Err(TxError::NotEnoughBalance),
+        }
 
         Ok(())
     }
 
-    fn account_add_inner(&mut self, name: &str, code: Code) -> Address {
-        let account = Account::new(name.to_string(), code);
-        let address = account.get_address().clone();
-        self.accounts.insert(address.clone(), account);
+    fn account_insert(&mut self, account: Account) -> Option<Address> {
+        let address = *account.get_address();
+        if self.address_exist(&address) {
+            return None;
+        }
+        self.account_create(account);
 
-        address
+        Some(address)
     }
 
-    /// Validity of Tx should be checked at caller side
-    fn handle_tx(&mut self, tx: &Tx) -> Result<Bytes, StateError> {
+    /// Insert an account, journaled to be undone with a failing transaction
+    /// and to recover the state of earlier blocks
+    fn account_create(&mut self, account: Account) {
+        let address = *account.get_address();
+        if !account.get_name().is_empty() {
+            self.names.insert(account.get_name().to_string(), address);
+        }
+        self.journal.push(JournalEntry::AccountCreated(address));
+        self.accounts.insert(address, account);
+    }
+
+    /// Validity of Tx should be checked at caller side. Executes with `gas`,
+    /// the gas limit less the intrinsic gas.
+    /// Returns the output and the gas used by execution before refunds.
+    fn handle_tx(
+        &mut self,
+        tx: &Tx,
+        substate: &mut Substate,
+        is_static: bool,
+        gas: usize,
+    ) -> (Result<Bytes, VMError>, usize) {
         match tx.tx_type() {
             TxType::EoaToEoa => self.handle_tx_eoa_to_eoa(tx),
-            TxType::DeployContract => self.handle_tx_deploy_contract(tx),
-            TxType::CallContract => self.handle_tx_call_contract(tx),
+            TxType::DeployContract => self.handle_tx_deploy_contract(tx, substate, gas),
+            TxType::CallContract => self.handle_tx_call_contract(tx, substate, is_static, gas),
         }
     }
 
-    fn handle_tx_eoa_to_eoa(&mut self, tx: &Tx) -> Result<Bytes, StateError> {
-        let from = self.accounts.get_mut(tx.from()).unwrap();
+    fn handle_tx_eoa_to_eoa(&mut self, tx: &Tx) -> (Result<Bytes, VMError>, usize) {
+        let from_balance = self.account_get_balance(tx.from()).unwrap();
+        self.set_balance(tx.from(), from_balance - tx.value());
+        let to_balance = self.account_get_balance(tx.to()).unwrap();
+        self.set_balance(tx.to(), to_balance + tx.value());
 
-        match from.sub_balance(tx.value()) {
-            Ok(_) => {
-                let to = self.accounts.get_mut(tx.to()).unwrap();
-                to.add_balance(tx.value());
-                Ok(Bytes::new())
-            }
-            Err(_) => Err(StateError::TxError(TxError::NotEnoughBalance)),
-        }
+        (Ok(Bytes::new()), 0)
     }
 
-    fn handle_tx_deploy_contract(&mut self, tx: &Tx) -> Result<Bytes, StateError> {
-        let address = self.account_add_inner(tx.contract_name().unwrap(), tx.data().clone());
-        let mut vm = VM::new(self.accounts.get(&address).unwrap().get_code().clone());
-        let mut ext = Ext::new(address, &mut self.accounts, tx);
+    fn handle_tx_deploy_contract(
+        &mut self,
+        tx: &Tx,
+        substate: &mut Substate,
+        gas: usize,
+    ) -> (Result<Bytes, VMError>, usize) {
+        let address = hash::create_address(tx.from(), tx.nonce());
+        if !self.address_exist(&address) {
+            self.account_create(Account::with_address(
+                tx.contract_name().unwrap().to_string(),
+                address,
+                Code::new(),
+            ));
+        }
+        // EIP-161: contracts start with nonce 1
+        self.increment_nonce(&address);
+
+        let limits = self.limits.clone();
+        let mut vm = VM::new(tx.data().clone(), &limits);
+        let mut ext = Ext::new(address, self, tx, substate, &limits, false, gas);
 
-        match vm.execute(&mut ext) {
-            Ok(vm_result) => match vm_result {
-                VMResult::Ok | VMResult::Stop => Ok(Bytes::new()),
-                VMResult::Return(bytes) => {
-                    let account = self.accounts.get_mut(&address).unwrap();
-                    account.set_code(bytes);
-                    Ok(Bytes::new())
-                }
-            },
-            Err(err) => Err(StateError::VMError(err)),
+        let result = vm.execute_tx(&mut ext);
+        let gas_used = Self::gas_used(&ext, gas, &result);
+        match result {
+            Ok(VMResult::Return(bytes)) => {
+                self.set_code(&address, bytes);
+                (Ok(Bytes::new()), gas_used)
+            }
+            Ok(VMResult::Ok | VMResult::Stop) => (Ok(Bytes::new()), gas_used),
+            Err(err) => (Err(err), gas_used),
         }
     }
 
-    fn handle_tx_call_contract(&mut self, tx: &Tx) -> Result<Bytes, StateError> {
+    fn handle_tx_call_contract(
+        &mut self,
+        tx: &Tx,
+        substate: &mut Substate,
+        is_static: bool,
+        gas: usize,
+    ) -> (Result<Bytes, VMError>, usize) {
         let account = self.accounts.get(tx.to()).unwrap();
-        if !account.is_contract() {
-            return Err(StateError::TxError(TxError::CallEoAAccount));
-        }
+        let address = *account.get_address();
+        let limits = self.limits.clone();
+        let mut vm = VM::new(account.get_code().clone(), &limits);
+        let mut ext = Ext::new(address, self, tx, substate, &limits, is_static, gas);
 
-        let mut vm = VM::new(account.get_code().clone());
-        let mut ext = Ext::new(account.get_address().clone(), &mut self.accounts, tx);
+        let result = vm.execute_tx(&mut ext);
+        let gas_used = Self::gas_used(&ext, gas, &result);
+        match result {
+            Ok(VMResult::Return(bytes)) => (Ok(bytes), gas_used),
+            Ok(VMResult::Ok | VMResult::Stop) => (Ok(Bytes::new()), gas_used),
+            Err(err) => (Err(err), gas_used),
+        }
+    }
 
-        match vm.execute(&mut ext) {
-            Ok(vm_result) => match vm_result {
-                VMResult::Ok | VMResult::Stop => Ok(Bytes::new()),
-                VMResult::Return(bytes) => Ok(bytes),
-            },
-            Err(err) => Err(StateError::VMError(err)),
+    /// REVERT keeps the gas left, other errors consume all `gas` given to execution
+    fn gas_used(ext: &Ext<State>, gas: usize, result: &Result<VMResult, VMError>) -> usize {
+        match result {
+            Ok(_)
+            | Err(VMError {
+                error_kind: VMErrorKind::Revert(_),
+                ..
+            }) => ext.gas_used(),
+            Err(_) => gas,
         }
     }
 
-    fn mine(&mut self, last_tx: Tx) {
+    /// Mine `tx` in a block of its own, its changes start at journal position `tx_start`
+    fn mine(
+        &mut self,
+        tx: Tx,
+        tx_start: usize,
+        gas_used: usize,
+        base_fee: U256,
+        success: bool,
+        logs: Vec<Log>,
+    ) {
         let prev_block_hash = if self.blocks.len() == 0 {
             H256::zero()
         } else {
             self.blocks.last().unwrap().get_hash()
         };
-        self.blocks.push(Block::new(last_tx, prev_block_hash));
+        let state_root = self.state_root();
+        self.block_changes.push(BlockChanges {
+            entries: self.journal.take(),
+            tx_start,
+        });
+        self.txs.push(tx.clone());
+        self.blocks.push(Block::new(
+            tx,
+            gas_used,
+            base_fee,
+            success,
+            logs,
+            state_root,
+            prev_block_hash,
+        ));
+    }
+}
+
+impl Host for State {
+    fn exists(&self, address: &Address) -> bool {
+        self.address_exist(address)
+    }
+
+    fn balance(&self, address: &Address) -> Option<U256> {
+        self.account_get_balance(address)
+    }
+
+    fn set_balance(&mut self, address: &Address, balance: U256) {
+        let account = self.accounts.get_mut(address).unwrap();
+        self.journal.push(JournalEntry::BalanceChanged {
+            address: *address,
+            previous: account.get_balance(),
+        });
+        account.set_balance(balance);
+    }
+
+    fn nonce(&self, address: &Address) -> Option<usize> {
+        self.account_get_nonce(address)
+    }
+
+    fn increment_nonce(&mut self, address: &Address) {
+        let account = self.accounts.get_mut(address).unwrap();
+        self.journal.push(JournalEntry::NonceChanged {
+            address: *address,
+            previous: account.get_nonce(),
+        });
+        account.increment_nonce();
+    }
+
+    fn code(&self, address: &Address) -> Option<&Code> {
+        self.account_get_code(address)
+    }
+
+    fn set_code(&mut self, address: &Address, code: Code) {
+        let account = self.accounts.get_mut(address).unwrap();
+        self.journal.push(JournalEntry::CodeChanged {
+            address: *address,
+            previous: account.get_code().clone(),
+        });
+        account.set_code(code);
+    }
+
+    fn create_account(&mut self, address: &Address) {
+        self.account_create(Account::with_address(String::new(), *address, Code::new()));
+    }
+
+    fn storage(&self, address: &Address, key: &H256) -> H256 {
+        self.accounts
+            .get(address)
+            .map(|account| account.get_storage(key))
+            .unwrap_or_default()
+    }
+
+    fn set_storage(&mut self, address: &Address, key: H256, value: H256) {
+        let account = self.accounts.get_mut(address).unwrap();
+        self.journal.push(JournalEntry::StorageChanged {
+            address: *address,
+            key,
+            previous: account.get_storage(&key),
+        });
+        account.set_storage(key, value);
+    }
+
+    /// Each transaction is mined in a block of its own,
+    /// with a fixed genesis timestamp blocks are one second apart
+    fn block_env(&self) -> BlockEnv {
+        let timestamp = match self.timestamp {
+            Some(timestamp) => timestamp + self.blocks.len(),
+            None => U256::from(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs()),
+            ),
+        };
+
+        BlockEnv {
+            number: U256::from(self.blocks.len()),
+            timestamp,
+            base_fee: self.base_fee(),
+            ..self.block_env.clone()
+        }
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        let current = U256::from(self.blocks.len());
+        if number >= current || current - number > U256::from(256) {
+            return H256::zero();
+        }
+
+        self.blocks[number.as_usize()].get_hash()
+    }
+
+    fn log(&mut self, log: Log) {
+        self.journal.push(JournalEntry::LogAdded);
+        self.logs.push(log);
+    }
+
+    fn keccak_preimage(&mut self, hash: H256, preimage: &[u8]) {
+        // a value key and a slot, or a string key and a slot declared in the layout,
+        // which is a small number unlike the hashed slots of nested entries
+        let is_entry = match preimage.len() {
+            64 => true,
+            len if len >= 32 => preimage[len - 32..len - 8].iter().all(|byte| *byte == 0),
+            _ => false,
+        };
+        if is_entry && self.preimages.len() < MAX_PREIMAGES {
+            self.preimages.insert(hash, preimage.to_vec());
+        }
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.journal.checkpoint()
+    }
+
+    fn revert_to_checkpoint(&mut self, checkpoint: usize) {
+        for entry in self.journal.revert(checkpoint) {
+            match &entry {
+                JournalEntry::LogAdded => {
+                    self.logs.pop();
+                }
+                JournalEntry::AccountCreated(address) => {
+                    let name = self.accounts[address].get_name();
+                    if self.names.get(name) == Some(address) {
+                        self.names.remove(name);
+                    }
+                }
+                _ => {}
+            }
+            entry.undo(&mut self.accounts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{eth_types::EthFrom, proof::verify_account_proof},
+        *,
+    };
+
+    /// State whose transactions pay no base fee, for tests not about fees
+    fn without_base_fee(limits: ExecutionLimits) -> State {
+        let mut genesis = Genesis::default();
+        genesis.block_env.base_fee = U256::zero();
+        State::from_genesis(limits, genesis)
+    }
+
+    #[test]
+    fn test_snapshot_revert() {
+        let mut state = without_base_fee(ExecutionLimits::default());
+        let alice = state.account_add("alice");
+        let bob = state.account_add("bob");
+        let balance = state.account_get_balance(&alice).unwrap();
+
+        let tx = Tx::new(
+            alice,
+            bob,
+            0,
+            U256::from(100),
+            Bytes::new(),
+            TxType::EoaToEoa,
+            String::new(),
+        );
+        state.account_set_balance(&bob, U256::from(7));
+        let first = state.snapshot();
+        state.account_rename(&bob, "carol");
+        state.tx_send(tx).unwrap();
+        let second = state.snapshot();
+        assert_eq!(state.blocks.len(), 1);
+
+        assert!(state.revert(first));
+        assert_eq!(state.account_get_balance(&alice), Some(balance));
+        assert_eq!(state.account_get_balance(&bob), Some(U256::from(7)));
+        assert_eq!(state.account_get_nonce(&alice), Some(0));
+        assert_eq!(state.account_query_address_by_name("bob"), Some(bob));
+        assert!(state.blocks.is_empty() && state.txs.is_empty());
+        // later snapshots are dropped with the one reverted to
+        assert!(!state.revert(second));
+        assert!(!state.revert(first));
+        // ids are not reused
+        assert!(state.snapshot() > second);
+        assert!(!state.revert(first));
+    }
+
+    #[test]
+    fn test_genesis_accounts_without_key() {
+        let genesis = Genesis::from_json(
+            r#"{ "baseFeePerGas": "0", "alloc": { "0x00000000000000000000000000000000000000aa": { "balance": "1000000000000000000" } } }"#,
+        )
+        .unwrap();
+        let mut state = State::from_genesis(ExecutionLimits::default(), genesis);
+        let alice = state.account_add("alice");
+        let balance = state.account_get_balance(&alice).unwrap();
+        let tx = Tx::new(
+            Address::from_low_u64_be(0xaa),
+            alice,
+            0,
+            U256::from(100),
+            Bytes::new(),
+            TxType::EoaToEoa,
+            String::new(),
+        );
+
+        assert!(state.tx_send(tx).is_ok());
+        assert_eq!(
+            state.account_get_balance(&alice),
+            Some(balance + U256::from(100))
+        );
+    }
+
+    #[test]
+    fn test_keccak_preimages() {
+        let mut state = without_base_fee(ExecutionLimits::default());
+        let mut string_key = b"key".to_vec();
+        string_key.extend(H256::from_low_u64_be(3).as_bytes());
+        let inputs: [&[u8]; 4] = [&[0xff; 64], &string_key, &[0xff; 40], &[0xff; 31]];
+        for (index, input) in inputs.iter().enumerate() {
+            state.keccak_preimage(H256::from_low_u64_be(index as u64), input);
+        }
+
+        let mut recorded: Vec<u64> = state
+            .keccak_preimages()
+            .keys()
+            .map(|hash| hash.to_low_u64_be())
+            .collect();
+        recorded.sort();
+        assert_eq!(recorded, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_execution_limits() {
+        let run = |code: &str, limits: ExecutionLimits| {
+            let mut state = without_base_fee(limits);
+            let alice = state.account_add("alice");
+            let contract = Address::from_low_u64_be(0xaa);
+            state.account_set_code(&contract, Code::ethfrom(code));
+            let tx = Tx::new(
+                alice,
+                contract,
+                0,
+                U256::zero(),
+                Bytes::new(),
+                TxType::CallContract,
+                String::new(),
+            );
+            let result = state.tx_send(tx).map_err(|err| match err {
+                StateError::VMError(err) => err.error_kind,
+                StateError::TxError(err) => panic!("{:?}", err),
+            });
+            (result.map(|_| ()), state.account_get_storage(&contract))
+        };
+        // JUMPDEST PUSH1 0 JUMP
+        let endless = "5b600056";
+
+        // ADD with an empty stack
+        assert!(matches!(
+            run("01", ExecutionLimits::default()).0,
+            Err(VMErrorKind::StackUnderflow)
+        ));
+
+        let limits = ExecutionLimits {
+            max_steps: 1000,
+            ..ExecutionLimits::default()
+        };
+        assert!(matches!(
+            run(endless, limits).0,
+            Err(VMErrorKind::StepLimitExceeded)
+        ));
+
+        let limits = ExecutionLimits {
+            timeout: std::time::Duration::ZERO,
+            ..ExecutionLimits::default()
+        };
+        assert!(matches!(run(endless, limits).0, Err(VMErrorKind::Timeout)));
+
+        // reading memory far away expands it as much as writing
+        let limits = ExecutionLimits {
+            max_memory: 1024,
+            ..ExecutionLimits::default()
+        };
+        assert!(matches!(
+            run("61040051", limits).0,
+            Err(VMErrorKind::MemoryLimitExceeded)
+        ));
+        // the limit covers the memory of all frames: both frames read at 1000,
+        // the success of the self call is stored in slot 0
+        // PUSH2 1000 MLOAD POP, CALLDATASIZE PUSH1 0x1a JUMPI,
+        // CALL itself with 1 byte of input, PUSH1 0 SSTORE STOP,
+        // JUMPDEST PUSH2 1000 MLOAD STOP
+        let nested = "6103e85150 36601a57 60006000600160006000305af1 60005500 5b6103e85100";
+        let nested = nested.replace(' ', "");
+        let limits = |max_memory| ExecutionLimits {
+            max_memory,
+            ..ExecutionLimits::default()
+        };
+        let (result, storage) = run(&nested, limits(2048));
+        assert!(result.is_ok() && storage.unwrap().is_empty());
+        let (result, storage) = run(&nested, limits(4096));
+        assert!(result.is_ok());
+        assert_eq!(
+            storage.unwrap(),
+            vec![(H256::zero(), H256::from_low_u64_be(1))]
+        );
+
+        // each frame counts itself in slot 0 and calls itself, until the depth limit fails the call
+        // PUSH1 0 SLOAD PUSH1 1 ADD PUSH1 0 SSTORE, CALL itself
+        let limits = ExecutionLimits {
+            max_call_depth: 4,
+            ..ExecutionLimits::default()
+        };
+        let (result, storage) = run("60005460010160005560006000600060006000305af1", limits);
+        assert!(result.is_ok());
+        assert_eq!(
+            storage.unwrap(),
+            vec![(H256::zero(), H256::from_low_u64_be(5))]
+        );
+        // depth limits beyond the EVM's are clamped instead of sizing the execution thread by them
+        let limits = ExecutionLimits {
+            max_call_depth: usize::MAX,
+            ..ExecutionLimits::default()
+        };
+        assert!(run("00", limits).0.is_ok());
+    }
+
+    #[test]
+    fn test_reverted_frame_warm_slots() {
+        // the gas of reading slot 1 after a self call which reads it too is stored in slot 0
+        // CALLDATASIZE PUSH1 0x1e JUMPI, CALL itself with 1 byte of input POP,
+        // GAS PUSH1 1 SLOAD POP GAS SWAP1 SUB PUSH1 0 SSTORE STOP,
+        // JUMPDEST PUSH1 1 SLOAD POP followed by `child_end`
+        let sload_gas = |child_end: &str| {
+            let mut state = without_base_fee(ExecutionLimits::default());
+            let alice = state.account_add("alice");
+            let contract = Address::from_low_u64_be(0xaa);
+            let code = format!(
+                "36601e57 60006000600160006000305af150 5a600154505a900360005500 5b60015450{}",
+                child_end
+            );
+            state.account_set_code(&contract, Code::ethfrom(&code.replace(' ', "")));
+            let tx = Tx::new(
+                alice,
+                contract,
+                0,
+                U256::zero(),
+                Bytes::new(),
+                TxType::CallContract,
+                String::new(),
+            );
+            assert!(state.tx_send(tx).is_ok());
+            state.storage(&contract, &H256::zero()).to_low_u64_be()
+        };
+        // PUSH1, POP and GAS cost 7 besides the SLOAD.
+        // a child ending with STOP leaves the slot warm
+        assert_eq!(sload_gas("00"), 100 + 7);
+        // PUSH1 0 PUSH1 0 REVERT, the slot is cold again
+        assert_eq!(sload_gas("60006000fd"), 2100 + 7);
+    }
+
+    #[test]
+    fn test_failed_tx_is_reverted() {
+        let mut state = without_base_fee(ExecutionLimits::default());
+        let alice = state.account_add("alice");
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 0 PUSH1 0 REVERT
+        let reverting = Code::ethfrom("600160005560006000fd");
+        // init code returning the reverting code as runtime code
+        let init = Code::ethfrom("69600160005560006000fd600052600a6016f3");
+        let deploy = |nonce, data| {
+            Tx::new(
+                alice,
+                Address::zero(),
+                nonce,
+                U256::zero(),
+                data,
+                TxType::DeployContract,
+                "C".to_string(),
+            )
+        };
+
+        assert!(matches!(
+            state.tx_send(deploy(0, reverting.clone())),
+            Err(StateError::VMError(_))
+        ));
+        assert!(!state.address_exist(&hash::create_address(&alice, 0)));
+        assert_eq!(state.account_get_nonce(&alice), Some(1));
+        assert_eq!(state.txs.len(), 1);
+
+        state.tx_send(deploy(1, init)).unwrap();
+        let contract = hash::create_address(&alice, 1);
+        assert_eq!(state.account_get_code(&contract), Some(&reverting));
+
+        let call = Tx::new(
+            alice,
+            contract,
+            2,
+            U256::zero(),
+            Bytes::new(),
+            TxType::CallContract,
+            String::new(),
+        );
+        assert!(state.tx_send(call).is_err());
+        assert_eq!(state.storage(&contract, &H256::zero()), H256::zero());
+        assert_eq!(state.blocks.len(), 3);
+    }
+
+    #[test]
+    fn test_block_account_proof() {
+        let mut state = without_base_fee(ExecutionLimits::default());
+        let alice = state.account_add("alice");
+        let bob = state.account_add("bob");
+        let transfer = |nonce| {
+            Tx::new(
+                alice,
+                bob,
+                nonce,
+                U256::from(100),
+                Bytes::new(),
+                TxType::EoaToEoa,
+                String::new(),
+            )
+        };
+
+        state.tx_send(transfer(0)).unwrap();
+        let carol = state.account_add("carol");
+        state.tx_send(transfer(1)).unwrap();
+        let balance = state.account_get_balance(&bob).unwrap();
+
+        let root = state.block_state_root(0).unwrap();
+        let proof = state.account_proof(&bob, &[], Some(0)).unwrap();
+        let account = verify_account_proof(&root, &bob, &proof.proof)
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.balance, balance - 100);
+        // accounts added after the block are proven absent
+        let proof = state.account_proof(&carol, &[], Some(0)).unwrap();
+        assert_eq!(verify_account_proof(&root, &carol, &proof.proof), Ok(None));
+
+        let proof = state.account_proof(&alice, &[], None).unwrap();
+        let account = verify_account_proof(&state.state_root(), &alice, &proof.proof)
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.nonce, U256::from(2));
+        assert!(state.account_proof(&alice, &[], Some(2)).is_none());
+    }
+
+    #[test]
+    fn test_tx_state_diff() {
+        let mut state = without_base_fee(ExecutionLimits::default());
+        let alice = state.account_add("alice");
+        // PUSH1 1 PUSH1 0 SSTORE STOP
+        let deploy = Tx::new(
+            alice,
+            Address::zero(),
+            0,
+            U256::zero(),
+            Code::ethfrom("600160005500"),
+            TxType::DeployContract,
+            "C".to_string(),
+        );
+        let tx_hash = deploy.hash();
+        state.tx_send(deploy).unwrap();
+        // accounts added later are not part of the diff
+        state.account_add("bob");
+
+        let diff = state.tx_state_diff(&tx_hash).unwrap();
+        let contract = hash::create_address(&alice, 0);
+        assert_eq!(diff.pre.len(), 1);
+        assert_eq!(diff.pre[&alice].nonce, Some(0));
+        assert_eq!(diff.post[&alice].nonce, Some(1));
+        // the fee of the deployment is paid by alice
+        assert!(diff.post[&alice].balance < diff.pre[&alice].balance);
+        assert!(!diff.pre.contains_key(&contract));
+        assert_eq!(diff.post[&contract].nonce, Some(1));
+        assert_eq!(
+            diff.post[&contract].storage[&H256::zero()],
+            H256::from_low_u64_be(1)
+        );
+        assert!(state.tx_state_diff(&H256::zero()).is_none());
+    }
+
+    #[test]
+    fn test_base_fee() {
+        let mut state = without_base_fee(ExecutionLimits::default());
+        let gwei = U256::exp10(9);
+        state.block_env.base_fee = gwei;
+        let alice = state.account_add("alice");
+        let bob = state.account_add("bob");
+        let coinbase = state.block_env.coinbase;
+        let balance = state.account_get_balance(&alice).unwrap();
+        let transfer = |nonce| {
+            Tx::new(
+                alice,
+                bob,
+                nonce,
+                U256::zero(),
+                Bytes::new(),
+                TxType::EoaToEoa,
+                String::new(),
+            )
+        };
+
+        assert!(matches!(
+            state.tx_send(transfer(0).with_gas(21000, gwei - 1)),
+            Err(StateError::TxError(TxError::FeeCapTooLow { .. }))
+        ));
+        assert!(matches!(
+            state.tx_send(transfer(0).with_dynamic_fee(gwei, gwei * 2)),
+            Err(StateError::TxError(TxError::TipAboveFeeCap))
+        ));
+
+        // the base fee is burnt, the priority fee goes to the coinbase
+        let tx = transfer(0)
+            .with_gas(21000, U256::zero())
+            .with_dynamic_fee(gwei * 3, gwei);
+        assert!(state.tx_send(tx).is_ok());
+        assert_eq!(
+            state.account_get_balance(&alice).unwrap(),
+            balance - gwei * 2 * 21000
+        );
+        assert_eq!(state.account_get_balance(&coinbase).unwrap(), gwei * 21000);
+
+        // far below the target of half the gas limit, the base fee falls by almost 1/8
+        let base_fee = state.base_fee();
+        assert_eq!(
+            base_fee,
+            next_base_fee(gwei, 21000, state.block_gas_limit())
+        );
+        assert!(base_fee < gwei && base_fee > gwei * 7 / 8);
+        assert_eq!(state.block_env().base_fee, base_fee);
+
+        state.fork = Fork::Berlin;
+        assert!(state.base_fee().is_zero());
+        assert!(matches!(
+            state.tx_send(transfer(1).with_dynamic_fee(gwei, gwei)),
+            Err(StateError::TxError(TxError::TxTypeNotSupported))
+        ));
+
+        // a huge genesis base fee falls without overflowing
+        let genesis = Genesis::from_json(&format!(
+            r#"{{ "baseFeePerGas": "0x1{}", "alloc": {{ "0x00000000000000000000000000000000000000aa": {{ "balance": "0x{}" }} }} }}"#,
+            "0".repeat(60),
+            "f".repeat(64)
+        ))
+        .unwrap();
+        let mut state = State::from_genesis(ExecutionLimits::default(), genesis);
+        let huge = U256::one() << 240;
+        assert_eq!(state.base_fee(), huge);
+        let bob = state.account_add("bob");
+        let tx = Tx::new(
+            Address::from_low_u64_be(0xaa),
+            bob,
+            0,
+            U256::zero(),
+            Bytes::new(),
+            TxType::EoaToEoa,
+            String::new(),
+        );
+        assert!(state.tx_send(tx.with_gas(21000, huge)).is_ok());
+        assert_eq!(
+            state.base_fee(),
+            next_base_fee(huge, 21000, state.block_gas_limit())
+        );
+        assert!(state.base_fee() < huge && state.base_fee() > huge * 7 / 8);
     }
 }
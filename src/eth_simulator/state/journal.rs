@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+use super::super::{
+    account::Account,
+    eth_types::{hex_bytes, Address, Code, H256, U256},
+};
+
+/// State change with what it replaced
+#[derive(Clone, Serialize, Deserialize)]
+pub enum JournalEntry {
+    AccountCreated(Address),
+    BalanceChanged {
+        address: Address,
+        previous: U256,
+    },
+    NonceChanged {
+        address: Address,
+        previous: usize,
+    },
+    CodeChanged {
+        address: Address,
+        #[serde(with = "hex_bytes")]
+        previous: Code,
+    },
+    StorageChanged {
+        address: Address,
+        key: H256,
+        previous: H256,
+    },
+    LogAdded,
+    NameChanged {
+        address: Address,
+        previous: String,
+    },
+}
+
+impl JournalEntry {
+    /// Account the change was made to, with the slot for storage changes
+    pub fn touched(&self) -> Option<(Address, Option<H256>)> {
+        match self {
+            JournalEntry::AccountCreated(address)
+            | JournalEntry::BalanceChanged { address, .. }
+            | JournalEntry::NonceChanged { address, .. }
+            | JournalEntry::CodeChanged { address, .. } => Some((*address, None)),
+            JournalEntry::StorageChanged { address, key, .. } => Some((*address, Some(*key))),
+            JournalEntry::LogAdded | JournalEntry::NameChanged { .. } => None,
+        }
+    }
+
+    /// Restore what the change replaced in `accounts`, logs and the index of names
+    /// are left to the caller
+    pub fn undo(self, accounts: &mut HashMap<Address, Account>) {
+        match self {
+            JournalEntry::AccountCreated(address) => {
+                accounts.remove(&address);
+            }
+            JournalEntry::BalanceChanged { address, previous } => {
+                accounts.get_mut(&address).unwrap().set_balance(previous)
+            }
+            JournalEntry::NonceChanged { address, previous } => {
+                accounts.get_mut(&address).unwrap().set_nonce(previous)
+            }
+            JournalEntry::CodeChanged { address, previous } => {
+                accounts.get_mut(&address).unwrap().set_code(previous)
+            }
+            JournalEntry::StorageChanged {
+                address,
+                key,
+                previous,
+            } => accounts
+                .get_mut(&address)
+                .unwrap()
+                .set_storage(key, previous),
+            JournalEntry::LogAdded => {}
+            JournalEntry::NameChanged { address, previous } => {
+                accounts.get_mut(&address).unwrap().set_name(previous)
+            }
+        }
+    }
+}
+
+/// Changes since the last mined block, so that those of failed call frames
+/// and failed transactions can be undone
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, entry: JournalEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Position to revert to, changes recorded after it can be undone
+    pub fn checkpoint(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Remove the changes recorded after `checkpoint`, latest first
+    pub fn revert(&mut self, checkpoint: usize) -> Vec<JournalEntry> {
+        self.entries.drain(checkpoint..).rev().collect()
+    }
+
+    /// Remove all changes to keep them with the block they are mined in
+    pub fn take(&mut self) -> Vec<JournalEntry> {
+        std::mem::take(&mut self.entries)
+    }
+}
@@ -0,0 +1,299 @@
+use rlp::{Rlp, RlpStream};
+
+use std::collections::HashMap;
+
+use super::{
+    eth_types::{Bytes, H256},
+    hash,
+};
+
+/// Root of the trie without entries, keccak of the RLP of the empty string
+pub const EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+#[derive(Debug, PartialEq)]
+pub enum ProofError {
+    /// A node referenced on the path is not part of the proof
+    MissingNode(H256),
+    InvalidNode,
+}
+
+/// Root of the Merkle Patricia Trie holding `items`.
+/// Keys have to be unique, the state tries use keccak hashes of addresses and slots.
+pub fn root(items: &[(Bytes, Bytes)]) -> H256 {
+    if items.is_empty() {
+        return EMPTY_ROOT;
+    }
+
+    let mut entries = entries(items);
+    entries.sort();
+    hash::keccak(encode_node(&entries, None, &mut Vec::new()))
+}
+
+/// Nodes on the path to `key` starting at the root, proving its value or its absence
+pub fn proof(items: &[(Bytes, Bytes)], key: &[u8]) -> Vec<Bytes> {
+    if items.is_empty() {
+        return Vec::new();
+    }
+
+    let mut entries = entries(items);
+    entries.sort();
+    let mut proof = Vec::new();
+    let root = encode_node(&entries, Some(&nibbles(key)), &mut proof);
+    // the root is part of the proof even if it is short
+    if root.len() < 32 {
+        proof.push(root);
+    }
+    proof.reverse();
+
+    proof
+}
+
+/// Value of `key` in the trie of `root` proven by `proof`, `None` if the proof shows it is absent
+pub fn verify_proof(root: &H256, key: &[u8], proof: &[Bytes]) -> Result<Option<Bytes>, ProofError> {
+    if *root == EMPTY_ROOT {
+        return Ok(None);
+    }
+
+    let nodes: HashMap<H256, &Bytes> = proof
+        .iter()
+        .map(|node| (hash::keccak(node), node))
+        .collect();
+    let key = nibbles(key);
+    let mut position = 0;
+    let mut node = nodes
+        .get(root)
+        .map(|node| node.to_vec())
+        .ok_or(ProofError::MissingNode(*root))?;
+
+    loop {
+        let rlp = Rlp::new(&node);
+        let child = match rlp.item_count().map_err(|_| ProofError::InvalidNode)? {
+            17 => {
+                if position == key.len() {
+                    let value: Bytes = rlp.val_at(16).map_err(|_| ProofError::InvalidNode)?;
+                    return Ok(Some(value).filter(|value| !value.is_empty()));
+                }
+                position += 1;
+                rlp.at(key[position - 1] as usize)
+                    .map_err(|_| ProofError::InvalidNode)?
+            }
+            2 => {
+                let path: Bytes = rlp.val_at(0).map_err(|_| ProofError::InvalidNode)?;
+                let (path, leaf) = decode_hex_prefix(&path).ok_or(ProofError::InvalidNode)?;
+                if !key[position..].starts_with(&path) {
+                    return Ok(None);
+                }
+                position += path.len();
+                if leaf {
+                    if position != key.len() {
+                        return Ok(None);
+                    }
+                    let value: Bytes = rlp.val_at(1).map_err(|_| ProofError::InvalidNode)?;
+                    return Ok(Some(value));
+                }
+                rlp.at(1).map_err(|_| ProofError::InvalidNode)?
+            }
+            _ => return Err(ProofError::InvalidNode),
+        };
+
+        // short children are embedded, the others referenced by hash
+        node = if child.is_list() {
+            child.as_raw().to_vec()
+        } else {
+            let reference = child.data().map_err(|_| ProofError::InvalidNode)?;
+            if reference.is_empty() {
+                return Ok(None);
+            }
+            if reference.len() != 32 {
+                return Err(ProofError::InvalidNode);
+            }
+            let hash = H256::from_slice(reference);
+            nodes
+                .get(&hash)
+                .map(|node| node.to_vec())
+                .ok_or(ProofError::MissingNode(hash))?
+        };
+    }
+}
+
+/// Key as nibbles with its value
+type Entry<'a> = (Vec<u8>, &'a [u8]);
+
+fn entries(items: &[(Bytes, Bytes)]) -> Vec<Entry<'_>> {
+    items
+        .iter()
+        .map(|(key, value)| (nibbles(key), value.as_slice()))
+        .collect()
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+/// RLP of the node holding `entries`, which are sorted by key and not empty.
+/// The keys are what is left of the full keys below the node.
+/// If the rest of a proven key is given as `path`, the hashed nodes on it
+/// are added to `proof`, deepest first.
+fn encode_node(entries: &[Entry], path: Option<&[u8]>, proof: &mut Vec<Bytes>) -> Bytes {
+    let node = if let [(key, value)] = entries {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(key, true));
+        stream.append(value);
+        stream.out().to_vec()
+    } else {
+        let prefix = common_prefix(entries);
+        if prefix > 0 {
+            let shared = &entries[0].0[..prefix];
+            let child: Vec<Entry> = entries
+                .iter()
+                .map(|(key, value)| (key[prefix..].to_vec(), *value))
+                .collect();
+            let child_path = path.and_then(|path| path.strip_prefix(shared));
+            let mut stream = RlpStream::new_list(2);
+            stream.append(&hex_prefix(shared, false));
+            append_reference(&mut stream, &encode_node(&child, child_path, proof));
+            stream.out().to_vec()
+        } else {
+            encode_branch(entries, path, proof)
+        }
+    };
+
+    if path.is_some() && node.len() >= 32 {
+        proof.push(node.clone());
+    }
+    node
+}
+
+fn encode_branch(entries: &[Entry], path: Option<&[u8]>, proof: &mut Vec<Bytes>) -> Bytes {
+    let mut stream = RlpStream::new_list(17);
+    for nibble in 0..16 {
+        let child: Vec<Entry> = entries
+            .iter()
+            .filter(|(key, _)| key.first() == Some(&nibble))
+            .map(|(key, value)| (key[1..].to_vec(), *value))
+            .collect();
+        let child_path = path.and_then(|path| match path.split_first() {
+            Some((first, rest)) if *first == nibble => Some(rest),
+            _ => None,
+        });
+        if child.is_empty() {
+            stream.append_empty_data();
+        } else {
+            append_reference(&mut stream, &encode_node(&child, child_path, proof));
+        }
+    }
+    // a key ending at a branch, sorting puts it first
+    match entries.first() {
+        Some((key, value)) if key.is_empty() => stream.append(value),
+        _ => stream.append_empty_data(),
+    };
+
+    stream.out().to_vec()
+}
+
+/// Nodes shorter than 32 bytes are embedded in their parent, longer ones are referenced by hash
+fn append_reference(stream: &mut RlpStream, node: &[u8]) {
+    if node.len() < 32 {
+        stream.append_raw(node, 1);
+    } else {
+        stream.append(&hash::keccak(node));
+    }
+}
+
+/// Number of leading nibbles all keys have in common
+fn common_prefix(entries: &[Entry]) -> usize {
+    let first = &entries[0].0;
+    entries[1..].iter().fold(first.len(), |prefix, (key, _)| {
+        first[..prefix]
+            .iter()
+            .zip(key)
+            .take_while(|(a, b)| a == b)
+            .count()
+    })
+}
+
+/// Compact encoding of a key part with a flag for leaves and odd lengths
+fn hex_prefix(nibbles: &[u8], leaf: bool) -> Bytes {
+    let flag = if leaf { 2 } else { 0 };
+    let (mut bytes, rest) = if !nibbles.len().is_multiple_of(2) {
+        (vec![((flag + 1) << 4) | nibbles[0]], &nibbles[1..])
+    } else {
+        (vec![flag << 4], nibbles)
+    };
+    bytes.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+
+    bytes
+}
+
+/// Nibbles and leaf flag of a compact encoded key part
+fn decode_hex_prefix(bytes: &[u8]) -> Option<(Vec<u8>, bool)> {
+    let (first, rest) = bytes.split_first()?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return None;
+    }
+
+    let mut path = if flag & 1 == 1 {
+        vec![first & 0x0f]
+    } else {
+        Vec::new()
+    };
+    path.extend(nibbles(rest));
+
+    Some((path, flag & 2 == 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_root() {
+        assert_eq!(root(&[]), hash::keccak(rlp::NULL_RLP));
+
+        let items: Vec<(Bytes, Bytes)> = [
+            ("doe", "reindeer"),
+            ("dog", "puppy"),
+            ("dogglesworth", "cat"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()))
+        .collect();
+        assert_eq!(
+            root(&items),
+            H256::from_str("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_proof() {
+        let items: Vec<(Bytes, Bytes)> = (0u8..50)
+            .map(|i| {
+                let key = hash::keccak([i]).as_bytes().to_vec();
+                (key, vec![i; (i % 40) as usize + 1])
+            })
+            .collect();
+        let root = root(&items);
+
+        for (key, value) in &items {
+            let proof = proof(&items, key);
+            assert_eq!(verify_proof(&root, key, &proof), Ok(Some(value.clone())));
+        }
+
+        let absent = hash::keccak([0xff]);
+        let proof = proof(&items, absent.as_bytes());
+        assert_eq!(verify_proof(&root, absent.as_bytes(), &proof), Ok(None));
+        assert!(matches!(
+            verify_proof(&root, &items[0].0, &proof[..1]),
+            Err(ProofError::MissingNode(_))
+        ));
+    }
+}
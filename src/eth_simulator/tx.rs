@@ -1,22 +1,42 @@
+use serde::{Deserialize, Serialize};
+
 use super::{
-    eth_types::{Address, Bytes, H256},
+    eth_types::{hex_bytes, Address, Bytes, H256, U256},
     hash,
 };
 
-#[derive(Clone, Copy, PartialEq)]
+/// Gas limit of transactions which do not set one
+pub const DEFAULT_GAS: usize = 10_000_000;
+/// Gas price in wei of transactions which do not set one
+pub const DEFAULT_GAS_PRICE: u64 = 10;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum TxType {
     EoaToEoa,
     CallContract,
     DeployContract,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Tx {
     from: Address,
     to: Address,
-    value: usize,
+    nonce: usize,
+    value: U256,
+    #[serde(with = "hex_bytes")]
     data: Bytes,
-    gasprice: usize,
+    /// Wei paid per unit of gas
+    #[serde(default)]
+    gas_price: U256,
+    /// Most gas the transaction may use, paid for upfront
+    gas: usize,
+    /// EIP-1559: most wei paid per unit of gas, base fee included.
+    /// Set for dynamic fee transactions, which leave `gas_price` unused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_fee_per_gas: Option<U256>,
+    /// EIP-1559: most wei per unit of gas paid to the coinbase on top of the base fee
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_priority_fee_per_gas: Option<U256>,
     tx_type: TxType,
     contract_name: String,
 }
@@ -25,7 +45,8 @@ impl Tx {
     pub fn new(
         from: Address,
         to: Address,
-        value: usize,
+        nonce: usize,
+        value: U256,
         data: Bytes,
         tx_type: TxType,
         contract_name: String,
@@ -33,14 +54,36 @@ impl Tx {
         Tx {
             from,
             to,
+            nonce,
             value,
             data,
-            gasprice: 10,
+            gas_price: U256::from(DEFAULT_GAS_PRICE),
+            gas: DEFAULT_GAS,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
             tx_type,
             contract_name,
         }
     }
 
+    /// Set the gas limit and the price per gas in wei
+    pub fn with_gas(mut self, gas: usize, gas_price: U256) -> Self {
+        self.gas = gas;
+        self.gas_price = gas_price;
+        self
+    }
+
+    /// Make it a dynamic fee transaction of EIP-1559
+    pub fn with_dynamic_fee(
+        mut self,
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    ) -> Self {
+        self.max_fee_per_gas = Some(max_fee_per_gas);
+        self.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+        self
+    }
+
     pub fn from(&self) -> &Address {
         &self.from
     }
@@ -49,7 +92,11 @@ impl Tx {
         &self.to
     }
 
-    pub fn value(&self) -> usize {
+    pub fn nonce(&self) -> usize {
+        self.nonce
+    }
+
+    pub fn value(&self) -> U256 {
         self.value
     }
 
@@ -57,15 +104,49 @@ impl Tx {
         &self.data
     }
 
-    pub fn gasprice(&self) -> usize {
-        self.gasprice
+    pub fn gas(&self) -> usize {
+        self.gas
+    }
+
+    pub fn is_dynamic_fee(&self) -> bool {
+        self.max_fee_per_gas.is_some()
+    }
+
+    /// Transaction type of EIP-2718: 2 for dynamic fee transactions, 0 for legacy ones
+    pub fn envelope_type(&self) -> u8 {
+        if self.is_dynamic_fee() {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// Most wei paid per unit of gas
+    pub fn max_fee_per_gas(&self) -> U256 {
+        self.max_fee_per_gas.unwrap_or(self.gas_price)
+    }
+
+    /// Most wei per unit of gas paid to the coinbase, all above the base fee for legacy ones
+    pub fn max_priority_fee_per_gas(&self) -> Option<U256> {
+        self.max_priority_fee_per_gas
+    }
+
+    /// Wei paid per unit of gas in a block with `base_fee`, which must not be above the max fee
+    pub fn effective_gas_price(&self, base_fee: U256) -> U256 {
+        match (self.max_fee_per_gas, self.max_priority_fee_per_gas) {
+            (Some(max_fee), Some(max_priority_fee)) => {
+                max_fee.min(base_fee.saturating_add(max_priority_fee))
+            }
+            _ => self.gas_price,
+        }
     }
 
     pub fn hash(&self) -> H256 {
         hash::keccak(format!(
-            "{}{}{}",
+            "{}{}{}{}",
             &self.from,
             &self.to,
+            self.nonce,
             self.value.to_string(),
         ))
     }
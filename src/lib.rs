@@ -3,4 +3,8 @@ mod eth_api;
 mod eth_simulator;
 mod utils;
 
-pub use client::{Client, Rpc, REPL};
+pub use client::{CfgExport, Client, Rpc, REPL};
+pub use eth_simulator::{
+    verify_account_proof, verify_storage_proof, AccountProof, EthSimulator, ExecutionLimits,
+    GenesisError, ProofError, ProvenAccount, StateFileError, StorageProof,
+};
@@ -1,12 +1,15 @@
 use clap::{Parser, Subcommand};
-use ethereum_simulator::{Client, Rpc, REPL};
+use ethereum_simulator::{CfgExport, Client, EthSimulator, ExecutionLimits, Rpc, REPL};
+
+use std::{path::PathBuf, process, time::Duration};
 
 fn main() {
     let cli = Cli::parse();
 
     let mut client: Box<dyn Client> = match &cli.command {
-        Commands::REPL => Box::new(REPL::new()),
-        Commands::Rpc { socket } => Box::new(Rpc::new(&socket)),
+        Commands::REPL => Box::new(REPL::new(cli.simulator(), cli.state.clone())),
+        Commands::Rpc { socket } => Box::new(Rpc::new(&socket, cli.simulator(), cli.state.clone())),
+        Commands::Cfg { file } => Box::new(CfgExport::new(file)),
     };
     client.run();
 }
@@ -17,6 +20,79 @@ fn main() {
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Maximum number of instructions executed by a transaction
+    #[clap(long, global = true, value_parser)]
+    max_steps: Option<usize>,
+
+    /// Maximum memory of all active call frames of a transaction in bytes
+    #[clap(long, global = true, value_parser)]
+    max_memory: Option<usize>,
+
+    /// Maximum depth of nested calls, at most 1024
+    #[clap(long, global = true, value_parser)]
+    max_call_depth: Option<usize>,
+
+    /// Maximum execution time of a transaction in milliseconds
+    #[clap(long, global = true, value_parser)]
+    timeout: Option<u64>,
+
+    /// Genesis file in geth format with the initial accounts and block environment
+    #[clap(long, global = true, value_parser)]
+    genesis: Option<PathBuf>,
+
+    /// File the state is loaded from at start if it exists, and saved to on exit or when the rpc server is stopped
+    #[clap(long, global = true, value_parser)]
+    state: Option<PathBuf>,
+
+    /// State dump in geth format whose accounts are added at start, ignored if the state file exists
+    #[clap(long, global = true, value_parser)]
+    import: Option<PathBuf>,
+}
+
+impl Cli {
+    fn simulator(&self) -> EthSimulator {
+        if let Some(state) = self.state.as_ref().filter(|state| state.exists()) {
+            return EthSimulator::from_state_file(self.limits(), state).unwrap_or_else(|err| {
+                eprintln!("invalid state file {}: {}", state.display(), err);
+                process::exit(1);
+            });
+        }
+
+        let mut eth_simulator = match &self.genesis {
+            Some(genesis) => {
+                EthSimulator::from_genesis(self.limits(), genesis).unwrap_or_else(|err| {
+                    eprintln!("invalid genesis file {}: {}", genesis.display(), err);
+                    process::exit(1);
+                })
+            }
+            None => EthSimulator::new(self.limits()),
+        };
+        if let Some(import) = &self.import {
+            if let Err(err) = eth_simulator.import_dump(import) {
+                eprintln!("invalid state dump {}: {}", import.display(), err);
+                process::exit(1);
+            }
+        }
+
+        eth_simulator
+    }
+
+    fn limits(&self) -> ExecutionLimits {
+        let default = ExecutionLimits::default();
+
+        ExecutionLimits {
+            max_steps: self.max_steps.unwrap_or(default.max_steps),
+            max_memory: self.max_memory.unwrap_or(default.max_memory),
+            max_call_depth: self.max_call_depth.map_or(default.max_call_depth, |depth| {
+                depth.min(default.max_call_depth)
+            }),
+            timeout: self
+                .timeout
+                .map(Duration::from_millis)
+                .unwrap_or(default.timeout),
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -26,4 +102,10 @@ enum Commands {
         #[clap(value_parser)]
         socket: String,
     },
+    /// Print the control-flow graph of a contract in DOT format
+    Cfg {
+        /// Solidity source file, or file with bytecode in hex
+        #[clap(value_parser)]
+        file: String,
+    },
 }
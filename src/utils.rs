@@ -1,20 +1,411 @@
 pub mod solc {
-    use std::{error::Error, path::Path, process::Command};
+    use keccak_hash::keccak;
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use std::{error::Error, fmt, fs, path::Path, process::Command};
 
     const COMPILER: &'static str = "solc";
 
-    pub fn compile(file: &Path) -> Result<String, Box<dyn Error>> {
+    /// Compiler output of a contract
+    #[derive(Serialize, Deserialize)]
+    pub struct Contract {
+        pub bin: String,
+        pub bin_runtime: String,
+        pub abi: Value,
+        /// Source map of the runtime code, one entry per instruction
+        pub srcmap_runtime: String,
+        /// Path and content of the files in the order of the compiler's source list
+        pub sources: Vec<(String, String)>,
+        /// Storage layout with the slot and type of every state variable,
+        /// null if the compiler does not output it
+        #[serde(default)]
+        pub storage_layout: Value,
+    }
+
+    /// Position of a piece of Solidity code
+    pub struct SourceLocation {
+        pub file: String,
+        pub line: usize,
+        pub column: usize,
+        /// Source line containing the position
+        pub snippet: String,
+    }
+
+    impl fmt::Display for SourceLocation {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "{}:{}:{}\n    {}",
+                self.file, self.line, self.column, self.snippet
+            )
+        }
+    }
+
+    impl Contract {
+        /// Whether `input` calls a `view` or `pure` function of the contract
+        pub fn is_view_call(&self, input: &[u8]) -> bool {
+            if input.len() < 4 {
+                return false;
+            }
+
+            self.abi.as_array().is_some_and(|items| {
+                items
+                    .iter()
+                    .filter(|item| item["type"] == "function")
+                    .filter(|item| {
+                        item["constant"] == true
+                            || item["stateMutability"] == "view"
+                            || item["stateMutability"] == "pure"
+                    })
+                    .any(|item| selector(item) == input[..4])
+            })
+        }
+
+        /// Solidity location of the instruction at `pc` of the deployed `code`,
+        /// `None` for pcs inside push data and compiler-generated code
+        pub fn source_location(&self, code: &[u8], pc: usize) -> Option<SourceLocation> {
+            let index = instruction_offsets(code)
+                .iter()
+                .position(|&offset| offset == pc)?;
+            let (start, _, file) = (*source_map_entries(&self.srcmap_runtime).get(index)?)?;
+
+            self.location(start, file)
+        }
+
+        /// Solidity location of every instruction of the deployed `code`, by pc
+        pub fn source_locations(&self, code: &[u8]) -> Vec<(usize, Option<SourceLocation>)> {
+            let entries = source_map_entries(&self.srcmap_runtime);
+
+            instruction_offsets(code)
+                .into_iter()
+                .enumerate()
+                .map(|(index, pc)| {
+                    let location = entries
+                        .get(index)
+                        .copied()
+                        .flatten()
+                        .and_then(|(start, _, file)| self.location(start, file));
+                    (pc, location)
+                })
+                .collect()
+        }
+
+        fn location(&self, start: usize, file: usize) -> Option<SourceLocation> {
+            let (path, content) = self.sources.get(file)?;
+            let before = content.as_bytes().get(..start)?;
+            let line_start = before
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |i| i + 1);
+            let line_end = content[start..]
+                .find('\n')
+                .map_or(content.len(), |i| start + i);
+
+            Some(SourceLocation {
+                file: path.clone(),
+                line: before.iter().filter(|&&b| b == b'\n').count() + 1,
+                column: start - line_start + 1,
+                snippet: content[line_start..line_end].trim().to_string(),
+            })
+        }
+    }
+
+    /// Offset of every instruction of `code`
+    fn instruction_offsets(code: &[u8]) -> Vec<usize> {
+        const OP_PUSH1: u8 = 0x60;
+        const OP_PUSH32: u8 = 0x7f;
+
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        while offset < code.len() {
+            offsets.push(offset);
+            offset += match code[offset] {
+                op @ OP_PUSH1..=OP_PUSH32 => (op - OP_PUSH1) as usize + 2,
+                _ => 1,
+            };
+        }
+
+        offsets
+    }
+
+    /// Start, length and file index of each entry of a compressed source map,
+    /// `None` for compiler-generated code.
+    /// Entries are `s:l:f:j:m` separated by `;`, empty fields repeat the previous entry.
+    fn source_map_entries(srcmap: &str) -> Vec<Option<(usize, usize, usize)>> {
+        let mut entry: [i64; 3] = [0, 0, -1];
+
+        srcmap
+            .split(';')
+            .map(|item| {
+                for (field, value) in item.split(':').take(3).enumerate() {
+                    if let Ok(value) = value.parse() {
+                        entry[field] = value;
+                    }
+                }
+                if entry.iter().any(|&value| value < 0) {
+                    None
+                } else {
+                    Some((entry[0] as usize, entry[1] as usize, entry[2] as usize))
+                }
+            })
+            .collect()
+    }
+
+    /// Compile `file` and return the contract named after the file,
+    /// or the first contract of the file if there is no such contract
+    pub fn compile(file: &Path) -> Result<Contract, Box<dyn Error>> {
         if !file.exists() {
             Err("file does not exist")?;
         }
         let command = Command::new(COMPILER)
-            .arg("--bin")
+            .arg("--combined-json")
+            .arg("bin,bin-runtime,abi,srcmap-runtime,storage-layout")
             .arg(file.to_str().unwrap())
             .output()?;
-        let output = String::from_utf8(command.stdout).unwrap();
-        let lines: Vec<&str> = output.lines().collect();
+        let output: Value = serde_json::from_slice(&command.stdout)?;
+
+        let contracts = output["contracts"]
+            .as_object()
+            .ok_or("no contracts in compiler output")?;
+        let name = super::path::get_file_name(file);
+        let contract = contracts
+            .iter()
+            .find(|(key, _)| key.rsplit(':').next() == Some(name))
+            .or_else(|| contracts.iter().next())
+            .map(|(_, contract)| contract)
+            .ok_or("no contracts in compiler output")?;
+
+        // solc before 0.8 outputs the abi and storage layout as json strings
+        let abi = match &contract["abi"] {
+            Value::String(abi) => serde_json::from_str(abi)?,
+            abi => abi.clone(),
+        };
+        let storage_layout = match &contract["storage-layout"] {
+            Value::String(layout) => serde_json::from_str(layout)?,
+            layout => layout.clone(),
+        };
+
+        // sources that cannot be read only lose their locations
+        let sources = match output["sourceList"].as_array() {
+            Some(list) => list
+                .iter()
+                .filter_map(|path| path.as_str())
+                .map(|path| {
+                    (
+                        path.to_string(),
+                        fs::read_to_string(path).unwrap_or_default(),
+                    )
+                })
+                .collect(),
+            None => vec![(
+                file.to_str().unwrap().to_string(),
+                fs::read_to_string(file)?,
+            )],
+        };
+
+        Ok(Contract {
+            bin: contract["bin"]
+                .as_str()
+                .ok_or("no bin in compiler output")?
+                .to_string(),
+            bin_runtime: contract["bin-runtime"]
+                .as_str()
+                .ok_or("no bin-runtime in compiler output")?
+                .to_string(),
+            abi,
+            srcmap_runtime: contract["srcmap-runtime"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            sources,
+            storage_layout,
+        })
+    }
+
+    fn selector(function: &Value) -> [u8; 4] {
+        let signature = format!(
+            "{}({})",
+            function["name"].as_str().unwrap_or_default(),
+            param_types(&function["inputs"])
+        );
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&keccak(signature.as_bytes())[..4]);
+
+        selector
+    }
+
+    /// Canonical types of abi params, with tuples expanded to their components
+    fn param_types(params: &Value) -> String {
+        params
+            .as_array()
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|param| {
+                        let ty = param["type"].as_str().unwrap_or_default();
+                        match ty.strip_prefix("tuple") {
+                            Some(suffix) => {
+                                format!("({}){}", param_types(&param["components"]), suffix)
+                            }
+                            None => ty.to_string(),
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .unwrap_or_default()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_source_location() {
+            // PUSH1 0x80 PUSH1 0x40 MSTORE CALLVALUE
+            let code = [0x60, 0x80, 0x60, 0x40, 0x52, 0x34];
+            let contract = Contract {
+                bin: String::new(),
+                bin_runtime: String::new(),
+                abi: Value::Null,
+                srcmap_runtime: "0:30:0:-:0;;;17:6;:::i".to_string(),
+                sources: vec![(
+                    "A.sol".to_string(),
+                    "contract A {\n    uint x;\n}\n".to_string(),
+                )],
+                storage_layout: Value::Null,
+            };
+
+            assert_eq!(instruction_offsets(&code), vec![0, 2, 4, 5]);
+            assert_eq!(
+                source_map_entries(&contract.srcmap_runtime)[1],
+                Some((0, 30, 0))
+            );
+            assert!(contract.source_location(&code, 3).is_none());
+
+            let location = contract.source_location(&code, 5).unwrap();
+            assert_eq!((location.line, location.column), (2, 5));
+            assert_eq!(location.to_string(), "A.sol:2:5\n    uint x;");
+        }
+    }
+}
+
+pub mod units {
+    use ethereum_types::U256;
+
+    use std::{fmt, str::FromStr};
+
+    /// Denomination of ether
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Unit {
+        Wei,
+        Gwei,
+        Ether,
+    }
+
+    impl Unit {
+        fn decimals(self) -> usize {
+            match self {
+                Unit::Wei => 0,
+                Unit::Gwei => 9,
+                Unit::Ether => 18,
+            }
+        }
+    }
+
+    impl FromStr for Unit {
+        type Err = ();
+
+        fn from_str(unit: &str) -> Result<Self, Self::Err> {
+            match unit.trim().to_lowercase().as_str() {
+                "wei" => Ok(Unit::Wei),
+                "gwei" => Ok(Unit::Gwei),
+                "ether" | "eth" => Ok(Unit::Ether),
+                _ => Err(()),
+            }
+        }
+    }
 
-        Ok(String::from(lines[3]))
+    impl fmt::Display for Unit {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Unit::Wei => write!(f, "wei"),
+                Unit::Gwei => write!(f, "gwei"),
+                Unit::Ether => write!(f, "ether"),
+            }
+        }
+    }
+
+    /// Amount of wei given as hex quantity like `0x10`, or as decimal number
+    /// with an optional unit like `1.5ether` or `20 gwei`
+    pub fn parse_amount(amount: &str) -> Option<U256> {
+        let amount = amount.trim().to_lowercase();
+        if let Some(hex) = amount.strip_prefix("0x") {
+            return U256::from_str_radix(hex, 16).ok();
+        }
+
+        let split = amount
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(amount.len());
+        let (number, unit) = amount.split_at(split);
+        let decimals = if unit.is_empty() {
+            0
+        } else {
+            unit.parse::<Unit>().ok()?.decimals()
+        };
+
+        let number = number.trim();
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if fraction.len() > decimals || (integer.is_empty() && fraction.is_empty()) {
+            return None;
+        }
+        let digits = format!(
+            "{}{}{}",
+            integer,
+            fraction,
+            "0".repeat(decimals - fraction.len())
+        );
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        U256::from_dec_str(&digits).ok()
+    }
+
+    /// `amount` of wei in `unit`, without trailing zeros of the fraction
+    pub fn format_amount(amount: U256, unit: Unit) -> String {
+        let scale = U256::exp10(unit.decimals());
+        let integer = amount / scale;
+        let fraction = amount % scale;
+
+        if fraction.is_zero() {
+            format!("{} {}", integer, unit)
+        } else {
+            let fraction = format!("{:0>width$}", fraction, width = unit.decimals());
+            format!("{}.{} {}", integer, fraction.trim_end_matches('0'), unit)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_amount() {
+            let ether = U256::exp10(18);
+
+            assert_eq!(parse_amount("1.5ether"), Some(ether * 3 / 2));
+            assert_eq!(parse_amount("20 gwei"), Some(U256::from(20_000_000_000u64)));
+            assert_eq!(parse_amount("100"), Some(U256::from(100)));
+            assert_eq!(parse_amount("0x10"), Some(U256::from(16)));
+            assert_eq!(parse_amount("1.5wei"), None);
+            assert_eq!(parse_amount("1ton"), None);
+
+            assert_eq!(format_amount(ether * 3 / 2, Unit::Ether), "1.5 ether");
+            assert_eq!(format_amount(ether, Unit::Gwei), "1000000000 gwei");
+            assert_eq!(format_amount(U256::from(1), Unit::Gwei), "0.000000001 gwei");
+        }
     }
 }
 